@@ -0,0 +1,22 @@
+use {
+    criterion::{criterion_group, criterion_main, Criterion},
+    vee::{bench, config::Config},
+};
+
+fn editing(c: &mut Criterion) {
+    let bytes = bench::sample(100_000);
+    c.bench_function("open 100k lines", |b| {
+        b.iter(|| bench::open(&bytes, Config::default()))
+    });
+    let mut s = bench::open(&bytes, Config::default());
+    c.bench_function("search 100k lines", |b| {
+        b.iter(|| bench::search(&mut s, bench::PATTERN))
+    });
+    c.bench_function("reannotate 100k lines", |b| {
+        b.iter(|| bench::reannotate(&mut s))
+    });
+    c.bench_function("draw frame", |b| b.iter(|| bench::draw(&mut s).unwrap()));
+}
+
+criterion_group!(benches, editing);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+#![no_main]
+
+// Opens arbitrary file contents and types arbitrary keys at them, checking that the cursor and
+// selection stay valid after every key. Run with `cargo fuzz run keys` from the repository root.
+//
+// The first byte of the input says how many of the following bytes are the file, and each byte
+// after that is a key.
+
+use {
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    libfuzzer_sys::fuzz_target,
+    vee::{
+        config::Config,
+        keymap::Keymap,
+        state::{Cursor, State},
+    },
+};
+
+// Digits are left out, since a long count in front of a macro could make a run take forever.
+const SPECIAL: &[KeyCode] = &[
+    KeyCode::Esc,
+    KeyCode::Enter,
+    KeyCode::Backspace,
+    KeyCode::Delete,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Tab,
+    KeyCode::Char('中'),
+    KeyCode::Char('é'),
+    KeyCode::Char('\u{301}'),
+];
+
+fn key(b: u8) -> Option<KeyCode> {
+    match b {
+        b'0'..=b'9' => None,
+        b' '..=b'~' => Some(KeyCode::Char(b as char)),
+        _ => SPECIAL.get(b as usize % 32).copied(),
+    }
+}
+
+fn check(s: &State, cursor: Cursor) {
+    assert!(cursor.y < s.text.len(), "cursor past the last line");
+    let line = &s.text[cursor.y].0;
+    assert!(cursor.x <= line.len(), "cursor past the end of the line");
+    assert!(line.is_char_boundary(cursor.x), "cursor inside a char");
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (&n, data) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let (file, keys) = data.split_at(usize::min(n as usize, data.len()));
+    let path = std::env::temp_dir().join("vee-fuzz.rs");
+    let mut s = State::from_bytes(
+        path.to_string_lossy().into_owned(),
+        file,
+        Config::default(),
+        Keymap::default(),
+    );
+    s.resize((80, 24));
+    for code in keys.iter().filter_map(|&b| key(b)) {
+        if !s.handle(KeyEvent::new(code, KeyModifiers::NONE)) {
+            break;
+        }
+        assert!(!s.text.is_empty(), "no lines left");
+        check(&s, s.cursor);
+        if let Some(anchor) = s.anchor {
+            check(&s, anchor);
+        }
+        if let Some((start, end)) = s.selection() {
+            assert!(start <= end, "selection out of order");
+        }
+        while !s.idle.is_empty() {
+            s.run_idle();
+        }
+    }
+});
@@ -0,0 +1,364 @@
+use {
+    crate::{
+        buffer::Buffer,
+        config,
+        mark::MarkId,
+        state::{Point, State},
+    },
+    anyhow::{anyhow, bail, Result},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        process,
+        sync::mpsc,
+        time::Duration,
+    },
+};
+
+// How often to check for requests while waiting for a key.
+pub const POLL: Duration = Duration::from_millis(50);
+
+// Something an external tool has to say about a range of lines, like coverage or a review
+// comment: a sign for the gutter next to each of them, and text drawn after the first. The
+// range is held by marks, so it stays with the lines as they're edited.
+pub struct Annotation {
+    start: MarkId,
+    end: MarkId,
+    sign: Option<char>,
+    text: String,
+}
+
+// A line sent by a tool, and where to send the reply.
+pub struct Request {
+    line: String,
+    reply: mpsc::Sender<String>,
+}
+
+// Listens for tools on a socket at sockets/<pid> in the cache directory, see config::cache_path,
+// one request per line:
+//
+//     add NAMESPACE FILE LINE[-LINE] SIGN [TEXT]
+//     clear NAMESPACE
+//
+// Lines count from 1, and a SIGN of - means no sign. Each request is answered with "ok" or
+// "error: " and what went wrong. Requests are handed to the main loop to carry out.
+pub struct Server {
+    path: PathBuf,
+    requests: mpsc::Receiver<Request>,
+}
+
+impl Server {
+    pub fn start() -> Result<Self> {
+        let path = config::cache_path(&format!("sockets/{}", process::id()))
+            .ok_or(anyhow!("No home directory"))?;
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        // left behind by an earlier process with the same pid that didn't exit cleanly
+        let _ = fs::remove_file(&path);
+        let (sender, requests) = mpsc::channel();
+        listener().listen(&path, sender)?;
+        Ok(Server { path, requests })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn try_recv(&self) -> Option<Request> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// What tools reach us through: a unix socket, or nothing off unix or in builds without the
+// annotate feature, where there's no socket and VEE_SOCKET is left unset.
+pub trait Listener: Sync {
+    // hands each request to requests from then on
+    fn listen(&self, path: &Path, requests: mpsc::Sender<Request>) -> Result<()>;
+    fn available(&self) -> bool {
+        true
+    }
+}
+
+pub fn listener() -> &'static dyn Listener {
+    #[cfg(all(unix, feature = "annotate"))]
+    {
+        &socket::Socket
+    }
+    #[cfg(not(all(unix, feature = "annotate")))]
+    {
+        &NoListener
+    }
+}
+
+#[cfg(not(all(unix, feature = "annotate")))]
+struct NoListener;
+
+#[cfg(not(all(unix, feature = "annotate")))]
+impl Listener for NoListener {
+    fn listen(&self, _: &Path, _: mpsc::Sender<Request>) -> Result<()> {
+        bail!("Annotations need unix sockets, and the annotate feature")
+    }
+
+    fn available(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(all(unix, feature = "annotate"))]
+mod socket {
+    use {
+        super::{Listener, Request},
+        anyhow::Result,
+        std::{
+            io::{self, BufRead, BufReader},
+            os::unix::net::UnixListener,
+            path::Path,
+            sync::mpsc,
+            thread,
+        },
+    };
+
+    pub struct Socket;
+
+    impl Listener for Socket {
+        fn listen(&self, path: &Path, requests: mpsc::Sender<Request>) -> Result<()> {
+            let listener = UnixListener::bind(path)?;
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let requests = requests.clone();
+                    if let Ok(reader) = stream.try_clone() {
+                        thread::spawn(move || serve(reader, stream, requests));
+                    }
+                }
+            });
+            Ok(())
+        }
+    }
+
+    // passes on each line from a tool, and writes back the reply, until either side hangs up
+    fn serve(reader: impl io::Read, mut writer: impl io::Write, requests: mpsc::Sender<Request>) {
+        for line in BufReader::new(reader).lines().map_while(io::Result::ok) {
+            let (reply, replies) = mpsc::channel();
+            if requests.send(Request { line, reply }).is_err() {
+                return;
+            }
+            match replies.recv() {
+                Ok(reply) if writeln!(writer, "{}", reply).is_ok() => (),
+                _ => return,
+            }
+        }
+    }
+}
+
+// only we can reach a socket in it, whatever the umask, like the recovery files
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        builder.mode(0o700).create(dir)?;
+        // made by an earlier version, perhaps
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    builder.create(dir)
+}
+
+// Whether path names the same file as file, which may be written differently.
+pub fn same_file(path: &str, file: &str) -> bool {
+    match (fs::canonicalize(path), fs::canonicalize(file)) {
+        (Ok(path), Ok(file)) => path == file,
+        _ => path == file,
+    }
+}
+
+// LINE or LINE-LINE, counting from 1, as the lines from 0
+fn parse_lines(s: &str) -> Result<(usize, usize)> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end): (usize, usize) = (start.parse()?, end.parse()?);
+    if start == 0 || end < start {
+        bail!("Invalid lines: {}", s);
+    }
+    Ok((start - 1, end - 1))
+}
+
+impl Buffer {
+    fn clear_annotations(&mut self, namespace: &str) {
+        for annotation in self.annotations.remove(namespace).unwrap_or_default() {
+            self.marks.remove(annotation.start);
+            self.marks.remove(annotation.end);
+        }
+    }
+}
+
+impl State {
+    // carries out every request waiting, returning whether there were any
+    pub fn serve_annotations(&mut self, server: &Server) -> bool {
+        let mut any = false;
+        while let Some(request) = server.try_recv() {
+            let reply = match self.annotation_request(&request.line) {
+                Ok(()) => "ok".to_string(),
+                Err(err) => format!("error: {}", err),
+            };
+            let _ = request.reply.send(reply);
+            any = true;
+        }
+        any
+    }
+
+    pub fn annotation_request(&mut self, line: &str) -> Result<()> {
+        let mut words = line.splitn(6, ' ');
+        match (words.next(), words.next()) {
+            (Some("add"), Some(namespace)) => {
+                let (file, lines, sign) = match (words.next(), words.next(), words.next()) {
+                    (Some(file), Some(lines), Some(sign)) => (file, lines, sign),
+                    _ => bail!("Usage: add NAMESPACE FILE LINE[-LINE] SIGN [TEXT]"),
+                };
+                // in whichever buffer has it open, shown or not
+                let buffer = match self.buffers.iter_mut().find(|b| same_file(file, &b.file)) {
+                    Some(buffer) => buffer,
+                    None => bail!("{} isn't open", file),
+                };
+                let (start, end) = parse_lines(lines)?;
+                if end >= buffer.text.len() {
+                    bail!("Line {} is past the end of the file", end + 1);
+                }
+                let sign = match sign {
+                    "-" => None,
+                    _ => {
+                        let mut chars = sign.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => Some(c),
+                            _ => bail!("Invalid sign: {}", sign),
+                        }
+                    }
+                };
+                let annotation = Annotation {
+                    start: buffer.marks.add(Point { x: 0, y: start }),
+                    end: buffer.marks.add(Point { x: 0, y: end }),
+                    sign,
+                    // escape sequences would be written straight to the terminal
+                    text: words
+                        .next()
+                        .unwrap_or("")
+                        .chars()
+                        .filter(|c| !c.is_control())
+                        .collect(),
+                };
+                buffer
+                    .annotations
+                    .entry(namespace.to_string())
+                    .or_default()
+                    .push(annotation);
+            }
+            (Some("clear"), Some(namespace)) if words.next().is_none() => {
+                for buffer in &mut self.buffers {
+                    buffer.clear_annotations(namespace);
+                }
+            }
+            _ => bail!("Unknown request: {}", line),
+        }
+        Ok(())
+    }
+
+    pub fn clear_all_annotations(&mut self) {
+        let namespaces: Vec<String> = self.annotations.keys().cloned().collect();
+        for namespace in namespaces {
+            self.clear_annotations(&namespace);
+        }
+    }
+
+    fn annotations_on(&self, y: usize) -> impl Iterator<Item = (usize, &Annotation)> {
+        self.annotations
+            .values()
+            .flatten()
+            .filter_map(move |annotation| {
+                let start = self.marks.get(annotation.start)?.y;
+                let end = self.marks.get(annotation.end)?.y;
+                (start..=end).contains(&y).then_some((start, annotation))
+            })
+    }
+
+    // the signs tools have put on line y
+    pub fn annotation_signs(&self, y: usize) -> impl Iterator<Item = char> + '_ {
+        self.annotations_on(y)
+            .filter_map(|(_, annotation)| annotation.sign)
+    }
+
+    // the text tools have put after line y, if any
+    pub fn annotation_text(&self, y: usize) -> String {
+        let texts: Vec<&str> = self
+            .annotations_on(y)
+            .filter(|&(start, annotation)| start == y && !annotation.text.is_empty())
+            .map(|(_, annotation)| annotation.text.as_str())
+            .collect();
+        texts.join("  ")
+    }
+
+    // the text of every annotation on line y, wherever it starts, see hover.rs
+    pub fn annotation_messages(&self, y: usize) -> Vec<String> {
+        self.annotations_on(y)
+            .filter(|(_, annotation)| !annotation.text.is_empty())
+            .map(|(_, annotation)| annotation.text.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+        std::env,
+    };
+
+    #[test]
+    fn annotates_any_open_buffer() {
+        let dir = env::temp_dir().join(format!("vee-annotate-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::write(&a, "one\n").unwrap();
+        fs::write(&b, "two\nthree\n").unwrap();
+        let (a, b) = (a.to_string_lossy(), b.to_string_lossy());
+        let mut s =
+            State::open(a.to_string(), false, Config::default(), Keymap::default()).unwrap();
+        s.open_file(b.to_string());
+        s.annotation_request(&format!("add lint {} 1 E \x1b[2Jgone\x07 \u{9b}31mred", a))
+            .unwrap();
+        s.annotation_request(&format!("add lint {} 2 W here", b))
+            .unwrap();
+        assert_eq!(s.annotation_text(1), "here");
+        assert!(s.annotation_request("add lint nowhere 1 E text").is_err());
+        s.prev_buffer();
+        assert_eq!(s.annotation_text(0), "[2Jgone 31mred");
+        assert_eq!(s.annotation_signs(0).collect::<String>(), "E");
+
+        s.annotation_request("clear lint").unwrap();
+        assert_eq!(s.annotation_text(0), "");
+        s.next_buffer();
+        assert_eq!(s.annotation_text(1), "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_sockets_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = env::temp_dir().join(format!("vee-sockets-{}", process::id()));
+        let sockets = dir.join("cache").join("sockets");
+        create_private_dir(&sockets).unwrap();
+        fs::set_permissions(&sockets, fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&sockets).unwrap();
+        let mode = fs::metadata(&sockets).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use {
+    crate::state::{Mode, State},
+    std::{
+        fs::{File, OpenOptions},
+        io::{self, Write},
+    },
+};
+
+// What screen readers have been told so far, so that they're only told what's changed.
+#[derive(Default)]
+pub struct Announced {
+    mode: Option<Mode>,
+    line: Option<usize>,
+    message: Option<String>,
+}
+
+// Where announcements go: a file (or a fifo) that a screen reader reads from, if the config
+// names one, otherwise the message line.
+pub struct Channel {
+    file: Option<File>,
+}
+
+impl Channel {
+    pub fn open(announce: Option<&str>) -> io::Result<Self> {
+        let file = match announce {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(Channel { file })
+    }
+}
+
+impl State {
+    // With config.accessible, after each key: the mode if it changed, the line the cursor is
+    // on if it moved to another, and any message, one announcement each.
+    pub fn announcements(&mut self) -> Vec<String> {
+        let mut announcements = Vec::new();
+        if self.announced.mode != Some(self.mode) {
+            announcements.push(match &self.pager {
+                Some(pager) => format!("pager: {}", pager.title),
+                None => format!("{} mode", self.mode.to_string().to_lowercase()),
+            });
+        }
+        let line = self.pager.is_none().then_some(self.cursor.y);
+        if line.is_some() && (self.announced.line != line || self.announced.mode != Some(self.mode))
+        {
+            let text = self.text[self.cursor.y].0.trim();
+            let mut announcement = format!(
+                "line {}: {}",
+                self.cursor.y + 1,
+                if text.is_empty() { "blank" } else { text }
+            );
+            // which isn't drawn in accessible mode
+            let annotation = self.annotation_text(self.cursor.y);
+            if !annotation.is_empty() {
+                announcement.push_str(&format!(" ({})", annotation));
+            }
+            announcements.push(announcement);
+        }
+        if self.message.is_some() && self.announced.message != self.message {
+            announcements.extend(self.message.clone());
+        }
+        self.announced = Announced {
+            mode: Some(self.mode),
+            line,
+            message: self.message.clone(),
+        };
+        announcements
+    }
+
+    pub fn announce(&mut self, channel: &mut Channel) -> io::Result<()> {
+        let announcements = self.announcements();
+        if announcements.is_empty() {
+            return Ok(());
+        }
+        match &mut channel.file {
+            Some(file) => {
+                for announcement in announcements {
+                    writeln!(file, "{}", announcement)?;
+                }
+                file.flush()
+            }
+            None => {
+                // as the last thing drawn, which is what screen readers pick up on
+                self.message = Some(announcements.join("; "));
+                self.announced.message = self.message.clone();
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use {crate::state::State, crossterm::style::Color, std::ops::Range};
+
+const STANDARD: [Color; 8] = [
+    Color::Black,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+    Color::Grey,
+];
+
+const BRIGHT: [Color; 8] = [
+    Color::DarkGrey,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+// An escape sequence in the text, like the colours in a build log, and for those that set the
+// foreground, what they set it to. None is the terminal's default.
+pub struct Escape {
+    pub range: Range<usize>,
+    pub fg: Option<Option<Color>>,
+}
+
+// The foreground an SGR sequence's parameters leave, if they touch it. Other attributes are
+// ignored, and so is the background, which would fight with search and selection highlights.
+fn sgr(params: &str) -> Option<Option<Color>> {
+    let mut fg = None;
+    let mut params = params.split(';').map(|p| p.parse::<u8>().unwrap_or(0));
+    while let Some(p) = params.next() {
+        fg = match p {
+            0 | 39 => Some(None),
+            30..=37 => Some(Some(STANDARD[p as usize - 30])),
+            90..=97 => Some(Some(BRIGHT[p as usize - 90])),
+            38 => match params.next() {
+                Some(5) => params.next().map(|n| Some(Color::AnsiValue(n))),
+                Some(2) => match (params.next(), params.next(), params.next()) {
+                    (Some(r), Some(g), Some(b)) => Some(Some(Color::Rgb { r, g, b })),
+                    _ => fg,
+                },
+                _ => fg,
+            },
+            _ => fg,
+        };
+    }
+    fg
+}
+
+// CSI sequences (ESC [ parameters, then a final byte), and any other ESC on its own
+pub fn escapes(line: &str) -> Vec<Escape> {
+    let bytes = line.as_bytes();
+    let mut escapes = Vec::new();
+    let mut i = 0;
+    while let Some(start) = line[i..].find('\x1b').map(|j| i + j) {
+        let (end, fg) = if bytes.get(start + 1) == Some(&b'[') {
+            let params = bytes[start + 2..]
+                .iter()
+                .take_while(|&&b| matches!(b, b'0'..=b'9' | b';' | b':' | b'?'))
+                .count();
+            let params_end = start + 2 + params;
+            match bytes.get(params_end) {
+                Some(b'm') => (params_end + 1, sgr(&line[start + 2..params_end])),
+                Some(0x40..=0x7e) => (params_end + 1, None),
+                // cut off, so hide what there is of it
+                _ => (params_end, None),
+            }
+        } else {
+            (start + 1, None)
+        };
+        escapes.push(Escape {
+            range: start..end,
+            fg,
+        });
+        i = end;
+    }
+    escapes
+}
+
+// Files with escape sequences in them are shown with the sequences taking up no room and
+// colouring the text after them, rather than as raw bytes. The text itself is left alone.
+impl State {
+    pub fn toggle_ansi(&mut self) {
+        self.ansi = !self.ansi;
+    }
+
+    // The chars of line y as they're drawn, with where they are and how wide they are. Escape
+    // sequences are left out while they're being rendered, and otherwise ESC is drawn as a
+    // symbol, since printing it would have the terminal act on the sequence itself. So is a CR
+    // left in a line, which would send the terminal back to the start of it.
+    pub fn drawn_chars(&self, y: usize) -> impl Iterator<Item = (usize, char, usize)> + '_ {
+        let escapes = if self.ansi {
+            escapes(&self.text[y].0)
+        } else {
+            Vec::new()
+        };
+        let mut next = 0;
+        self.text[y].0.char_indices().filter_map(move |(x, c)| {
+            while next < escapes.len() && escapes[next].range.end <= x {
+                next += 1;
+            }
+            if escapes
+                .get(next)
+                .is_some_and(|escape| escape.range.contains(&x))
+            {
+                None
+            } else if c == '\x1b' {
+                Some((x, '\u{241B}', 1))
+            } else if c == '\r' {
+                Some((x, '\u{240D}', 1))
+            } else {
+                Some((x, c, self.char_width(c)))
+            }
+        })
+    }
+
+    // how wide line y is drawn between the given bytes
+    pub fn drawn_width(&self, y: usize, range: Range<usize>) -> usize {
+        self.drawn_chars(y)
+            .filter(|&(x, _, _)| range.contains(&x))
+            .map(|(_, _, w)| w)
+            .sum()
+    }
+}
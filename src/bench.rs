@@ -0,0 +1,87 @@
+use {
+    crate::{config::Config, keymap::Keymap, state::State, ui},
+    anyhow::Result,
+    regex::Regex,
+    std::{
+        fmt::Write as _,
+        fs, io,
+        time::{Duration, Instant},
+    },
+};
+
+// Representative workloads, shared by the criterion benchmarks (benches/editing.rs) and
+// `vee --bench [file]`, so that the numbers from one can be checked against the other.
+
+pub const SIZE: (u16, u16) = (120, 50);
+pub const PATTERN: &str = r"item_\d+7";
+
+// generated text in the shape of source code: indentation, comments, and some wide characters
+pub fn sample(lines: usize) -> Vec<u8> {
+    let mut text = String::new();
+    for i in 0..lines {
+        match i % 4 {
+            0 => writeln!(text, "fn item_{}(x: usize) -> usize {{", i),
+            1 => writeln!(text, "    // add {} to x, 中文 included for width", i),
+            2 => writeln!(text, "    x + {} // item_{}", i, i + 1),
+            _ => writeln!(text, "}}"),
+        }
+        .unwrap();
+    }
+    text.into_bytes()
+}
+
+pub fn open(bytes: &[u8], config: Config) -> State {
+    let mut s = State::from_bytes("bench".to_string(), bytes, config, Keymap::default());
+    s.resize(SIZE);
+    s
+}
+
+// runs idle work to completion, as if the user waited for it
+pub fn drain(s: &mut State) {
+    while !s.idle.is_empty() {
+        s.run_idle();
+    }
+}
+
+pub fn search(s: &mut State, pattern: &str) {
+    s.search = Some(Regex::new(pattern));
+    s.reannotate();
+    drain(s);
+}
+
+pub fn reannotate(s: &mut State) {
+    s.reannotate();
+    drain(s);
+}
+
+pub fn draw(s: &mut State) -> Result<()> {
+    ui::draw(io::sink(), s)
+}
+
+// runs f repeatedly for about a second, returning the mean time per run
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs == 0 || start.elapsed() < Duration::from_secs(1) {
+        f();
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+pub fn run(file: Option<String>, config: Config) -> Result<()> {
+    let (name, bytes) = match file {
+        Some(file) => (file.clone(), fs::read(&file)?),
+        None => ("100000 generated lines".to_string(), sample(100_000)),
+    };
+    println!("{}", name);
+    let report = |label: &str, mean: Duration| println!("  {:<12} {:>12.3?}", label, mean);
+    report("open", time(|| drop(open(&bytes, config.clone()))));
+    let mut s = open(&bytes, config);
+    report("search", time(|| search(&mut s, PATTERN)));
+    report("reannotate", time(|| reannotate(&mut s)));
+    // the sink never fails to write, so the only errors are the ones this first draw would hit
+    draw(&mut s)?;
+    report("draw", time(|| draw(&mut s).unwrap()));
+    Ok(())
+}
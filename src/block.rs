@@ -0,0 +1,214 @@
+use {
+    crate::{
+        edit::EditOp,
+        operator::{swap_case, Operator},
+        state::{Mode, Point, State},
+        transaction::Transaction,
+    },
+    std::ops::RangeInclusive,
+};
+
+// A block selection is the rectangle with the anchor and cursor at its corners. It's measured
+// in columns rather than chars, so it lines up on screen through tabs and wide chars, and it
+// uses the cursor's target column, so moving down past a short line doesn't narrow it. Like a
+// normal selection the cursor's column is just outside it, so a block with the anchor and
+// cursor in the same column is empty, a cursor on each line to insert at.
+pub struct Block {
+    pub lines: RangeInclusive<usize>,
+    left: usize,
+    right: usize,
+}
+
+impl State {
+    pub fn set_anchor(&mut self, block: bool) {
+        // a block is selections of its own, one per line
+        if block {
+            self.clear_selections();
+        }
+        self.anchor = Some(self.cursor);
+        self.block = block;
+    }
+
+    pub fn block(&self) -> Option<Block> {
+        let anchor = self.anchor.filter(|_| self.block)?;
+        let (top, bottom) = if anchor.y < self.cursor.y {
+            (anchor.y, self.cursor.y)
+        } else {
+            (self.cursor.y, anchor.y)
+        };
+        Some(Block {
+            lines: top..=bottom,
+            left: usize::min(anchor.w, self.cursor.w),
+            right: usize::max(anchor.w, self.cursor.w),
+        })
+    }
+
+    // The part of line y between the block's columns, or None if the line ends before them. A
+    // char belongs to the block if it starts in it.
+    pub fn block_span(&self, block: &Block, y: usize) -> Option<(Point, Point)> {
+        let len = self.text[y].0.len();
+        let (mut start, mut end) = (None, None);
+        let mut w = 0;
+        for (x, _, cw) in self.drawn_chars(y) {
+            if start.is_none() && w >= block.left {
+                start = Some(x);
+            }
+            if w >= block.right {
+                end = Some(x);
+                break;
+            }
+            w += cw;
+        }
+        if start.is_none() && w < block.left {
+            return None;
+        }
+        Some((
+            Point {
+                x: start.unwrap_or(len),
+                y,
+            },
+            Point {
+                x: end.unwrap_or(len),
+                y,
+            },
+        ))
+    }
+
+    fn block_spans(&self, block: &Block) -> Vec<(Point, Point)> {
+        block
+            .lines
+            .clone()
+            .filter_map(|y| self.block_span(block, y))
+            .collect()
+    }
+
+    // Operators act on each line of a block separately. What's yanked or deleted is the lines
+    // of the block, joined by newlines. A change leaves a cursor at the start of each line,
+    // inserting at all of them, see selections.rs. None of this is recorded, since a block
+    // can't be replayed at the cursor, except for the insert after a change.
+    pub fn operate_block(&mut self, op: Operator, block: Block) {
+        let f: fn(&str) -> String = match op {
+            Operator::Yank | Operator::Delete | Operator::Change => |_| String::new(),
+            Operator::Uppercase => str::to_uppercase,
+            Operator::Lowercase => str::to_lowercase,
+            Operator::SwapCase => swap_case,
+            // every line of the block, even those that end before its columns
+            Operator::Comment => {
+                return self.comment_lines(*block.lines.start(), *block.lines.end());
+            }
+        };
+        let spans = self.block_spans(&block);
+        if spans.is_empty() {
+            self.message = Some("Nothing in the block".to_string());
+            return;
+        }
+        let text = spans
+            .iter()
+            .map(|&(start, end)| self.text_between(start, end))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Operator::Yank = op {
+            self.kill(text);
+            return;
+        }
+        let mut transaction = Transaction::default();
+        for &(start, end) in &spans {
+            transaction.replace(start, end, f(&self.text_between(start, end)));
+        }
+        let spans = self.apply(transaction).expect("lines are in order");
+        if !matches!(op, Operator::Delete | Operator::Change) {
+            return;
+        }
+        self.kill(text);
+        self.anchor = None;
+        self.move_cursor(spans[0].0);
+        if matches!(op, Operator::Change) {
+            for &(start, _) in &spans[1..] {
+                self.add_selection(start, start, false);
+            }
+            self.mode = Mode::Insert;
+            self.record(EditOp::BeginEdit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    fn lines(s: &State) -> Vec<&str> {
+        s.text.iter().map(|line| line.0.as_str()).collect()
+    }
+
+    // a block from column left on the first line to column right on the last
+    fn blocked(text: &str, left: usize, right: usize) -> State {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            text.as_bytes(),
+            Config::default(),
+            Keymap::default(),
+        );
+        s.move_cursor(Point { x: left, y: 0 });
+        s.set_anchor(true);
+        s.move_cursor(Point {
+            x: right,
+            y: s.text.len() - 1,
+        });
+        s
+    }
+
+    #[test]
+    fn operates_on_each_line() {
+        // lines ending inside the block, or before it, and a last line with no newline
+        let mut s = blocked("abcdef\nab\n\nabcdef", 1, 4);
+        let block = s.block().unwrap();
+        s.operate_block(Operator::Delete, block);
+        assert_eq!(lines(&s), ["aef", "a", "", "aef"]);
+        assert_eq!(s.kills.get(0).map(String::as_str), Some("bcd\nb\nbcd"));
+        assert_eq!(Point::from(s.cursor), Point { x: 1, y: 0 });
+        assert!(s.violations().is_empty());
+
+        let mut s = blocked("abc\nabc", 1, 2);
+        let block = s.block().unwrap();
+        s.operate_block(Operator::Uppercase, block);
+        assert_eq!(lines(&s), ["aBc", "aBc"]);
+
+        let mut s = blocked("abc\nabc", 1, 2);
+        let block = s.block().unwrap();
+        s.operate_block(Operator::Change, block);
+        assert!(s.mode == Mode::Insert);
+        assert_eq!(lines(&s), ["ac", "ac"]);
+        assert_eq!(
+            s.other_selections(),
+            [(Point { x: 1, y: 1 }, Point { x: 2, y: 1 })]
+        );
+        assert!(s.violations().is_empty());
+    }
+
+    #[test]
+    fn skips_lines_short_of_the_block() {
+        // moving down keeps the column the cursor's aiming for, past the end of the short lines
+        let mut s = blocked("abcdef\n\nab", 3, 5);
+        s.move_cursor(Point { x: 5, y: 0 });
+        s.move_down(2);
+        let block = s.block().unwrap();
+        assert_eq!((block.left, block.right), (3, 5));
+        assert_eq!(s.block_span(&block, 1), None);
+        assert_eq!(s.block_span(&block, 2), None);
+        s.operate_block(Operator::Delete, block);
+        assert_eq!(lines(&s), ["abcf", "", "ab"]);
+        assert_eq!(s.kills.get(0).map(String::as_str), Some("de"));
+        assert!(s.violations().is_empty());
+
+        // an empty block still has a place on each line that reaches it
+        let s = blocked("ab\nab", 1, 1);
+        let block = s.block().unwrap();
+        assert_eq!(
+            s.block_span(&block, 1),
+            Some((Point { x: 1, y: 1 }, Point { x: 1, y: 1 }))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use {
+    crate::{
+        config::Config,
+        keymap::Keymap,
+        pager::Target,
+        path_prompt::PathAction,
+        state::{Point, State},
+    },
+    std::{
+        fs,
+        path::{Path, PathBuf, MAIN_SEPARATOR},
+    },
+};
+
+// A directory listed in the pager, one entry per line, to open files from and to create,
+// rename and delete them. Directories come first, each with a separator on the end, and
+// choosing one lists that instead.
+impl State {
+    // for a directory named on the command line, which has no text of its own
+    pub fn open_dir(dir: String, config: Config, keymap: Keymap) -> State {
+        let mut s = State::from_bytes(dir.clone(), b"", config, keymap);
+        s.scratch = true;
+        s.browse(PathBuf::from(dir));
+        s
+    }
+
+    pub fn browse(&mut self, dir: PathBuf) {
+        let dir = fs::canonicalize(&dir).unwrap_or(dir);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.message = Some(format!("Couldn't list {}: {}", dir.display(), err));
+                return;
+            }
+        };
+        // false for directories, so that they sort first
+        let mut entries: Vec<(bool, String)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let is_file = !entry.file_type().ok()?.is_dir();
+                Some((is_file, entry.file_name().to_string_lossy().into_owned()))
+            })
+            .collect();
+        entries.sort();
+        let parent = dir.parent().map(|_| (false, "..".to_string()));
+        let mut lines = Vec::new();
+        let mut targets = Vec::new();
+        for (i, (is_file, name)) in parent.into_iter().chain(entries).enumerate() {
+            targets.push(Target {
+                line: i,
+                span: 0..0,
+                file: dir.join(&name).to_string_lossy().into_owned(),
+                point: Point { x: 0, y: 0 },
+            });
+            lines.push(if is_file {
+                name
+            } else {
+                format!("{}{}", name, MAIN_SEPARATOR)
+            });
+        }
+        self.open_pager(dir.display().to_string(), lines);
+        if let Some(pager) = &mut self.pager {
+            pager.targets = targets;
+            pager.dir = Some(dir);
+        }
+    }
+
+    // lists the directory the file being edited is in
+    pub fn browse_here(&mut self) {
+        let file = Path::new(&self.file);
+        let dir = if file.is_dir() {
+            file
+        } else {
+            file.parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+        };
+        self.browse(dir.to_path_buf());
+    }
+
+    // the path of the chosen entry, unless it's the parent
+    fn chosen_entry(&mut self) -> Option<String> {
+        let entry = self
+            .pager
+            .as_ref()
+            .filter(|pager| pager.dir.is_some())
+            .and_then(|pager| pager.targets.get(pager.selected))
+            .map(|target| target.file.clone());
+        if entry.is_none() {
+            self.message = Some("Not a directory listing".to_string());
+        }
+        entry.filter(|entry| Path::new(entry).file_name().is_some())
+    }
+
+    pub fn browse_create(&mut self) {
+        match self.pager.as_ref().and_then(|pager| pager.dir.as_ref()) {
+            Some(dir) => {
+                let input = format!("{}{}", dir.display(), MAIN_SEPARATOR);
+                self.open_path_prompt(PathAction::Create, input);
+            }
+            None => self.message = Some("Not a directory listing".to_string()),
+        }
+    }
+
+    pub fn browse_rename(&mut self) {
+        if let Some(entry) = self.chosen_entry() {
+            self.open_path_prompt(PathAction::Rename(entry.clone()), entry);
+        }
+    }
+
+    pub fn browse_delete(&mut self) {
+        if let Some(entry) = self.chosen_entry() {
+            self.open_path_prompt(PathAction::Delete, entry);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use {
+    crate::{
+        annotate::{self, Annotation},
+        coverage::Coverage,
+        crypt::Encryption,
+        disk::Stamp,
+        edit::Recorder,
+        expand::Expansions,
+        git::GitStatus,
+        idle::{Idle, Task},
+        language::Language,
+        line::Line,
+        mark::{MarkId, Marks},
+        pager::Pager,
+        replace::Replace,
+        selections::Selection,
+        state::{Cursor, Mode, Point, State},
+        temp_file::TempFile,
+        undo::History,
+    },
+    regex::Regex,
+    std::{
+        collections::{BTreeMap, HashMap},
+        ops::{Deref, DerefMut},
+        path::{Path, PathBuf},
+        time::Instant,
+    },
+};
+
+// An open file, and everything about editing it. State keeps them all in State::buffers, in the
+// order they were opened, and derefs to the one being edited, buffers[active], so that the rest
+// of the editor only ever deals with one buffer at a time.
+pub struct Buffer {
+    pub file: String,
+    // never empty, see line::split
+    pub text: Vec<Line>,
+    // set for files kept encrypted on disk
+    pub encryption: Option<Encryption>,
+    // unsaved changes
+    pub dirty: bool,
+    // lines end in \r\n on disk, rather than \n
+    pub crlf: bool,
+    // the last line ends in a newline on disk, or None to decide on saving, see ends_in_newline
+    pub eol: Option<bool>,
+    // not on disk yet, until the first save
+    pub new: bool,
+    // never saved, like the empty buffer standing in for a directory, see browse.rs
+    pub scratch: bool,
+    // the file on disk as of the last time it was read or written, see disk.rs
+    pub stamp: Option<Stamp>,
+    // set when another program is waiting on us to edit this file, see temp_file.rs
+    pub temp_file: Option<&'static TempFile>,
+    // set for files over the configured size limits, which disables expensive features
+    pub large: bool,
+    pub language: &'static Language,
+    // the filetype picked from the status line, whatever the extension says, see status.rs
+    pub filetype_override: Option<String>,
+    // the temp file's own, drawn instead of config.ruler
+    pub ruler: Option<usize>,
+    pub cursor: Cursor,
+    pub anchor: Option<Cursor>,
+    // the anchor is the corner of a block rather than the start of a selection, see block.rs
+    pub block: bool,
+    // the selections expand-selection grew from, to shrink back to
+    pub expansions: Expansions,
+    // the others, after selecting every match, see selections.rs
+    pub selections: Vec<Selection>,
+    pub search: Option<Result<Regex, regex::Error>>,
+    pub replace: Option<Replace>,
+    pub pager: Option<Pager>,
+    pub idle: Idle,
+    pub edits: Recorder,
+    pub history: History,
+    pub marks: Marks,
+    // marks set by the user, by register
+    pub bookmarks: HashMap<Option<char>, MarkId>,
+    // from external tools, by namespace
+    pub annotations: BTreeMap<String, Vec<Annotation>>,
+    pub coverage: Option<Coverage>,
+    // escape sequences drawn as the colours they set, see ansi.rs
+    pub ansi: bool,
+    // which kill was last pasted, and where, so that it can be swapped for an older one
+    pub last_paste: Option<(usize, Point, Point, bool)>,
+    // when and where the last click was, and how many clicks in a row it was
+    pub last_click: Option<(Instant, Point, usize)>,
+    // when unsaved changes were last written where they can be recovered from, see recovery.rs
+    pub recovered: Option<Instant>,
+    // and when they were last saved, with config.autosave
+    pub autosaved: Option<Instant>,
+    // None outside a repository, see git.rs
+    pub git: Option<GitStatus>,
+    pub git_checked: Option<Instant>,
+}
+
+// the only buffer of a State opened on its own
+impl From<State> for Buffer {
+    fn from(mut s: State) -> Self {
+        s.buffers.swap_remove(s.active)
+    }
+}
+
+impl Deref for State {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Buffer {
+        &self.buffers[self.active]
+    }
+}
+
+impl DerefMut for State {
+    fn deref_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.active]
+    }
+}
+
+impl State {
+    // Switches to the buffer at index to. A replace in progress is about the buffer it was
+    // started in, so it stops there, but the pager is shown again on switching back.
+    pub fn switch_buffer(&mut self, to: usize) {
+        // so that undo doesn't mix up one buffer's changes with the other's
+        self.break_undo();
+        if self.replace.is_some() {
+            self.cancel_replace();
+        }
+        self.active = to;
+        self.windows[self.window].buffer = to;
+        self.mode = if self.pager.is_some() {
+            Mode::Pager
+        } else {
+            Mode::Normal
+        };
+        // annotating may have been cut short when it was put away
+        self.reannotate();
+        self.idle.schedule(Task::GitStatus);
+    }
+
+    // The file each buffer has open and where its cursor is, in the order they were opened, or
+    // None for those with nothing on disk to open again: new files, and temp files that
+    // something's waiting on. See session.rs.
+    pub fn reopenable(&self) -> Vec<Option<(&str, Cursor)>> {
+        self.buffers
+            .iter()
+            .map(|b| (!b.new && b.temp_file.is_none()).then_some((b.file.as_str(), b.cursor)))
+            .collect()
+    }
+
+    // every open file, in the order they were opened
+    pub fn buffer_files(&self) -> Vec<String> {
+        self.buffers.iter().map(|b| b.file.clone()).collect()
+    }
+
+    pub fn next_buffer(&mut self) {
+        if self.buffers.len() == 1 {
+            self.message = Some("No other buffers".to_string());
+            return;
+        }
+        self.switch_buffer((self.active + 1) % self.buffers.len());
+    }
+
+    pub fn prev_buffer(&mut self) {
+        if self.buffers.len() == 1 {
+            self.message = Some("No other buffers".to_string());
+            return;
+        }
+        let n = self.buffers.len();
+        self.switch_buffer((self.active + n - 1) % n);
+    }
+
+    // Closes the buffer being edited, without saving, for the next one (or the one before, if
+    // it was the last). Returns false if it's the only one.
+    pub fn close_buffer(&mut self) -> bool {
+        if self.buffers.len() == 1 {
+            return false;
+        }
+        if self.replace.is_some() {
+            self.cancel_replace();
+        }
+        let buffer = self.buffers.remove(self.active);
+        self.remove_buffer_windows(self.active);
+        self.switch_buffer(usize::min(self.active, self.buffers.len() - 1));
+        self.message = Some(format!("Closed {}", buffer.file));
+        true
+    }
+
+    // Opens file in a buffer of its own, after the one being edited, or switches to it if it's
+    // already open. Everything that isn't about a buffer, like registers and history, is kept.
+    pub fn open_file(&mut self, file: String) {
+        if Path::new(&file).is_dir() {
+            self.browse(PathBuf::from(file));
+            return;
+        }
+        if let Some(i) = self
+            .buffers
+            .iter()
+            .position(|buffer| annotate::same_file(&file, &buffer.file))
+        {
+            if i != self.active {
+                self.switch_buffer(i);
+            }
+            return;
+        }
+        let mut s = match State::open(
+            file.clone(),
+            false,
+            self.config.clone(),
+            self.keymap.clone(),
+        ) {
+            Ok(s) => s,
+            Err(err) => {
+                self.message = Some(format!("Error opening {}: {}", file, err));
+                return;
+            }
+        };
+        let message = s.message.take();
+        self.insert_buffer_windows(self.active + 1);
+        self.buffers.insert(self.active + 1, Buffer::from(s));
+        self.switch_buffer(self.active + 1);
+        // the empty buffer left by opening a directory has done its job
+        if self.buffers[self.active - 1].scratch {
+            self.buffers.remove(self.active - 1);
+            self.remove_buffer_windows(self.active - 1);
+            self.active -= 1;
+        }
+        self.message = message;
+        self.check_recovery();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+        std::{env, fs},
+    };
+
+    #[test]
+    fn each_buffer_keeps_its_own_state() {
+        let dir = env::temp_dir().join(format!("vee-buffers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::write(&a, "one\n").unwrap();
+        fs::write(&b, "two\n").unwrap();
+        let mut s = State::open(
+            a.to_string_lossy().into_owned(),
+            false,
+            Config::default(),
+            Keymap::default(),
+        )
+        .unwrap();
+        s.insert_char('x');
+        s.open_pager("listing".to_string(), vec!["line".to_string()]);
+        s.open_file(b.to_string_lossy().into_owned());
+        assert_eq!(s.buffer_files().len(), 2);
+        assert!(s.mode == Mode::Normal && s.pager.is_none());
+        assert!(!s.dirty);
+        assert!(s.violations().is_empty());
+        s.prev_buffer();
+        assert!(s.mode == Mode::Pager && s.pager.is_some());
+        assert!(s.dirty);
+        assert_eq!(s.text[0].0, "xone");
+        assert!(s.violations().is_empty());
+        assert!(s.close_buffer());
+        assert_eq!(s.text[0].0, "two");
+        assert!(!s.close_buffer());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
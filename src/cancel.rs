@@ -0,0 +1,20 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+// Shared between a piece of background work and whoever might make it obsolete. The work checks
+// the token between steps and gives up once it's cancelled, rather than finishing and
+// clobbering fresher state.
+#[derive(Clone, Default)]
+pub struct Token(Arc<AtomicBool>);
+
+impl Token {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    config::Normalization,
+    operator::Operator,
+    path_prompt::PathAction,
+    sort::Sort,
+    state::{Mode, State},
+};
+
+pub struct Command {
+    pub name: &'static str,
+    // Whether the command moves the cursor or changes the selection, and so should happen at
+    // every selection when there are several, marked `moves` in the table below. Selecting
+    // every match starts them again instead.
+    pub moves: bool,
+    pub run: fn(&mut State),
+}
+
+pub fn lookup(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+// see State::wordish for words in the text
+pub fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_big_word(c: char) -> bool {
+    !c.is_whitespace()
+}
+
+macro_rules! commands {
+    ($($name:literal $($moves:ident)? => $run:expr,)*) => {
+        pub const COMMANDS: &[Command] = &[$(Command {
+            name: $name,
+            moves: commands!(@moves $($moves)?),
+            run: $run,
+        },)*];
+    };
+    (@moves moves) => { true };
+    (@moves) => { false };
+}
+
+commands! {
+    "select-inside-quotes" moves => |s| s.select_inside_quotes(),
+    "select-outside-quotes" moves => |s| s.select_outside_quotes(),
+    "select-word" moves => |s| s.select_word(s.wordish()),
+    "select-big-word" moves => |s| s.select_word(is_big_word),
+    "select-inside-brackets" moves => |s| s.select_inside_brackets(),
+    "select-outside-brackets" moves => |s| s.select_outside_brackets(),
+    "select-all" moves => |s| s.select_all(),
+    "select-line" moves => |s| s.select_line(),
+    "select-para" moves => |s| s.select_para(),
+    "expand-selection" => |s| s.repeat(State::expand_selection),
+    "shrink-selection" => |s| s.repeat(State::shrink_selection),
+    "set-anchor" moves => |s| s.set_anchor(false),
+    "set-block-anchor" => |s| s.set_anchor(true),
+    "clear-anchor" moves => |s| s.anchor = None,
+    "move-start-of-line" moves => |s| s.move_start_of_line(),
+    "move-end-of-line" moves => |s| s.move_end_of_line(),
+    "move-start-of-para" moves => |s| s.repeat(State::move_start_of_para),
+    "move-end-of-para" moves => |s| s.repeat(State::move_end_of_para),
+    "move-start-of-file" moves => |s| s.move_start_of_file(),
+    "move-end-of-file" moves => |s| s.move_end_of_file(),
+    "move-left-word" moves => |s| s.repeat(|s| s.move_left_word(s.wordish())),
+    "move-right-word" moves => |s| s.repeat(|s| s.move_right_word(s.wordish())),
+    "move-left-big-word" moves => |s| s.repeat(|s| s.move_left_word(is_big_word)),
+    "move-right-big-word" moves => |s| s.repeat(|s| s.move_right_word(is_big_word)),
+    "move-bracket-inside" moves => |s| s.move_bracket_inside(),
+    "move-bracket-outside" moves => |s| s.move_bracket_outside(),
+    "move-left" moves => |s| s.move_left(s.count()),
+    "move-down" moves => |s| s.move_down(s.count()),
+    "move-up" moves => |s| s.move_up(s.count()),
+    "move-right" moves => |s| s.move_right(s.count()),
+    "move-left-5" moves => |s| s.move_left(5 * s.count()),
+    "move-down-5" moves => |s| s.move_down(5 * s.count()),
+    "move-up-5" moves => |s| s.move_up(5 * s.count()),
+    "move-right-5" moves => |s| s.move_right(5 * s.count()),
+    "move-next-match" moves => |s| s.repeat(State::move_next_match),
+    "move-prev-match" moves => |s| s.repeat(State::move_prev_match),
+    "yank" => |s| s.operate(Operator::Yank),
+    "delete-selection" => |s| s.operate(Operator::Delete),
+    "change-selection" => |s| s.operate(Operator::Change),
+    "uppercase" => |s| s.operate(Operator::Uppercase),
+    "lowercase" => |s| s.operate(Operator::Lowercase),
+    "swap-case" => |s| s.operate(Operator::SwapCase),
+    "normalize" => |s| s.normalize(None),
+    "normalize-nfc" => |s| s.normalize(Some(Normalization::Nfc)),
+    "normalize-nfd" => |s| s.normalize(Some(Normalization::Nfd)),
+    "indent" => |s| s.indent(),
+    "dedent" => |s| s.dedent(),
+    "toggle-comment" => |s| s.operate(Operator::Comment),
+    "sort-lines" => |s| s.sort_lines(Sort::Ascending),
+    "sort-lines-reverse" => |s| s.sort_lines(Sort::Descending),
+    "sort-lines-unique" => |s| s.sort_lines(Sort::Unique),
+    "paste" => |s| s.paste(false),
+    "paste-verbatim" => |s| s.paste(true),
+    "paste-cycle" => |s| s.paste_cycle(),
+    "search" => |s| s.open_search(false),
+    "search-raw" => |s| s.open_search(true),
+    "search-word" => |s| s.search_word(s.wordish()),
+    "select-matches" => |s| s.select_matches(),
+    "clear-selections" => |s| s.clear_selections(),
+    "rotate-selections" => |s| s.repeat(|s| s.rotate_selections(true)),
+    "rotate-selections-back" => |s| s.repeat(|s| s.rotate_selections(false)),
+    "keep-selections" => |s| s.open_filter(true),
+    "drop-selections" => |s| s.open_filter(false),
+    "filter-run" => |s| s.run_filter(),
+    "filter-backspace" => |s| s.filter_backspace(),
+    "filter-cancel" => |s| s.cancel_filter(),
+    "add-cursor-below" => |s| s.repeat(|s| s.add_cursor(true)),
+    "add-cursor-above" => |s| s.repeat(|s| s.add_cursor(false)),
+    "replace" => |s| s.open_replace(false),
+    "replace-selection" => |s| s.open_replace(true),
+    "replace-start" => |s| s.start_replace(),
+    "replace-backspace" => |s| s.replace_backspace(),
+    "replace-yes" => |s| s.replace_yes(),
+    "replace-skip" => |s| s.replace_skip(),
+    "replace-all" => |s| s.replace_all(),
+    "replace-cancel" => |s| s.cancel_replace(),
+    "search-run" => |s| s.run_search(),
+    "search-cancel" => |s| s.cancel_search_prompt(),
+    "search-backspace" => |s| s.search_backspace(),
+    "search-prev" => |s| s.search_prev(),
+    "search-next" => |s| s.search_next(),
+    "toggle-search-case" => |s| s.toggle_search_case(),
+    "cancel-search" => |s| s.cancel_search(),
+    "cancel-task" => |s| s.cancel_task(),
+    "begin-edit" => |s| s.begin_edit(),
+    "end-edit" => |s| s.end_edit(),
+    "open-line-below" => |s| s.open_line(false),
+    "open-line-above" => |s| s.open_line(true),
+    "repeat-edit" => |s| s.repeat_edit(),
+    "undo" => |s| s.undo(),
+    "redo" => |s| s.redo(),
+    "record-macro" => |s| s.toggle_macro_recording(),
+    "play-macro" => |s| s.play_macro(),
+    "set-mark" => |s| s.set_mark(),
+    "jump-to-mark" => |s| s.jump_to_mark(),
+    "newline" => |s| s.newline(),
+    "backspace" => |s| s.backspace(),
+    "delete" => |s| s.delete(),
+    "join-lines" => |s| s.join_lines(),
+    "system-mode" => |s| s.mode = Mode::System,
+    "choose-register" => |s| s.mode = Mode::Register,
+    "register-cancel" => |s| s.mode = Mode::Normal,
+    "insert-binding" => |s| s.start_binding(),
+    "palette" => |s| s.open_palette(),
+    "palette-run" => |s| s.run_palette(),
+    "palette-cancel" => |s| s.cancel_palette(),
+    "palette-backspace" => |s| s.palette_backspace(),
+    "palette-delete" => |s| s.palette_delete(),
+    "palette-delete-word" => |s| s.palette_delete_word(),
+    "palette-kill-start" => |s| s.palette_kill_start(),
+    "palette-left" => |s| s.palette_left(),
+    "palette-right" => |s| s.palette_right(),
+    "palette-home" => |s| s.palette_home(),
+    "palette-end" => |s| s.palette_end(),
+    "palette-prev" => |s| s.palette_prev(),
+    "palette-next" => |s| s.palette_next(),
+    "palette-search" => |s| s.palette_search(),
+    "repeat-last-command" => |s| s.repeat_last_command(),
+    "zen" => |s| s.toggle_zen(),
+    "minimap" => |s| s.toggle_minimap(),
+    "toggle-ansi" => |s| s.toggle_ansi(),
+    "toggle-virtual-edit" => |s| s.toggle_virtual_edit(),
+    "toggle-eol" => |s| s.toggle_eol(),
+    "clear-annotations" => |s| s.clear_all_annotations(),
+    "coverage" => |s| s.toggle_coverage(),
+    "keymap" => |s| s.open_pager("keymap".to_string(), s.keymap.describe()),
+    "pager-down" => |s| s.scroll_pager(1),
+    "pager-up" => |s| s.scroll_pager(-1),
+    "pager-page-down" => |s| s.scroll_pager(s.height() as isize),
+    "pager-page-up" => |s| s.scroll_pager(-(s.height() as isize)),
+    "pager-close" => |s| s.close_pager(),
+    "pager-open" => |s| s.open_result(),
+    "grep" => |s| s.grep(),
+    "save" => |s| s.save(),
+    "split-window" => |s| s.split_window(false),
+    "split-window-right" => |s| s.split_window(true),
+    "next-window" => |s| s.next_window(),
+    "close-window" => |s| s.close_window(),
+    "goto-line" => |s| s.open_status_prompt(Mode::Goto),
+    "pick-filetype" => |s| s.open_status_prompt(Mode::Filetype),
+    "status-prompt-run" => |s| s.run_status_prompt(),
+    "status-prompt-cancel" => |s| s.cancel_status_prompt(),
+    "status-prompt-backspace" => |s| s.status_prompt_backspace(),
+    "session-save" => |s| s.open_session_prompt(true),
+    "session-load" => |s| s.open_session_prompt(false),
+    "session-list" => |s| s.list_sessions(),
+    "session-run" => |s| s.run_session_prompt(),
+    "session-backspace" => |s| s.session_backspace(),
+    "session-cancel" => |s| s.cancel_session_prompt(),
+    "reload" => |s| s.reload(),
+    "quit" => |s| s.quit(),
+    "force-quit" => |s| s.force_quit(),
+    "save-and-quit" => |s| s.save_and_quit(),
+    "open-file" => |s| s.open_path_prompt(PathAction::Open, String::new()),
+    "path-run" => |s| s.run_path_prompt(),
+    "path-cancel" => |s| s.cancel_path_prompt(),
+    "path-backspace" => |s| s.path_backspace(),
+    "path-complete" => |s| s.complete_path(),
+    "browse" => |s| s.browse_here(),
+    "browse-create" => |s| s.browse_create(),
+    "browse-rename" => |s| s.browse_rename(),
+    "browse-delete" => |s| s.browse_delete(),
+    "next-buffer" => |s| s.next_buffer(),
+    "previous-buffer" => |s| s.prev_buffer(),
+    "quit-with-error" => |s| s.quit_with_error(),
+    "dialog-yes" => |s| s.answer(Some(true)),
+    "dialog-no" => |s| s.answer(Some(false)),
+    "dialog-cancel" => |s| s.answer(None),
+}
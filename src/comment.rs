@@ -0,0 +1,108 @@
+use crate::{
+    indent::{common_prefix, indent_of},
+    state::State,
+};
+
+impl State {
+    // Comments out lines first to last, or uncomments them if they're all comments already.
+    // Markers go after the indentation the lines share, so that they line up, and blank lines
+    // are left alone. As an operator, see operator.rs, this is the lines of the target.
+    pub fn comment_lines(&mut self, first: usize, last: usize) {
+        let marker = match self.language.line_comment {
+            Some(marker) => marker,
+            None => {
+                self.message = Some(format!("No line comments in {}", self.language.name));
+                return;
+            }
+        };
+        let lines: Vec<&str> = self.text[first..=last]
+            .iter()
+            .map(|line| line.0.as_str())
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        if lines
+            .iter()
+            .all(|line| line.trim_start().starts_with(marker))
+        {
+            self.map_lines(first, last, |line| {
+                let indent = indent_of(line);
+                match line[indent.len()..].strip_prefix(marker) {
+                    Some(rest) => format!("{}{}", indent, rest.strip_prefix(' ').unwrap_or(rest)),
+                    None => line.to_string(),
+                }
+            });
+        } else {
+            // the indent they share, as text, which a tab or wider whitespace can be part of
+            let indent = lines
+                .iter()
+                .map(|line| indent_of(line))
+                .reduce(common_prefix)
+                .unwrap()
+                .to_string();
+            self.map_lines(first, last, |line| match line.strip_prefix(&indent) {
+                Some(rest) if !line.trim().is_empty() => format!("{}{} {}", indent, marker, rest),
+                _ => line.to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{
+            config::Config,
+            keymap::Keymap,
+            operator::Operator,
+            state::{Point, State},
+        },
+        crossterm::event::{KeyCode, KeyEvent},
+    };
+
+    // toggles comments on all of text, as a rust file
+    fn toggled(text: &str) -> String {
+        let mut s = State::from_bytes(
+            "test.rs".to_string(),
+            text.as_bytes(),
+            Config::default(),
+            Keymap::default(),
+        );
+        s.select_all();
+        s.operate(Operator::Comment);
+        s.text_between(Point { x: 0, y: 0 }, s.end_of_file())
+    }
+
+    #[test]
+    fn comments_as_an_operator() {
+        let mut s = State::from_bytes(
+            "test.rs".to_string(),
+            b"a\nb\nc\n",
+            Config::default(),
+            Keymap::default(),
+        );
+        // commenting the line, then the next one with repeat-edit
+        for c in "#jg".chars() {
+            s.handle(KeyEvent::from(KeyCode::Char(c)));
+        }
+        assert_eq!(
+            s.text_between(Point { x: 0, y: 0 }, s.end_of_file()),
+            "// a\n// b\nc"
+        );
+    }
+
+    #[test]
+    fn comments_at_the_shared_indent() {
+        assert_eq!(toggled("  a\n\n    b"), "  // a\n\n  //   b");
+        assert_eq!(toggled("  // a\n\n  //   b"), "  a\n\n    b");
+    }
+
+    #[test]
+    fn comments_unicode_indents() {
+        assert_eq!(toggled("\u{3000}a\n b"), "// \u{3000}a\n//  b");
+        assert_eq!(toggled("\t\u{3000}a\n\t b"), "\t// \u{3000}a\n\t//  b");
+        assert_eq!(toggled("\u{3000}// a\n\t// b"), "\u{3000}a\n\tb");
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{bail, Result};
+
+// The flags main understands, which completions are generated from. Anything else is the file
+// to edit, and +LINE before it starts on that line.
+pub struct Flag {
+    pub name: &'static str,
+    // what follows the flag, for the usage message
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+pub static FLAGS: &[Flag] = &[
+    Flag {
+        name: "--private",
+        args: "",
+        help: "Leave nothing behind on disk",
+    },
+    Flag {
+        name: "--no-color",
+        args: "",
+        help: "Reverse video, bold and underline in place of colours",
+    },
+    Flag {
+        name: "--accessible",
+        args: "",
+        help: "No decorations, and announcements for screen readers",
+    },
+    Flag {
+        name: "--wait",
+        args: "",
+        help: "Ignored, since vee always waits",
+    },
+    Flag {
+        name: "--tutor",
+        args: "",
+        help: "Start the tutorial",
+    },
+    Flag {
+        name: "--encrypt",
+        args: "FILE",
+        help: "Edit FILE, encrypted with a passphrase on save",
+    },
+    Flag {
+        name: "--exec",
+        args: "SCRIPT FILE",
+        help: "Run SCRIPT on FILE without the terminal",
+    },
+    Flag {
+        name: "--bench",
+        args: "[FILE]",
+        help: "Time opening, searching and drawing",
+    },
+];
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+impl Flag {
+    // what it takes, in lower case, and whether each is optional, like [FILE]
+    fn params(&self) -> impl Iterator<Item = (String, bool)> + '_ {
+        self.args.split_whitespace().map(|arg| {
+            let name = arg.trim_start_matches('[').trim_end_matches(']');
+            (name.to_lowercase(), name != arg)
+        })
+    }
+
+    // how many it needs before anything else
+    fn required(&self) -> usize {
+        self.params().filter(|(_, optional)| !optional).count()
+    }
+}
+
+// the names of the flags that need at least n of what they take
+fn taking(n: usize) -> String {
+    let names: Vec<&str> = FLAGS
+        .iter()
+        .filter(|flag| flag.required() >= n)
+        .map(|flag| flag.name)
+        .collect();
+    names.join(" ")
+}
+
+// `vee completions SHELL` prints a script for SHELL to source, completing flags, files, and
+// the shells for completions itself. Whatever a flag takes is a file, so after one that needs
+// them, only files are completed, even starting with - or +.
+pub fn script(shell: &str) -> Result<String> {
+    let names: Vec<&str> = FLAGS.iter().map(|flag| flag.name).collect();
+    Ok(match shell {
+        "bash" => format!(
+            r#"_vee() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}} prev=${{COMP_WORDS[COMP_CWORD-1]}} before=
+    (( COMP_CWORD > 1 )) && before=${{COMP_WORDS[COMP_CWORD-2]}}
+    if [[ $COMP_CWORD == 2 && $prev == completions ]]; then
+        COMPREPLY=($(compgen -W "{shells}" -- "$cur"))
+    elif [[ " {one} " == *" $prev "* || " {two} " == *" $before "* ]]; then
+        COMPREPLY=($(compgen -f -- "$cur"))
+    elif [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "{names}" -- "$cur"))
+    elif [[ $cur != +* ]]; then
+        COMPREPLY=($(compgen -f -- "$cur"))
+        if [[ $COMP_CWORD == 1 ]]; then
+            COMPREPLY+=($(compgen -W "completions" -- "$cur"))
+        fi
+    fi
+}}
+complete -o filenames -F _vee vee
+"#,
+            shells = SHELLS.join(" "),
+            one = taking(1),
+            two = taking(2),
+            names = names.join(" "),
+        ),
+        "zsh" => {
+            let mut script = format!(
+                concat!(
+                    "#compdef vee\n",
+                    "if [[ $words[2] == completions ]]; then\n",
+                    "    _values shell {}\n",
+                    "    return\n",
+                    "fi\n",
+                    "_arguments \\\n",
+                ),
+                SHELLS.join(" "),
+            );
+            for flag in FLAGS {
+                let params: String = flag
+                    .params()
+                    .map(|(name, optional)| {
+                        format!("{}{}:_files", if optional { "::" } else { ":" }, name)
+                    })
+                    .collect();
+                script.push_str(&format!(
+                    "    '{}[{}]{}' \\\n",
+                    flag.name, flag.help, params
+                ));
+            }
+            script.push_str("    '*:file:_files'\n");
+            script
+        }
+        "fish" => {
+            let mut script = String::new();
+            for flag in FLAGS {
+                let files = match (flag.args, flag.required()) {
+                    ("", _) => "",
+                    (_, 0) => " -F",
+                    _ => " -r -F",
+                };
+                script.push_str(&format!(
+                    "complete -c vee -l {}{} -d '{}'\n",
+                    &flag.name[2..],
+                    files,
+                    flag.help,
+                ));
+            }
+            script.push_str(&format!(
+                concat!(
+                    "complete -c vee -n __fish_use_subcommand -a completions",
+                    " -d 'Print a completion script'\n",
+                    "complete -c vee -n '__fish_seen_subcommand_from completions' -x -a '{}'\n",
+                ),
+                SHELLS.join(" "),
+            ));
+            script
+        }
+        _ => bail!("Unknown shell: {} (try {})", shell, SHELLS.join(", ")),
+    })
+}
+
+pub fn usage() -> String {
+    let mut usage = concat!(
+        "Usage: vee [FLAGS] [+LINE] [FILE]\n",
+        "       vee completions SHELL\n",
+        "\n",
+        "Without a file, opens the files open last time in this directory.\n",
+    )
+    .to_string();
+    for flag in FLAGS {
+        let name = format!("{} {}", flag.name, flag.args);
+        usage.push_str(&format!("\n  {:<22}{}", name, flag.help));
+    }
+    usage
+}
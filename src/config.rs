@@ -0,0 +1,359 @@
+use {
+    anyhow::{anyhow, bail, Context, Result},
+    std::{collections::HashMap, env, fs, io, path::PathBuf},
+    unicode_normalization::UnicodeNormalization,
+    unicode_width::{UnicodeWidthChar, UnicodeWidthStr},
+};
+
+#[derive(Clone, Copy)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+}
+
+impl Normalization {
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            Normalization::Nfc => s.nfc().collect(),
+            Normalization::Nfd => s.nfd().collect(),
+        }
+    }
+}
+
+// East Asian ambiguous-width characters are drawn one or two columns wide depending on the
+// terminal, so this needs to match the terminal for the cursor and highlights to line up.
+#[derive(Clone, Copy)]
+pub enum AmbiguousWidth {
+    Narrow,
+    Wide,
+}
+
+impl AmbiguousWidth {
+    pub fn char_width(self, c: char) -> usize {
+        match self {
+            AmbiguousWidth::Narrow => c.width(),
+            AmbiguousWidth::Wide => c.width_cjk(),
+        }
+        .unwrap_or(0)
+    }
+
+    pub fn str_width(self, s: &str) -> usize {
+        match self {
+            AmbiguousWidth::Narrow => s.width(),
+            AmbiguousWidth::Wide => s.width_cjk(),
+        }
+    }
+}
+
+// Whether searches match case. Smart ignores case unless the pattern has an uppercase letter in
+// it, so typing one is enough to ask for an exact match.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Case {
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl Case {
+    pub fn ignored(self, pattern: &str) -> bool {
+        match self {
+            Case::Smart => !pattern.chars().any(char::is_uppercase),
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Case::Smart => Case::Sensitive,
+            Case::Sensitive => Case::Insensitive,
+            Case::Insensitive => Case::Smart,
+        }
+    }
+
+    // what applies to pattern, for showing alongside it
+    pub fn describe(self, pattern: &str) -> String {
+        let effect = if self.ignored(pattern) {
+            "ignoring case"
+        } else {
+            "matching case"
+        };
+        match self {
+            Case::Smart => format!("smart, {}", effect),
+            _ => effect.to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Config {
+    // files bigger than this (in bytes or lines) are opened with expensive features disabled
+    pub max_file_size: u64,
+    pub max_file_lines: usize,
+    // the form to normalize to on save, and the default for the normalize command
+    pub normalization: Option<Normalization>,
+    pub ambiguous_width: AmbiguousWidth,
+    // carry the previous line's leading whitespace over to new lines
+    pub auto_indent: bool,
+    // let the cursor go past the end of a line, padding it with spaces when there's an edit
+    pub virtual_edit: bool,
+    // what indent and dedent add and remove: indent_width spaces, or a tab
+    pub indent_width: usize,
+    pub indent_tabs: bool,
+    // word characters by filetype, in place of the language's, set with `word_chars:rs = ...`
+    pub word_chars: HashMap<String, String>,
+    pub sign_column: bool,
+    // false for attributes like reverse video and bold in place of colours, for monochrome
+    // terminals, set by NO_COLOR or --no-color
+    pub color: bool,
+    // copy the file to file~ before each save
+    pub backup: bool,
+    // keep unsaved changes somewhere they can be recovered from after a crash, see recovery.rs
+    pub recovery: bool,
+    // save files with unsaved changes whenever there's a pause in typing
+    pub autosave: bool,
+    // whether lines end in \r\n in files that don't have any line endings yet to go by
+    pub crlf: bool,
+    // a line down the text just after this many columns, drawn where lines are shorter
+    pub ruler: Option<usize>,
+    pub search_case: Case,
+    // an overview of the whole file on the right of the text
+    pub minimap: bool,
+    // the width of the text column in zen mode
+    pub zen_width: usize,
+    // the lcov report the coverage command reads, relative to where the editor was started
+    pub coverage_report: String,
+    // lines shown either side of each grep result
+    pub grep_context: usize,
+    // how many yanks and deletes to remember for cycling through after a paste
+    pub kill_ring_size: usize,
+    // for screen readers: nothing drawn just for decoration, and announcements of what's
+    // changed after each key, see announce.rs
+    pub accessible: bool,
+    // a file or fifo for announcements to go to, rather than the message line
+    pub announce: Option<String>,
+    // check that the cursor and friends are valid after every key, logging what went wrong
+    pub check_invariants: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_file_size: 10 * 1024 * 1024,
+            max_file_lines: 100_000,
+            normalization: None,
+            ambiguous_width: AmbiguousWidth::Narrow,
+            auto_indent: true,
+            virtual_edit: false,
+            indent_width: 4,
+            indent_tabs: false,
+            word_chars: HashMap::new(),
+            sign_column: true,
+            // https://no-color.org: set to anything but the empty string
+            color: env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            backup: false,
+            recovery: false,
+            autosave: false,
+            crlf: cfg!(windows),
+            ruler: None,
+            search_case: Case::Smart,
+            minimap: false,
+            zen_width: 80,
+            coverage_report: "lcov.info".to_string(),
+            grep_context: 1,
+            kill_ring_size: 32,
+            accessible: false,
+            announce: None,
+            check_invariants: false,
+        }
+    }
+}
+
+// HOME isn't usually set on Windows, but USERPROFILE is the same thing
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE").filter(|_| cfg!(windows)))
+        .map(PathBuf::from)
+}
+
+// The vee directory in a base directory: $XDG_..._HOME wherever it's set, otherwise where the
+// platform puts such things, a Windows known folder or somewhere under the home directory.
+fn dir(xdg: &str, windows: &str, home_relative: &[&str]) -> Option<PathBuf> {
+    // the spec says to ignore relative paths
+    let base = match env::var_os(xdg).map(PathBuf::from) {
+        Some(base) if base.is_absolute() => base,
+        _ => match env::var_os(windows).filter(|_| cfg!(windows)) {
+            Some(base) => PathBuf::from(base),
+            None => {
+                let mut base = home()?;
+                base.extend(home_relative);
+                base
+            }
+        },
+    };
+    Some(base.join("vee"))
+}
+
+// for configuration, which vee only reads
+pub fn path(name: &str) -> Option<PathBuf> {
+    let home_relative: &[&str] = if cfg!(target_os = "macos") {
+        &["Library", "Application Support"]
+    } else {
+        &[".config"]
+    };
+    dir("XDG_CONFIG_HOME", "APPDATA", home_relative).map(|dir| dir.join(name))
+}
+
+// for files vee writes itself and reads back next time, like histories and the log
+pub fn state_path(name: &str) -> Option<PathBuf> {
+    let home_relative: &[&str] = if cfg!(target_os = "macos") {
+        &["Library", "Application Support"]
+    } else {
+        &[".local", "state"]
+    };
+    dir("XDG_STATE_HOME", "LOCALAPPDATA", home_relative).map(|dir| dir.join(name))
+}
+
+// for files that are no use once vee exits, like sockets
+pub fn cache_path(name: &str) -> Option<PathBuf> {
+    let home_relative: &[&str] = if cfg!(target_os = "macos") {
+        &["Library", "Caches"]
+    } else {
+        &[".cache"]
+    };
+    dir("XDG_CACHE_HOME", "LOCALAPPDATA", home_relative).map(|dir| dir.join(name))
+}
+
+impl Config {
+    // Reads `key = value` lines from config in the config directory, see path. Blank lines and
+    // lines starting with `#` are ignored. A missing file just means the defaults.
+    pub fn load() -> Result<Self> {
+        let mut config = Config::default();
+        let path = match path("config") {
+            Some(path) => path,
+            None => return Ok(config),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(config),
+            Err(err) => return Err(err.into()),
+        };
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            config
+                .set(line)
+                .with_context(|| format!("{}:{}", path.display(), i + 1))?;
+        }
+        Ok(config)
+    }
+
+    // turns off the decorations, which can still be turned back on one by one afterwards
+    pub fn make_accessible(&mut self) {
+        self.accessible = true;
+        self.sign_column = false;
+        self.minimap = false;
+        self.ruler = None;
+    }
+
+    pub fn indent(&self) -> String {
+        if self.indent_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.indent_width)
+        }
+    }
+
+    fn set(&mut self, line: &str) -> Result<()> {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(anyhow!("Expected `key = value`"))?;
+        let value = value.trim();
+        match key.trim() {
+            "max_file_size" => self.max_file_size = value.parse()?,
+            "max_file_lines" => self.max_file_lines = value.parse()?,
+            "normalization" => {
+                self.normalization = match value {
+                    "nfc" => Some(Normalization::Nfc),
+                    "nfd" => Some(Normalization::Nfd),
+                    "none" => None,
+                    _ => bail!("Expected nfc, nfd, or none"),
+                }
+            }
+            "ambiguous_width" => {
+                self.ambiguous_width = match value {
+                    "narrow" => AmbiguousWidth::Narrow,
+                    "wide" => AmbiguousWidth::Wide,
+                    _ => bail!("Expected narrow or wide"),
+                }
+            }
+            "auto_indent" => self.auto_indent = value.parse()?,
+            "virtual_edit" => self.virtual_edit = value.parse()?,
+            "indent_width" => {
+                self.indent_width = value.parse()?;
+                if self.indent_width == 0 {
+                    bail!("Expected at least 1");
+                }
+            }
+            "indent_tabs" => self.indent_tabs = value.parse()?,
+            "sign_column" => self.sign_column = value.parse()?,
+            "color" => self.color = value.parse()?,
+            "backup" => self.backup = value.parse()?,
+            "recovery" => self.recovery = value.parse()?,
+            "autosave" => self.autosave = value.parse()?,
+            "crlf" => self.crlf = value.parse()?,
+            "ruler" => {
+                self.ruler = match value {
+                    "none" => None,
+                    _ => Some(value.parse()?),
+                }
+            }
+            "search_case" => {
+                self.search_case = match value {
+                    "smart" => Case::Smart,
+                    "sensitive" => Case::Sensitive,
+                    "insensitive" => Case::Insensitive,
+                    _ => bail!("Expected smart, sensitive, or insensitive"),
+                }
+            }
+            "minimap" => self.minimap = value.parse()?,
+            "zen_width" => {
+                self.zen_width = value.parse()?;
+                if self.zen_width == 0 {
+                    bail!("Expected at least 1");
+                }
+            }
+            "coverage_report" => self.coverage_report = value.to_string(),
+            "grep_context" => self.grep_context = value.parse()?,
+            "kill_ring_size" => {
+                self.kill_ring_size = value.parse()?;
+                if self.kill_ring_size == 0 {
+                    bail!("Expected at least 1");
+                }
+            }
+            "accessible" => {
+                self.accessible = value.parse()?;
+                if self.accessible {
+                    self.make_accessible();
+                }
+            }
+            "announce" => {
+                self.announce = match value {
+                    "none" => None,
+                    _ => Some(value.to_string()),
+                }
+            }
+            "check_invariants" => self.check_invariants = value.parse()?,
+            key => match key.strip_prefix("word_chars:") {
+                Some(filetype) => {
+                    self.word_chars
+                        .insert(filetype.to_string(), value.to_string());
+                }
+                None => bail!("Unknown config key: {}", key),
+            },
+        }
+        Ok(())
+    }
+}
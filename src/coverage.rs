@@ -0,0 +1,181 @@
+use {
+    crate::{
+        idle::Task,
+        mark::MarkId,
+        state::{Point, State},
+    },
+    anyhow::Result,
+    std::{
+        collections::BTreeMap,
+        fs,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+// Which lines a test run covered, from an lcov report like the ones cargo llvm-cov --lcov
+// writes. Each line is held by a mark, so the tint stays with it as the text is edited, until
+// the report changes and is read again.
+pub struct Coverage {
+    report: PathBuf,
+    modified: Option<SystemTime>,
+    lines: Vec<(MarkId, bool)>,
+}
+
+// What reads a report: lcov, or nothing in builds without the coverage feature, where asking
+// for coverage says so.
+pub trait Report: Sync {
+    // The lines of file in report, from 0, and whether each was run.
+    fn lines(&self, report: &str, file: &str) -> Result<BTreeMap<usize, bool>>;
+}
+
+pub fn reader() -> &'static dyn Report {
+    #[cfg(feature = "coverage")]
+    {
+        &lcov::Lcov
+    }
+    #[cfg(not(feature = "coverage"))]
+    {
+        &NoReport
+    }
+}
+
+#[cfg(not(feature = "coverage"))]
+struct NoReport;
+
+#[cfg(not(feature = "coverage"))]
+impl Report for NoReport {
+    fn lines(&self, _: &str, _: &str) -> Result<BTreeMap<usize, bool>> {
+        anyhow::bail!("Coverage isn't part of this build, see the coverage feature")
+    }
+}
+
+#[cfg(feature = "coverage")]
+mod lcov {
+    use {
+        super::Report,
+        crate::annotate,
+        anyhow::{bail, Result},
+        std::collections::BTreeMap,
+    };
+
+    pub struct Lcov;
+
+    impl Report for Lcov {
+        fn lines(&self, report: &str, file: &str) -> Result<BTreeMap<usize, bool>> {
+            parse(report, file)
+        }
+    }
+
+    // The lines of file in report, from 0, and whether each was run. A line can appear in more
+    // than one record (once per instantiation of a generic function, say), and counts as run if it
+    // was run in any of them.
+    pub fn parse(report: &str, file: &str) -> Result<BTreeMap<usize, bool>> {
+        let mut lines = BTreeMap::new();
+        let mut found = false;
+        let mut in_file = false;
+        for line in report.lines() {
+            if let Some(path) = line.strip_prefix("SF:") {
+                in_file = annotate::same_file(path, file);
+                found |= in_file;
+            } else if line == "end_of_record" {
+                in_file = false;
+            } else if let Some(record) = line.strip_prefix("DA:").filter(|_| in_file) {
+                let mut fields = record.split(',');
+                if let (Some(Ok(n)), Some(Ok(hits))) = (
+                    fields.next().map(str::parse::<usize>),
+                    fields.next().map(str::parse::<u64>),
+                ) {
+                    if n > 0 {
+                        *lines.entry(n - 1).or_insert(false) |= hits > 0;
+                    }
+                }
+            }
+        }
+        if !found {
+            bail!("No coverage for {}", file);
+        }
+        Ok(lines)
+    }
+}
+
+fn modified(report: &Path) -> Option<SystemTime> {
+    fs::metadata(report).and_then(|meta| meta.modified()).ok()
+}
+
+impl State {
+    // Shows the coverage in the report named by the coverage_report config key, or stops
+    // showing it.
+    pub fn toggle_coverage(&mut self) {
+        if let Some(coverage) = self.coverage.take() {
+            self.clear_coverage(coverage);
+            return;
+        }
+        let report = PathBuf::from(&self.config.coverage_report);
+        match self.load_coverage(report) {
+            Ok(coverage) => {
+                self.coverage = Some(coverage);
+                self.idle.schedule(Task::Coverage);
+            }
+            Err(err) => self.message = Some(format!("Couldn't load coverage: {}", err)),
+        }
+    }
+
+    fn load_coverage(&mut self, report: PathBuf) -> Result<Coverage> {
+        let modified = modified(&report);
+        let lines = reader().lines(&fs::read_to_string(&report)?, &self.file)?;
+        let len = self.text.len();
+        let lines = lines
+            .into_iter()
+            .filter(|&(y, _)| y < len)
+            .map(|(y, covered)| (self.marks.add(Point { x: 0, y }), covered))
+            .collect();
+        Ok(Coverage {
+            report,
+            modified,
+            lines,
+        })
+    }
+
+    fn clear_coverage(&mut self, coverage: Coverage) {
+        for (id, _) in coverage.lines {
+            self.marks.remove(id);
+        }
+    }
+
+    // Reads the report again if it's changed since it was last read, and checks again later.
+    // Returns whether it was read.
+    pub fn refresh_coverage(&mut self) -> bool {
+        let coverage = match self.coverage.take() {
+            Some(coverage) => coverage,
+            None => return false,
+        };
+        self.idle.schedule(Task::Coverage);
+        // a missing report is most likely being written again, so wait for it
+        let modified = modified(&coverage.report);
+        if modified.is_none() || modified == coverage.modified {
+            self.coverage = Some(coverage);
+            return false;
+        }
+        let report = coverage.report.clone();
+        self.clear_coverage(coverage);
+        match self.load_coverage(report) {
+            Ok(coverage) => self.coverage = Some(coverage),
+            Err(err) => {
+                self.idle.cancel(Task::Coverage);
+                self.message = Some(format!("Couldn't reload coverage: {}", err));
+            }
+        }
+        true
+    }
+
+    // whether line y was run, if the report says either way
+    pub fn covered(&self, y: usize) -> Option<bool> {
+        let coverage = self.coverage.as_ref()?;
+        coverage
+            .lines
+            .iter()
+            .find(|&&(id, _)| self.marks.get(id).is_some_and(|point| point.y == y))
+            .map(|&(_, covered)| covered)
+    }
+}
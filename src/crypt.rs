@@ -0,0 +1,257 @@
+use {
+    crate::{config::Config, keymap::Keymap, screen, state::State},
+    anyhow::{anyhow, Result},
+    std::{fs, io, path::Path},
+};
+
+const ARMOR: &[u8] = b"-----BEGIN PGP MESSAGE-----";
+
+// Files encrypted with a passphrase by gpg are decrypted into the buffer and encrypted again on
+// save. Both go through gpg's stdin and stdout, so the plain text is never written to disk.
+pub struct Encryption {
+    passphrase: String,
+    // ASCII armored rather than binary, to match the file
+    armor: bool,
+}
+
+impl Encryption {
+    pub fn encrypt(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        cipher().encrypt(&self.passphrase, self.armor, plain)
+    }
+
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        cipher().decrypt(&self.passphrase, bytes)
+    }
+}
+
+// What encrypting and decrypting go through: gpg, or nothing in builds without the crypt
+// feature, which refuse to open encrypted files rather than show them as they are on disk.
+pub trait Cipher: Sync {
+    fn encrypt(&self, passphrase: &str, armor: bool, plain: &[u8]) -> Result<Vec<u8>>;
+    fn decrypt(&self, passphrase: &str, bytes: &[u8]) -> Result<Vec<u8>>;
+    // before asking for a passphrase that would be no use
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn cipher() -> &'static dyn Cipher {
+    #[cfg(feature = "crypt")]
+    {
+        &gpg::Gpg
+    }
+    #[cfg(not(feature = "crypt"))]
+    {
+        &NoCipher
+    }
+}
+
+#[cfg(not(feature = "crypt"))]
+struct NoCipher;
+
+#[cfg(not(feature = "crypt"))]
+impl Cipher for NoCipher {
+    fn encrypt(&self, _: &str, _: bool, _: &[u8]) -> Result<Vec<u8>> {
+        self.check().map(|()| Vec::new())
+    }
+
+    fn decrypt(&self, _: &str, _: &[u8]) -> Result<Vec<u8>> {
+        self.check().map(|()| Vec::new())
+    }
+
+    fn check(&self) -> Result<()> {
+        anyhow::bail!("Encryption isn't part of this build, see the crypt feature")
+    }
+}
+
+// Armored, or binary starting with a symmetric key packet (tag 3). The first byte alone isn't
+// enough, since 0xc3 starts UTF-8 text like "é" too, so the packet header and the start of the
+// packet have to make sense as well.
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(ARMOR) || symmetric_key_packet(bytes).is_some_and(is_symmetric_key)
+}
+
+// The body of the packet bytes start with, if they start with a symmetric key packet header, in
+// the old format (0x8c to 0x8e, by length of length) or the new (0xc3), see RFC 4880 4.2.
+fn symmetric_key_packet(bytes: &[u8]) -> Option<&[u8]> {
+    let (len, rest) = match bytes {
+        [0x8c, len, rest @ ..] => (*len as usize, rest),
+        [0x8d, a, b, rest @ ..] => (u16::from_be_bytes([*a, *b]) as usize, rest),
+        [0x8e, a, b, c, d, rest @ ..] | [0xc3, 0xff, a, b, c, d, rest @ ..] => {
+            (u32::from_be_bytes([*a, *b, *c, *d]) as usize, rest)
+        }
+        [0xc3, len @ 0..=191, rest @ ..] => (*len as usize, rest),
+        [0xc3, first @ 192..=223, second, rest @ ..] => (
+            ((*first as usize - 192) << 8) + *second as usize + 192,
+            rest,
+        ),
+        // partial lengths aren't allowed for this packet
+        _ => return None,
+    };
+    rest.get(..len)
+}
+
+// A version we know, a symmetric algorithm gpg has heard of, and a string-to-key specifier
+// that's simple, salted, iterated and salted, or Argon2, see RFC 9580 5.3.
+fn is_symmetric_key(body: &[u8]) -> bool {
+    let (algorithm, s2k) = match body {
+        [4, algorithm, s2k, ..] => (algorithm, s2k),
+        [5, algorithm, _aead, s2k, ..] => (algorithm, s2k),
+        [6, _count, algorithm, _aead, _len, s2k, ..] => (algorithm, s2k),
+        _ => return false,
+    };
+    matches!(algorithm, 1..=13) && matches!(s2k, 0 | 1 | 3 | 4)
+}
+
+#[cfg(feature = "crypt")]
+mod gpg {
+    use {
+        super::Cipher,
+        anyhow::{anyhow, bail, Context, Result},
+        std::{
+            io::Write,
+            process::{Command, Stdio},
+            thread,
+        },
+    };
+
+    pub struct Gpg;
+
+    impl Cipher for Gpg {
+        fn encrypt(&self, passphrase: &str, armor: bool, plain: &[u8]) -> Result<Vec<u8>> {
+            let mut args = vec!["--symmetric"];
+            if armor {
+                args.push("--armor");
+            }
+            gpg(&args, passphrase, plain)
+        }
+
+        fn decrypt(&self, passphrase: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+            gpg(&["--decrypt"], passphrase, bytes)
+        }
+    }
+
+    // Runs gpg with the passphrase on the first line of its input and the data after it.
+    fn gpg(args: &[&str], passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let mut child = Command::new("gpg")
+            .args(["--batch", "--quiet", "--yes", "--no-symkey-cache"])
+            .args(["--pinentry-mode", "loopback", "--passphrase-fd", "0"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Couldn't run gpg")?;
+        let mut stdin = child.stdin.take().ok_or(anyhow!("No stdin for gpg"))?;
+        let input = [passphrase.as_bytes(), b"\n", data].concat();
+        // from another thread, so that gpg can't fill up stdout while we're still writing
+        let writer = thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // gpg gives up without reading everything when the passphrase is wrong, so a broken pipe
+        // here says nothing the exit status doesn't
+        let _ = writer.join();
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            bail!("{}", err.trim().trim_start_matches("gpg: "));
+        }
+        Ok(output.stdout)
+    }
+}
+
+// asks for a new passphrase twice, until both match
+fn new_passphrase() -> Result<String> {
+    let mut prompt = "New passphrase: ".to_string();
+    loop {
+        let first = screen::read_secret(&prompt)?.ok_or(anyhow!("Cancelled"))?;
+        let second = screen::read_secret("Repeat passphrase: ")?.ok_or(anyhow!("Cancelled"))?;
+        if first == second {
+            return Ok(first);
+        }
+        prompt = "Passphrases didn't match, new passphrase: ".to_string();
+    }
+}
+
+impl State {
+    // Opens file, asking for the passphrase if it's encrypted. With encrypt, a file that isn't
+    // encrypted yet (or doesn't exist yet) is encrypted with a new passphrase from the first save.
+    pub fn open(file: String, encrypt: bool, config: Config, keymap: Keymap) -> Result<Self> {
+        let mut new = false;
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            // a file that doesn't exist yet is created on save
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                new = true;
+                Vec::new()
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if is_encrypted(&bytes) {
+            cipher().check()?;
+            let mut prompt = format!("Passphrase for {}: ", file);
+            loop {
+                let passphrase = screen::read_secret(&prompt)?.ok_or(anyhow!("Cancelled"))?;
+                match cipher().decrypt(&passphrase, &bytes) {
+                    Ok(plain) => {
+                        let mut s = Self::from_bytes(file, &plain, config, keymap);
+                        s.encryption = Some(Encryption {
+                            passphrase,
+                            armor: bytes.starts_with(ARMOR),
+                        });
+                        return Ok(s);
+                    }
+                    Err(err) => prompt = format!("{}, try again: ", err),
+                }
+            }
+        } else if encrypt {
+            cipher().check()?;
+            let passphrase = new_passphrase()?;
+            let ext = Path::new(&file).extension().and_then(|ext| ext.to_str());
+            let armor = ext == Some("asc");
+            let mut s = Self::from_bytes(file, &bytes, config, keymap);
+            s.encryption = Some(Encryption { passphrase, armor });
+            s.new = new;
+            s.message = Some("Will be encrypted on save".to_string());
+            Ok(s)
+        } else {
+            let mut s = Self::from_bytes(file, &bytes, config, keymap);
+            if new {
+                s.new = true;
+                s.message = Some("New file".to_string());
+            }
+            Ok(s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_gpg_output() {
+        // from gpg --symmetric, old format: AES256, iterated and salted SHA256
+        let binary = b"\x8c\x0d\x04\x09\x03\x02\x69\xf0\x7c\x3a\x60\x03\xa5\x67\xff\xd2\x3d";
+        assert!(is_encrypted(binary));
+        // the same in the new format
+        assert!(is_encrypted(
+            b"\xc3\x0d\x04\x09\x03\x02\x69\xf0\x7c\x3a\x60\x03\xa5\x67\xff"
+        ));
+        assert!(is_encrypted(b"-----BEGIN PGP MESSAGE-----\n\njA0ECQMC\n"));
+    }
+
+    #[test]
+    fn leaves_text_alone() {
+        for text in [
+            "",
+            "éclair\n",
+            "Übung macht den Meister\n",
+            "ß\n",
+            "Ã\n",
+            "ÿ",
+        ] {
+            assert!(!is_encrypted(text.as_bytes()), "{:?}", text);
+        }
+        // a header that claims more than there is
+        assert!(!is_encrypted(b"\x8c\x0d\x04\x09\x03"));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::state::{Mode, State};
+
+// a third answer, typed as its key, with a name for it to be shown as
+pub type Other = (char, &'static str, fn(&mut State));
+
+// A yes/no/cancel question drawn over the text. Cancelling leaves everything as it was, so
+// features only need to say what yes and no mean.
+pub struct Dialog {
+    pub question: String,
+    yes: fn(&mut State),
+    no: fn(&mut State),
+    pub other: Option<Other>,
+}
+
+impl State {
+    pub fn confirm(&mut self, question: String, yes: fn(&mut State), no: fn(&mut State)) {
+        self.dialog = Some(Dialog {
+            question,
+            yes,
+            no,
+            other: None,
+        });
+        self.mode = Mode::Dialog;
+    }
+
+    pub fn confirm_or(
+        &mut self,
+        question: String,
+        yes: fn(&mut State),
+        no: fn(&mut State),
+        other: Other,
+    ) {
+        self.confirm(question, yes, no);
+        if let Some(dialog) = &mut self.dialog {
+            dialog.other = Some(other);
+        }
+    }
+
+    // anything but the other answer's key is ignored
+    pub fn answer_other(&mut self, c: char) {
+        match self.dialog.as_ref().and_then(|dialog| dialog.other) {
+            Some((key, _, other)) if key == c => {
+                self.dialog = None;
+                self.mode = Mode::Normal;
+                other(self);
+            }
+            _ => (),
+        }
+    }
+
+    // None cancels
+    pub fn answer(&mut self, answer: Option<bool>) {
+        if let Some(dialog) = self.dialog.take() {
+            self.mode = Mode::Normal;
+            match answer {
+                Some(true) => (dialog.yes)(self),
+                Some(false) => (dialog.no)(self),
+                None => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use {
+    crate::{
+        line::{self, Endings},
+        pager::Target,
+        state::{Point, State},
+    },
+    std::{
+        fs::{self, File, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        process,
+        time::SystemTime,
+    },
+};
+
+// lines either side of a change in the diff
+const CONTEXT: usize = 2;
+
+// past this many lines squared, changed lines aren't matched up, just removed and added
+const MAX_DIFF: usize = 4_000_000;
+
+// when the file was modified, and how long it was, so that a change inside the mtime's
+// resolution is likely to show up anyway
+pub type Stamp = (SystemTime, u64);
+
+pub fn stamp(file: &str) -> Option<Stamp> {
+    let metadata = fs::metadata(file).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Writes to a file alongside and renames it over the original, so that a crash or a full disk
+// leaves either the old contents or the new, never a mix. The original's permissions and owner
+// are kept, and a symlink is followed and its target replaced. Where the new file can't be made
+// the same as the original, because it's a hard link or owned by someone else, it's written in
+// place instead, and so is a file in a directory where nothing new can be made.
+pub fn write(file: &str, bytes: &[u8]) -> io::Result<()> {
+    let path = fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    // which renaming over would get around, even for root
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file is read-only",
+        ));
+    }
+    // opened first, to check we're allowed to write to it at all
+    let original = match OpenOptions::new().write(true).open(&path) {
+        Ok(original) => Some(original),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{}.{}.vee", name, process::id()));
+    let written = match OpenOptions::new().write(true).create_new(true).open(&temp) {
+        Ok(file) => {
+            let written = write_temp(file, original.as_ref(), bytes).and_then(|same| {
+                if same {
+                    fs::rename(&temp, &path).map(|()| true)
+                } else {
+                    Ok(false)
+                }
+            });
+            if !matches!(written, Ok(true)) {
+                let _ = fs::remove_file(&temp);
+            }
+            written
+        }
+        // read-only, most likely, which leaves the original to write to
+        Err(_) if original.is_some() => Ok(false),
+        Err(err) => Err(err),
+    };
+    match (written?, original) {
+        (false, Some(original)) => write_in_place(original, bytes),
+        _ => {
+            // so that the rename itself survives a crash, where that's possible
+            if let Ok(dir) = fs::File::open(dir) {
+                let _ = dir.sync_all();
+            }
+            Ok(())
+        }
+    }
+}
+
+// Writes bytes to the temp file, with the original's permissions and owner, and says whether it
+// could be made the same as the original. It's left empty if not.
+fn write_temp(mut file: File, original: Option<&File>, bytes: &[u8]) -> io::Result<bool> {
+    if let Some(original) = original {
+        let metadata = original.metadata()?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+            // the other links would keep the old contents, and only root can give a file away
+            if metadata.nlink() > 1
+                || fchown(&file, Some(metadata.uid()), Some(metadata.gid())).is_err()
+            {
+                return Ok(false);
+            }
+        }
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+fn write_in_place(mut file: File, bytes: &[u8]) -> io::Result<()> {
+    file.set_len(0)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+// the file as it is on disk, decoded the same way as when it was opened
+struct Disk {
+    lines: Vec<String>,
+    // the placeholders in each line, see line::decode
+    invalid: Vec<Vec<(usize, Vec<u8>)>>,
+    endings: Endings,
+}
+
+// The file is stamped whenever it's read or written, and saving checks the stamp is still the
+// same, asking what to do instead of clobbering another program's changes if it isn't.
+impl State {
+    // false for a file that's gone altogether, since there's nothing to clobber
+    pub fn changed_on_disk(&self) -> bool {
+        match (self.stamp, stamp(&self.file)) {
+            (Some(ours), Some(theirs)) => !self.new && ours != theirs,
+            _ => false,
+        }
+    }
+
+    pub fn ask_changed_on_disk(&mut self) {
+        self.confirm_or(
+            format!("{} has changed on disk. Overwrite it?", self.file),
+            State::overwrite,
+            State::reload,
+            ('d', "diff", State::diff_disk),
+        );
+    }
+
+    pub fn overwrite(&mut self) {
+        self.stamp = stamp(&self.file);
+        self.save();
+    }
+
+    fn read_disk(&mut self) -> Option<Disk> {
+        let bytes = fs::read(&self.file)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| match &self.encryption {
+                Some(encryption) => encryption.decrypt(&bytes),
+                None => Ok(bytes),
+            });
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.message = Some(format!("Error reading {}: {}", self.file, err));
+                return None;
+            }
+        };
+        let (lines, endings) = line::decode_file(&bytes);
+        let (lines, invalid) = lines.into_iter().unzip();
+        Some(Disk {
+            lines,
+            invalid,
+            endings,
+        })
+    }
+
+    // Replaces the text with what's on disk, as an edit like any other so that it can be
+    // undone. The cursor stays on the same line, wherever that's moved to, and in the same
+    // column.
+    pub fn reload(&mut self) {
+        if self.new {
+            self.message = Some(format!("{} isn't on disk yet", self.file));
+            return;
+        }
+        let stamp = stamp(&self.file);
+        let disk = match self.read_disk() {
+            Some(disk) => disk,
+            None => return,
+        };
+        let text: Vec<&str> = self.text.iter().map(|line| line.0.as_str()).collect();
+        let y = line_in_old(&diff(&disk.lines, &text), self.cursor.y);
+        let x = self.cursor.x;
+        self.anchor = None;
+        // undone on its own, rather than along with whatever was typed just before
+        self.break_undo();
+        self.transform(self.start_of_file(), self.end_of_file(), |_| {
+            disk.lines.join("\n")
+        });
+        self.break_undo();
+        // the text is the same as on disk now, line for line
+        for (line, invalid) in self.text.iter_mut().zip(disk.invalid) {
+            line.1.invalid = invalid;
+        }
+        let point = self.clamp(Point { x, y });
+        self.move_cursor(point);
+        self.stamp = stamp;
+        self.eol = disk.endings.eol;
+        self.crlf = disk.endings.crlf.unwrap_or(self.crlf);
+        self.dirty = false;
+        self.message = Some(format!("Reloaded {}", self.file));
+    }
+
+    // Shows what's changed between the file on disk and the text in the pager, with lines only
+    // on disk marked - and lines only here marked +. Each change is a target, to go to.
+    pub fn diff_disk(&mut self) {
+        let disk = match self.read_disk() {
+            Some(disk) => disk,
+            None => return,
+        };
+        let new: Vec<&str> = self.text.iter().map(|line| line.0.as_str()).collect();
+        let ops = diff(&disk.lines, &new);
+        let changed: Vec<bool> = ops.iter().map(|op| op.0 != ' ').collect();
+        if !changed.contains(&true) {
+            self.message = Some(
+                if disk.endings.eol.unwrap_or(false) == self.ends_in_newline() {
+                    format!("No differences from {} on disk", self.file)
+                } else {
+                    format!("Only the newline at the end of {} differs", self.file)
+                },
+            );
+            return;
+        }
+        let mut lines = Vec::new();
+        let mut targets = Vec::new();
+        let mut shown = false;
+        for (i, (kind, text, y)) in ops.iter().enumerate() {
+            let near = changed[i.saturating_sub(CONTEXT)..usize::min(i + CONTEXT + 1, ops.len())]
+                .contains(&true);
+            if !near {
+                shown = false;
+                continue;
+            }
+            if !shown {
+                let header = format!("@@ line {} @@", y + 1);
+                targets.push(Target {
+                    line: lines.len(),
+                    span: 0..header.len(),
+                    file: self.file.clone(),
+                    point: Point { x: 0, y: *y },
+                });
+                lines.push(header);
+                shown = true;
+            }
+            lines.push(format!("{}{}", kind, text));
+        }
+        self.open_pager(format!("{} on disk (-) and here (+)", self.file), lines);
+        if let Some(pager) = &mut self.pager {
+            pager.targets = targets;
+        }
+    }
+}
+
+// Where line y of new is in old. A line only in new is matched up with the line of old in the
+// same place in the change, if it was changed rather than added.
+fn line_in_old(ops: &[(char, &str, usize)], y: usize) -> usize {
+    let mut old_y = 0;
+    // where the change so far started in old, and how many lines of new it has
+    let mut change = (0, 0);
+    for &(kind, _, new_y) in ops {
+        match kind {
+            ' ' if new_y == y => return old_y,
+            ' ' => {
+                old_y += 1;
+                change = (old_y, 0);
+            }
+            '+' if new_y == y => return change.0 + change.1,
+            '+' => change.1 += 1,
+            _ => old_y += 1,
+        }
+    }
+    old_y
+}
+
+// The lines of old and new in order, each marked ' ', '-' or '+' for both, old only or new
+// only, along with the line of new it's at. The longest common subsequence of what's left
+// after the lines they start and end with in common are taken off.
+fn diff<'a>(old: &'a [String], new: &[&'a str]) -> Vec<(char, &'a str, usize)> {
+    let head = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| a.as_str() == **b)
+        .count();
+    let tail = old[head..]
+        .iter()
+        .rev()
+        .zip(new[head..].iter().rev())
+        .take_while(|(a, b)| a.as_str() == **b)
+        .count();
+    let (a, b) = (&old[head..old.len() - tail], &new[head..new.len() - tail]);
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = Vec::new();
+    if a.len() * b.len() <= MAX_DIFF {
+        lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    usize::max(lcs[i + 1][j], lcs[i][j + 1])
+                };
+            }
+        }
+    }
+    let lcs = |i: usize, j: usize| lcs.get(i).map_or(0, |row| row[j]);
+    let mut ops: Vec<(char, &str, usize)> = new[..head]
+        .iter()
+        .enumerate()
+        .map(|(y, line)| (' ', *line, y))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((' ', b[j], head + j));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs(i + 1, j) >= lcs(i, j + 1)) {
+            ops.push(('-', &a[i], head + j));
+            i += 1;
+        } else {
+            ops.push(('+', b[j], head + j));
+            j += 1;
+        }
+    }
+    let start = new.len() - tail;
+    ops.extend(
+        new[start..]
+            .iter()
+            .enumerate()
+            .map(|(y, line)| (' ', *line, start + y)),
+    );
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::env};
+
+    // a fresh directory for each test, since they run at the same time
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("vee-disk-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_str(path: &Path, text: &str) -> io::Result<()> {
+        write(path.to_str().unwrap(), text.as_bytes())
+    }
+
+    #[test]
+    fn creates_and_replaces() {
+        let dir = dir("replace");
+        let file = dir.join("a.txt");
+        write_str(&file, "one\n").unwrap();
+        write_str(&file, "two\n").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "two\n");
+        // and leaves nothing else behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_read_only_files() {
+        let dir = dir("read-only");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        let mut permissions = fs::metadata(&file).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file, permissions).unwrap();
+        assert!(write_str(&file, "two\n").is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_in_read_only_directories() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = dir("read-only-dir");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o500)).unwrap();
+        write_str(&file, "two\n").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "two\n");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        // root can make files in a read-only directory regardless, so this is how it fails for
+        // root, and what's in the way is left alone
+        let temp = dir.join(format!(".a.txt.{}.vee", process::id()));
+        fs::create_dir(&temp).unwrap();
+        write_str(&file, "three\n").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "three\n");
+        assert!(temp.is_dir());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reloads_invalid_bytes() {
+        use crate::{config::Config, keymap::Keymap};
+        let dir = dir("reload");
+        let file = dir.join("a.txt");
+        fs::write(&file, b"a\xff\n").unwrap();
+        let mut s = State::from_bytes(
+            file.to_str().unwrap().to_string(),
+            b"a\xff\n",
+            Config::default(),
+            Keymap::default(),
+        );
+        fs::write(&file, b"a\xff\nb\xfe\r\n").unwrap();
+        s.reload();
+        s.save();
+        assert_eq!(fs::read(&file).unwrap(), b"a\xff\nb\xfe\r\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = dir("permissions");
+        let file = dir.join("a.sh");
+        fs::write(&file, "one\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o700)).unwrap();
+        write_str(&file, "two\n").unwrap();
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_links() {
+        use std::os::unix::fs::symlink;
+        let dir = dir("links");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        let hard = dir.join("hard.txt");
+        fs::hard_link(&file, &hard).unwrap();
+        let soft = dir.join("soft.txt");
+        symlink(&file, &soft).unwrap();
+        write_str(&hard, "two\n").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "two\n");
+        write_str(&soft, "three\n").unwrap();
+        assert_eq!(fs::read_to_string(&hard).unwrap(), "three\n");
+        assert!(fs::symlink_metadata(&soft)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
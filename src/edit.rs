@@ -0,0 +1,85 @@
+use crate::{
+    operator::Operator,
+    state::{Mode, State},
+};
+
+// Edits expressed as data, so that the last one can be replayed at the cursor.
+#[derive(Clone, Copy)]
+pub enum EditOp {
+    Operate(Operator),
+    // the register, how many times over, and whether verbatim
+    Paste(Option<char>, usize, bool),
+    BeginEdit,
+    InsertChar(char),
+    Newline,
+    Backspace,
+    Delete,
+    JoinLines,
+    // true for above
+    OpenLine(bool),
+}
+
+// The edit being recorded, and the last complete one. An edit starts with the first EditOp in
+// normal mode and finishes straight away, or at the end of the insert session it began.
+#[derive(Default)]
+pub struct Recorder {
+    recording: Option<Vec<EditOp>>,
+    last: Vec<EditOp>,
+    replaying: bool,
+}
+
+impl State {
+    pub fn record(&mut self, op: EditOp) {
+        if self.edits.replaying {
+            return;
+        }
+        self.edits.recording.get_or_insert_with(Vec::new).push(op);
+        if self.mode != Mode::Insert {
+            self.finish_recording();
+        }
+        self.edit_selections(op);
+    }
+
+    pub fn finish_recording(&mut self) {
+        if let Some(ops) = self.edits.recording.take() {
+            self.edits.last = ops;
+        }
+    }
+
+    pub fn repeat_edit(&mut self) {
+        let ops = self.edits.last.clone();
+        self.edits.replaying = true;
+        // at every selection, as each op was when it was recorded
+        for op in ops {
+            self.replay(op);
+            self.edit_selections(op);
+        }
+        if self.mode == Mode::Insert {
+            self.end_edit();
+        }
+        self.edits.replaying = false;
+    }
+
+    // does op at the cursor, without recording it
+    pub fn replay(&mut self, op: EditOp) {
+        let replaying = self.edits.replaying;
+        self.edits.replaying = true;
+        match op {
+            EditOp::Operate(op) => self.operate(op),
+            EditOp::Paste(register, count, verbatim) => {
+                self.register = register;
+                self.count = Some(count);
+                self.paste(verbatim);
+                self.count = None;
+            }
+            EditOp::BeginEdit => self.begin_edit(),
+            EditOp::InsertChar(c) => self.insert_char(c),
+            EditOp::Newline => self.newline(),
+            EditOp::Backspace => self.backspace(),
+            EditOp::Delete => self.delete(),
+            EditOp::JoinLines => self.join_lines(),
+            EditOp::OpenLine(above) => self.open_line(above),
+        }
+        self.edits.replaying = replaying;
+    }
+}
@@ -0,0 +1,193 @@
+use {
+    crate::{
+        command,
+        config::Config,
+        keymap::Keymap,
+        state::{Mode, State},
+    },
+    anyhow::{bail, Result},
+};
+
+// `vee --exec SCRIPT FILE` edits FILE without the terminal, for shell scripts and git hooks.
+// The script is a list of steps separated by semicolons or newlines:
+//
+//     sort-lines          any command from the palette
+//     /PATTERN            search, moving to the first match after the cursor, or failing
+//                         if there are none
+//     s/OLD/NEW/          replace every match of OLD, with $1 and so on for capture groups
+//
+// Any character can stand in for the slashes of a replace, and a backslash before one (or a
+// semicolon, in a search) takes it literally. The file is saved afterwards if anything changed.
+// A step that fails stops the script, and nothing is saved.
+enum Step {
+    Command(String),
+    Search(String),
+    Replace(String, String),
+}
+
+// Everything up to the first unescaped end, which is skipped over. Returns whether there was
+// one.
+fn take_until(rest: &mut &str, end: impl Fn(char) -> bool) -> (String, bool) {
+    let mut taken = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some((_, next)) if end(next) => taken.push(next),
+                Some((_, next)) => {
+                    taken.push(c);
+                    taken.push(next);
+                }
+                None => taken.push(c),
+            }
+        } else if end(c) {
+            *rest = &rest[i + c.len_utf8()..];
+            return (taken, true);
+        } else {
+            taken.push(c);
+        }
+    }
+    *rest = "";
+    (taken, false)
+}
+
+// The delimiter, if rest starts with a replace, which can't be mistaken for a command name
+// starting with s.
+fn replace_delimiter(rest: &str) -> Option<char> {
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next()) {
+        (Some('s'), Some(c))
+            if !c.is_alphanumeric() && !c.is_whitespace() && c != '-' && c != ';' =>
+        {
+            Some(c)
+        }
+        _ => None,
+    }
+}
+
+fn parse(script: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut rest = script;
+    let separator = |c| c == ';' || c == '\n';
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ';');
+        if rest.is_empty() {
+            return Ok(steps);
+        }
+        if let Some(search) = rest.strip_prefix('/') {
+            rest = search;
+            steps.push(Step::Search(take_until(&mut rest, separator).0));
+        } else if let Some(delimiter) = replace_delimiter(rest) {
+            rest = &rest[1 + delimiter.len_utf8()..];
+            let (old, _) = take_until(&mut rest, |c| c == delimiter);
+            let (new, closed) = take_until(&mut rest, |c| c == delimiter);
+            if !closed {
+                bail!(
+                    "Expected {} after s{}{}{}{}",
+                    delimiter,
+                    delimiter,
+                    old,
+                    delimiter,
+                    new
+                );
+            }
+            steps.push(Step::Replace(old, new));
+        } else {
+            let (name, _) = take_until(&mut rest, separator);
+            steps.push(Step::Command(name.trim().to_string()));
+        }
+    }
+}
+
+pub fn run(script: &str, file: String, config: Config, keymap: Keymap) -> Result<()> {
+    let steps = parse(script)?;
+    let mut s = State::open(file, false, config, keymap)?;
+    s.resize((80, 24));
+    if s.mode != Mode::Normal {
+        bail!("{} can't be opened without the terminal", s.file);
+    }
+    for step in steps {
+        s.message = None;
+        let name = match step {
+            Step::Command(name) => {
+                let command = match command::lookup(&name) {
+                    Some(command) => command,
+                    None => bail!("Unknown command: {}", name),
+                };
+                s.run(command);
+                name
+            }
+            Step::Search(pattern) => {
+                s.search = Some(s.compile_search(&pattern));
+                s.reannotate();
+                s.move_next_match();
+                // a script that goes on from the wrong place would edit the wrong thing
+                let found = s.text.iter().any(|line| !line.1.matches.is_empty());
+                if matches!(s.search, Some(Ok(_))) && !found {
+                    bail!("/{}: no matches", pattern);
+                }
+                format!("/{}", pattern)
+            }
+            Step::Replace(old, new) => {
+                s.search = Some(s.compile_search(&old));
+                s.reannotate();
+                s.anchor = None;
+                s.move_cursor(s.start_of_file());
+                s.open_replace(false);
+                for c in new.chars() {
+                    s.replace_insert(c);
+                }
+                s.start_replace();
+                s.replace_all();
+                format!("s/{}/{}/", old, new)
+            }
+        };
+        if let Some(Err(err)) = &s.search {
+            bail!("{}: {}", name, err);
+        }
+        // anything waiting on an answer, or for more typing, needs the terminal
+        if let Some(dialog) = &s.dialog {
+            bail!("{}: {}", name, dialog.question);
+        }
+        if s.mode != Mode::Normal {
+            bail!("{} can't be run without the terminal", name);
+        }
+        s.fixup();
+    }
+    if s.dirty {
+        s.save();
+        // like the file having changed on disk since it was opened
+        if let Some(dialog) = &s.dialog {
+            bail!("{}", dialog.question);
+        }
+        if s.dirty {
+            bail!("{}", s.message.take().unwrap_or_default());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs};
+
+    #[test]
+    fn fails_searches_without_matches() {
+        let file = std::env::temp_dir().join(format!("vee-exec-{}", std::process::id()));
+        fs::write(&file, "a\nb\n").unwrap();
+        let run = |script| {
+            run(
+                script,
+                file.to_string_lossy().into_owned(),
+                Config::default(),
+                Keymap::default(),
+            )
+        };
+        let err = run("/c; s/a/x/").unwrap_err();
+        assert_eq!(err.to_string(), "/c: no matches");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "a\nb\n");
+        run("/b; s/a/x/").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "x\nb\n");
+        fs::remove_file(&file).unwrap();
+    }
+}
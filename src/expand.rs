@@ -0,0 +1,116 @@
+use crate::state::{Cursor, Point, State};
+
+// Growing the selection a step at a time to the next thing around it, and back again. There's
+// no parser to say what the things are, so they're the ones the other selection commands know
+// about: the word, inside and then around quotes and brackets (level by level), the line, the
+// paragraph, and the whole file.
+#[derive(Default)]
+pub struct Expansions(Vec<Expansion>);
+
+struct Expansion {
+    before: (Cursor, Option<Cursor>),
+    after: (Point, Point),
+}
+
+impl State {
+    // the selection as the points it's between, or the cursor on its own
+    fn selected_range(&self) -> (Point, Point) {
+        match self.selection() {
+            Some((start, end)) => (start.into(), end.into()),
+            None => (self.cursor.into(), self.cursor.into()),
+        }
+    }
+
+    // everything around start to end that expanding could select
+    fn enclosing(&self, start: Point, end: Point) -> Vec<(Point, Point)> {
+        let mut ranges = Vec::new();
+        let wordish = self.wordish();
+        if let (Some(left), Some(right)) = (
+            self.left_word(&wordish, start),
+            self.right_word(&wordish, end),
+        ) {
+            // only a word if there's nothing else in it
+            if self.text_between(left, right).chars().all(&wordish) {
+                ranges.push((left, right));
+            }
+        }
+        if let (Some(open), Some(close)) = (self.open_quote(start), self.close_quote(end)) {
+            ranges.push((
+                Point {
+                    x: open.x + 1,
+                    ..open
+                },
+                close,
+            ));
+            ranges.push((
+                open,
+                Point {
+                    x: close.x + 1,
+                    ..close
+                },
+            ));
+        }
+        if let (Some(open), Some(close)) = (self.open_bracket(start), self.close_bracket(end)) {
+            ranges.push((
+                Point {
+                    x: open.x + 1,
+                    ..open
+                },
+                close,
+            ));
+            ranges.push((
+                open,
+                Point {
+                    x: close.x + 1,
+                    ..close
+                },
+            ));
+        }
+        ranges.push((self.start_of_line(start.y), self.end_of_line(end.y)));
+        ranges.push((self.start_of_para(start), self.end_of_para(end)));
+        ranges.push((self.start_of_file(), self.end_of_file()));
+        ranges
+    }
+
+    pub fn expand_selection(&mut self) {
+        let (start, end) = self.selected_range();
+        // anything else moving the selection leaves nothing to shrink back to
+        if self
+            .expansions
+            .0
+            .last()
+            .is_some_and(|last| last.after != (start, end))
+        {
+            self.expansions.0.clear();
+        }
+        let next = self
+            .enclosing(start, end)
+            .into_iter()
+            .filter(|&(a, b)| a <= start && b >= end && (a, b) != (start, end))
+            .min_by_key(|&(a, b)| self.text_between(a, b).len());
+        if let Some((a, b)) = next {
+            let before = (self.cursor, self.anchor);
+            self.expansions.0.push(Expansion {
+                before,
+                after: (a, b),
+            });
+            self.move_cursor(a);
+            self.anchor = Some(self.cursor);
+            self.move_cursor(b);
+        }
+    }
+
+    // goes back to the selection before the last expansion
+    pub fn shrink_selection(&mut self) {
+        let current = self.selected_range();
+        match self.expansions.0.pop() {
+            Some(expansion) if expansion.after == current => {
+                (self.cursor, self.anchor) = expansion.before;
+            }
+            _ => {
+                self.expansions.0.clear();
+                self.message = Some("Nothing to shrink back to".to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use {
+    crate::state::{Mode, State},
+    std::path::Path,
+};
+
+// A file dropped on the terminal arrives as its path, typed all at once
+pub struct Dropped {
+    path: String,
+    text: String,
+    // the mode it was dropped in, which the dialog replaces
+    mode: Mode,
+}
+
+// The file a burst of typed text names, if it looks like a dropped path. Terminals quote paths
+// with spaces in (Windows Terminal with double quotes), or escape the spaces, and some add a
+// space after.
+pub fn dropped_path(text: &str) -> Option<String> {
+    let text = text.trim();
+    let text = text.strip_prefix("file://").unwrap_or(text);
+    let quoted = |quote| text.strip_prefix(quote).and_then(|t| t.strip_suffix(quote));
+    let path = match quoted('\'').or_else(|| quoted('"')) {
+        Some(quoted) => quoted.to_string(),
+        // backslashes separate directories on Windows, rather than escaping
+        None if cfg!(windows) => text.to_string(),
+        None => {
+            let mut path = String::new();
+            let mut chars = text.chars();
+            while let Some(c) = chars.next() {
+                path.push(if c == '\\' { chars.next()? } else { c });
+            }
+            path
+        }
+    };
+    (Path::new(&path).is_absolute() && Path::new(&path).is_file()).then_some(path)
+}
+
+fn open_dropped(s: &mut State) {
+    if let Some(dropped) = s.dropped.take() {
+        s.open_file(dropped.path);
+    }
+}
+
+// typed text goes where it would have without the dialog, which only insert mode has
+fn insert_dropped(s: &mut State) {
+    if let Some(dropped) = s.dropped.take() {
+        if dropped.mode == Mode::Insert {
+            s.mode = Mode::Insert;
+            for c in dropped.text.chars() {
+                s.insert_char(c);
+            }
+        }
+    }
+}
+
+impl State {
+    // Asks whether to open a dropped file instead of taking the path as typed, returning
+    // whether we asked. Modes with their own input line take it as typed.
+    pub fn offer_drop(&mut self, path: String, text: String) -> bool {
+        if self.mode != Mode::Normal && self.mode != Mode::Insert {
+            return false;
+        }
+        let question = format!("Open {}?", path);
+        self.dropped = Some(Dropped {
+            path,
+            text,
+            mode: self.mode,
+        });
+        self.confirm(question, open_dropped, insert_dropped);
+        true
+    }
+}
@@ -0,0 +1,181 @@
+use {
+    crate::{idle::Task, state::State},
+    std::{
+        path::PathBuf,
+        time::{Duration, Instant},
+    },
+};
+
+// What git says about the file being edited, shown after its name in the status bar. Asking
+// means running git, which is too slow to do on every keystroke, so it's done when idle: on
+// opening a file, switching to it, and saving it. Only in trusted repositories, see trust.rs.
+
+// asking again sooner than this waits until it's been this long
+const INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct GitStatus {
+    // the branch checked out, or HEAD when there isn't one
+    pub branch: String,
+    // the file's two letter code from git status --porcelain, like M or ??, or empty if it's
+    // the same as the last commit
+    pub code: String,
+}
+
+// What the editor asks of version control: git, or nothing in builds without the git feature,
+// which show no status and have grep go through every file that isn't hidden.
+pub trait Vcs: Sync {
+    // None outside a repository
+    fn status(&self, file: &str) -> Option<GitStatus>;
+    // the files to search under the working directory, or None outside a repository
+    fn files(&self) -> Option<Vec<PathBuf>>;
+    // whether asking means running something the repository can configure, see trust.rs
+    fn runs_commands(&self) -> bool {
+        true
+    }
+}
+
+pub fn vcs() -> &'static dyn Vcs {
+    #[cfg(feature = "git")]
+    {
+        &cli::Git
+    }
+    #[cfg(not(feature = "git"))]
+    {
+        &NoVcs
+    }
+}
+
+#[cfg(not(feature = "git"))]
+struct NoVcs;
+
+#[cfg(not(feature = "git"))]
+impl Vcs for NoVcs {
+    fn status(&self, _: &str) -> Option<GitStatus> {
+        None
+    }
+
+    fn files(&self) -> Option<Vec<PathBuf>> {
+        None
+    }
+
+    fn runs_commands(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "git")]
+mod cli {
+    use {
+        super::{GitStatus, Vcs},
+        std::{
+            path::{Path, PathBuf},
+            process::Command,
+        },
+    };
+
+    pub struct Git;
+
+    impl Vcs for Git {
+        fn status(&self, file: &str) -> Option<GitStatus> {
+            let path = Path::new(file);
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+            let output = Command::new("git")
+                .current_dir(dir.unwrap_or_else(|| Path::new(".")))
+                .args(["status", "--porcelain", "--branch", "-z", "--"])
+                .arg(path.file_name()?)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            parse(&output.stdout)
+        }
+
+        // the files git would track, which leaves out what .gitignore does
+        fn files(&self) -> Option<Vec<PathBuf>> {
+            let output = Command::new("git")
+                .args([
+                    "ls-files",
+                    "-z",
+                    "--cached",
+                    "--others",
+                    "--exclude-standard",
+                ])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            Some(
+                output
+                    .stdout
+                    .split(|&b| b == 0)
+                    .filter(|path| !path.is_empty())
+                    .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+                    .collect(),
+            )
+        }
+    }
+
+    // The output of git status --porcelain --branch -z for a single file: a header naming the
+    // branch, then an entry for the file if it has changes.
+    pub fn parse(output: &[u8]) -> Option<GitStatus> {
+        let output = String::from_utf8_lossy(output);
+        let mut entries = output.split('\0');
+        let header = entries.next()?.strip_prefix("## ")?;
+        let header = header.strip_prefix("No commits yet on ").unwrap_or(header);
+        let branch = match header.split_once("...") {
+            Some((branch, _)) => branch,
+            None => header.split(' ').next()?,
+        };
+        let code = entries
+            .next()
+            .and_then(|entry| entry.get(..2))
+            .unwrap_or("")
+            .trim();
+        Some(GitStatus {
+            branch: branch.to_string(),
+            code: code.to_string(),
+        })
+    }
+}
+
+impl State {
+    pub fn refresh_git(&mut self) -> bool {
+        if self.scratch {
+            return false;
+        }
+        let file = self.file.clone();
+        if vcs().runs_commands() && !self.trusts(&file) {
+            return false;
+        }
+        if self
+            .git_checked
+            .is_some_and(|checked| checked.elapsed() < INTERVAL)
+        {
+            self.idle.schedule(Task::GitStatus);
+            return false;
+        }
+        self.git = vcs().status(&self.file);
+        self.git_checked = Some(Instant::now());
+        true
+    }
+}
+
+#[cfg(all(test, feature = "git"))]
+mod tests {
+    use super::cli::parse;
+
+    #[test]
+    fn parses_status() {
+        let status = parse(b"## main...origin/main [ahead 1]\0 M src/git.rs\0").unwrap();
+        assert_eq!(
+            (status.branch.as_str(), status.code.as_str()),
+            ("main", "M")
+        );
+        let status = parse(b"## No commits yet on trunk\0?? notes\0").unwrap();
+        assert_eq!(
+            (status.branch.as_str(), status.code.as_str()),
+            ("trunk", "??")
+        );
+        let status = parse(b"## HEAD (no branch)\0").unwrap();
+        assert_eq!((status.branch.as_str(), status.code.as_str()), ("HEAD", ""));
+        assert!(parse(b"").is_none());
+    }
+}
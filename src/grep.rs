@@ -0,0 +1,199 @@
+use {
+    crate::{
+        annotate, crypt, git,
+        pager::Target,
+        search::{self, point_in},
+        state::{Point, State},
+    },
+    anyhow::{bail, Result},
+    regex::Regex,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// Stops a search for something common from listing half the project.
+const MAX_RESULTS: usize = 10_000;
+
+// The files version control knows of under the working directory, see git::Vcs::files.
+// Outside a repository, or one that isn't trusted, every file that isn't hidden.
+fn project_files(trusted: bool) -> Result<Vec<PathBuf>> {
+    let files = if trusted { git::vcs().files() } else { None };
+    if let Some(files) = files {
+        return Ok(files);
+    }
+    let mut files = Vec::new();
+    walk(Path::new("."), &mut files)?;
+    Ok(files)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let path = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+        if entry.file_type()?.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// The first match on each line that has one, as where it starts and where it ends on that line.
+fn matches(re: &Regex, lines: &[&str]) -> Vec<(Point, usize)> {
+    if search::is_multi_line(re) {
+        let starts: Vec<usize> = lines
+            .iter()
+            .scan(0, |start, line| {
+                let line_start = *start;
+                *start += line.len() + 1;
+                Some(line_start)
+            })
+            .collect();
+        let joined = lines.join("\n");
+        let mut found: Vec<(Point, usize)> = Vec::new();
+        for m in re.find_iter(&joined) {
+            let (start, end) = (point_in(&starts, m.start()), point_in(&starts, m.end()));
+            if found.last().is_none_or(|(last, _)| last.y != start.y) {
+                let x = if end.y == start.y {
+                    end.x
+                } else {
+                    lines[start.y].len()
+                };
+                found.push((start, x));
+            }
+        }
+        found
+    } else {
+        lines
+            .iter()
+            .enumerate()
+            .filter_map(|(y, line)| {
+                let m = re.find(line)?;
+                Some((Point { x: m.start(), y }, m.end()))
+            })
+            .collect()
+    }
+}
+
+impl State {
+    // Searches every file in the project for the current search, listing the lines that match
+    // in the pager, from where enter opens the file at the match.
+    pub fn grep(&mut self) {
+        let re = match &self.search {
+            Some(Ok(re)) => re.clone(),
+            _ => {
+                self.message = Some("No search to grep for".to_string());
+                return;
+            }
+        };
+        match self.grep_files(&re) {
+            Ok((_, targets)) if targets.is_empty() => {
+                self.message = Some(format!("No matches for /{}/", re.as_str()))
+            }
+            Ok((lines, targets)) => {
+                let title = format!("grep /{}/ ({} lines)", re.as_str(), targets.len());
+                self.open_pager(title, lines);
+                if let Some(pager) = &mut self.pager {
+                    pager.targets = targets;
+                }
+            }
+            Err(err) => self.message = Some(format!("Couldn't grep: {}", err)),
+        }
+    }
+
+    // The listing, like grep -C: matching lines as file:line: text, with grep_context lines
+    // either side as file-line- text, and -- between the parts that don't join up.
+    fn grep_files(&self, re: &Regex) -> Result<(Vec<String>, Vec<Target>)> {
+        let context = self.config.grep_context;
+        let mut listing = Vec::new();
+        let mut targets = Vec::new();
+        // without asking, in the middle of a search, see trust.rs
+        let trusted = !git::vcs().runs_commands() || self.untrusted(".").is_none();
+        for path in project_files(trusted)? {
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            // binary, encrypted, or too big to be worth it
+            if bytes.len() as u64 > self.config.max_file_size
+                || bytes.contains(&0)
+                || crypt::is_encrypted(&bytes)
+            {
+                continue;
+            }
+            let file = path.to_string_lossy().into_owned();
+            let text = String::from_utf8_lossy(&bytes);
+            let lines: Vec<&str> = text.lines().collect();
+            let found = matches(re, &lines);
+            if targets.len() + found.len() > MAX_RESULTS {
+                bail!("More than {} matching lines", MAX_RESULTS);
+            }
+            // the last line of this file listed so far
+            let mut listed: Option<usize> = None;
+            for (i, &(point, end)) in found.iter().enumerate() {
+                let from = point.y.saturating_sub(context);
+                let from = listed.map_or(from, |listed| usize::max(from, listed + 1));
+                if listed.map_or(!listing.is_empty(), |listed| from > listed + 1) {
+                    listing.push("--".to_string());
+                }
+                listing
+                    .extend((from..point.y).map(|y| format!("{}-{}- {}", file, y + 1, lines[y])));
+                let prefix = format!("{}:{}: ", file, point.y + 1);
+                targets.push(Target {
+                    line: listing.len(),
+                    span: prefix.len() + point.x..prefix.len() + end,
+                    file: file.clone(),
+                    point,
+                });
+                listing.push(format!("{}{}", prefix, lines[point.y]));
+                // up to the next match, which lists its own line
+                let next = found.get(i + 1).map_or(lines.len(), |(next, _)| next.y);
+                let to = usize::min(point.y + 1 + context, next);
+                listing
+                    .extend((point.y + 1..to).map(|y| format!("{}-{}- {}", file, y + 1, lines[y])));
+                listed = Some(usize::max(point.y, to.saturating_sub(1)));
+            }
+        }
+        Ok((listing, targets))
+    }
+
+    // Opens the file of the selected result at its match, in a buffer of its own if it's another
+    // file, see buffer.rs.
+    pub fn open_result(&mut self) {
+        let target = self
+            .pager
+            .as_ref()
+            .and_then(|pager| pager.targets.get(pager.selected))
+            .map(|target| (target.file.clone(), target.point));
+        let (file, point) = match target {
+            Some(target) => target,
+            None => return,
+        };
+        // from a directory listing, see browse.rs
+        if Path::new(&file).is_dir() {
+            self.browse(PathBuf::from(file));
+            return;
+        }
+        self.close_pager();
+        if !annotate::same_file(&file, &self.file) {
+            let search = self.search.take();
+            self.open_file(file.clone());
+            self.search = search;
+            self.reannotate();
+            // couldn't be opened, and the message says why
+            if !annotate::same_file(&file, &self.file) {
+                return;
+            }
+        }
+        self.anchor = None;
+        let point = self.clamp(point);
+        self.move_cursor(point);
+    }
+}
@@ -0,0 +1,76 @@
+use crossterm::style::{Attribute, Attributes, Color};
+
+// Highlight sources, lowest priority first. Each layer only sets the attributes it cares about
+// and the rest show through from the layers below, so for example a comment inside the
+// selection keeps its foreground from Syntax and takes its background from Selection.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Layer {
+    Syntax,
+    Invalid,
+    Search,
+    Selection,
+}
+
+#[derive(Default, PartialEq, Clone, Copy)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attributes: Attributes,
+}
+
+impl Style {
+    pub fn fg(color: Color) -> Style {
+        Style {
+            fg: Some(color),
+            ..Style::default()
+        }
+    }
+
+    pub fn bg(color: Color) -> Style {
+        Style {
+            bg: Some(color),
+            ..Style::default()
+        }
+    }
+
+    // other takes priority over self wherever it sets an attribute
+    pub fn merge(self, other: Style) -> Style {
+        Style {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            attributes: self.attributes | other.attributes,
+        }
+    }
+
+    // Without colour (see Config::color) the colours are dropped, and the attribute, if any,
+    // stands in for them.
+    pub fn or_mono(self, color: bool, attribute: Option<Attribute>) -> Style {
+        if color {
+            self
+        } else {
+            Style {
+                attributes: attribute.map(Attributes::from).unwrap_or_default(),
+                ..Style::default()
+            }
+        }
+    }
+}
+
+impl Layer {
+    pub fn style(self, color: bool) -> Style {
+        match self {
+            Layer::Syntax => Style::fg(Color::DarkRed).or_mono(color, Some(Attribute::Dim)),
+            Layer::Invalid => Style::fg(Color::Magenta).or_mono(color, Some(Attribute::Bold)),
+            Layer::Search => Style::bg(Color::Red).or_mono(color, Some(Attribute::Underlined)),
+            Layer::Selection => Style::bg(Color::Grey).or_mono(color, Some(Attribute::Reverse)),
+        }
+    }
+}
+
+// merges the styles of the given layers in priority order, regardless of the order given
+pub fn resolve(mut layers: Vec<Layer>, color: bool) -> Style {
+    layers.sort();
+    layers.into_iter().fold(Style::default(), |style, layer| {
+        style.merge(layer.style(color))
+    })
+}
@@ -0,0 +1,102 @@
+use crate::{idle::Task, state::State};
+
+// Resting the mouse on a line for a moment shows what's wrong with it, in a box by the pointer:
+// what tools have annotated it with, in full, where the text after the line is cut short at the
+// edge of the window, and which bytes any placeholders for invalid UTF-8 stand for. Moving the
+// mouse again, clicking, or typing anything puts it away.
+
+pub struct Tooltip {
+    // the screen position of the pointer it's shown for
+    pub column: u16,
+    pub row: u16,
+    pub lines: Vec<String>,
+}
+
+impl State {
+    // The mouse moved to column and row, so what it was over is out of date. Returns whether
+    // there was a tooltip to put away.
+    pub fn hover(&mut self, column: u16, row: u16) -> bool {
+        self.hovering = Some((column, row));
+        self.idle.schedule(Task::Hover);
+        self.hide_tooltip()
+    }
+
+    pub fn hide_tooltip(&mut self) -> bool {
+        self.tooltip.take().is_some()
+    }
+
+    // what there is to say about line y
+    fn tooltip_lines(&self, y: usize) -> Vec<String> {
+        let mut lines = self.annotation_messages(y);
+        lines.extend(self.text[y].1.invalid.iter().map(|(x, bytes)| {
+            let bytes: String = bytes.iter().map(|b| format!("\\x{:02x}", b)).collect();
+            format!("invalid UTF-8 at column {}: {}", x + 1, bytes)
+        }));
+        lines
+    }
+
+    // shows what there is to say about the line the mouse has come to rest on, returning
+    // whether there was anything
+    pub fn show_tooltip(&mut self) -> bool {
+        let (column, row) = match self.hovering.take() {
+            Some(at) => at,
+            None => return false,
+        };
+        let (i, rect) = match self
+            .window_rects()
+            .into_iter()
+            .find(|(_, rect)| rect.contains(column, row))
+        {
+            Some(found) => found,
+            None => return false,
+        };
+        let lines = self.with_window(i, |s| match s.line_at(row - rect.y) {
+            Some(y) => s.tooltip_lines(y),
+            None => Vec::new(),
+        });
+        if lines.is_empty() {
+            return false;
+        }
+        self.tooltip = Some(Tooltip { column, row, lines });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+        crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    };
+
+    #[test]
+    fn hovers_over_annotations() {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            b"one\ntwo\nthree\n",
+            Config::default(),
+            Keymap::default(),
+        );
+        s.resize((80, 24));
+        s.annotation_request("add lint test.txt 2-3 E unused variable")
+            .unwrap();
+        s.hover(10, 0);
+        s.run_idle();
+        assert!(s.tooltip.is_none());
+        s.hover(10, 2);
+        assert!(s.tooltip.is_none());
+        s.run_idle();
+        let tooltip = s.tooltip.as_ref().unwrap();
+        assert_eq!(
+            (tooltip.row, tooltip.lines.as_slice()),
+            (2, &["unused variable".to_string()][..])
+        );
+        assert!(s.hover(11, 2));
+        assert!(s.tooltip.is_none());
+        s.run_idle();
+        assert!(s.tooltip.is_some());
+        s.handle(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE));
+        assert!(s.tooltip.is_none());
+    }
+}
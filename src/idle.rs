@@ -0,0 +1,107 @@
+use {
+    crate::cancel::Token,
+    std::{collections::BTreeSet, time::Duration},
+};
+
+// How long the event loop waits without input before running pending tasks.
+pub const DELAY: Duration = Duration::from_millis(200);
+
+// Long tasks do this many lines at a time, checking for input in between.
+pub const CHUNK: usize = 10_000;
+
+// Work that is too slow to do on every keystroke. Tasks are deduplicated, so scheduling one
+// that is already pending is free.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Task {
+    // annotate every line, not just the visible ones
+    Reannotate,
+    // say what's wrong with the line under the mouse, see hover.rs
+    Hover,
+    // check whether the coverage report has changed, for as long as it's shown
+    Coverage,
+    // write unsaved changes where they can be recovered from, see recovery.rs
+    Recover,
+    // save unsaved changes, with config.autosave
+    Autosave,
+    // ask git about the file again, see git.rs
+    GitStatus,
+    // write which files are open, see session.rs
+    Session,
+}
+
+// How far through a long running task we are. Shown in the status area.
+pub struct Progress {
+    pub task: Task,
+    pub token: Token,
+    pub label: &'static str,
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Default)]
+pub struct Idle {
+    pending: BTreeSet<Task>,
+    pub progress: Option<Progress>,
+}
+
+impl Idle {
+    pub fn schedule(&mut self, task: Task) {
+        self.pending.insert(task);
+    }
+
+    // Returns true if the task was pending, so that callers who can't wait can run it now. A
+    // task in progress keeps its token, cancelled, until it stops, see take.
+    pub fn cancel(&mut self, task: Task) -> bool {
+        if let Some(progress) = &self.progress {
+            if progress.task == task {
+                progress.token.cancel();
+            }
+        }
+        self.pending.remove(&task)
+    }
+
+    // the task in progress, unless it's been cancelled and only has to stop
+    pub fn running(&self) -> Option<&Progress> {
+        self.progress
+            .as_ref()
+            .filter(|progress| !progress.token.is_cancelled())
+    }
+
+    // the token for the task in progress, or a fresh one if it's just starting
+    pub fn token(&self, task: Task) -> Token {
+        match &self.progress {
+            Some(progress) if progress.task == task => progress.token.clone(),
+            _ => Token::default(),
+        }
+    }
+
+    // once a long task has started, keep going as soon as there's no input waiting
+    pub fn delay(&self) -> Duration {
+        if self.progress.is_some() {
+            Duration::ZERO
+        } else {
+            DELAY
+        }
+    }
+
+    pub fn is_pending(&self, task: Task) -> bool {
+        self.pending.contains(&task)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.progress.is_none()
+    }
+
+    // Takes the tasks to run next. Between steps is where a long task checks its token, so one
+    // that's been cancelled stops here, starting again from scratch if it's been scheduled again.
+    pub fn take(&mut self) -> BTreeSet<Task> {
+        if self
+            .progress
+            .as_ref()
+            .is_some_and(|progress| progress.token.is_cancelled())
+        {
+            self.progress = None;
+        }
+        std::mem::take(&mut self.pending)
+    }
+}
@@ -0,0 +1,159 @@
+use crate::state::{Point, State};
+
+// how much of the start of line dedenting removes: one tab, or up to a full indent of spaces
+fn dedent_len(line: &str, width: usize) -> usize {
+    if line.starts_with('\t') {
+        1
+    } else {
+        line.len()
+            - line
+                .trim_start_matches(' ')
+                .len()
+                .max(line.len().saturating_sub(width))
+    }
+}
+
+fn indent_len(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+pub fn indent_of(line: &str) -> &str {
+    &line[..indent_len(line)]
+}
+
+// as much of the start of a as b starts with too, which is always whole chars
+pub fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .take_while(|&((_, c), d)| c == d)
+        .map(|((i, c), _)| i + c.len_utf8())
+        .last()
+        .unwrap_or(0);
+    &a[..len]
+}
+
+// Reindents lines of text to go after indent, keeping how far each is indented relative to the
+// others. The first line is left alone unless first, when it's going at the start of a line. It
+// could have been cut from after its own indent, so it only counts towards the indent the lines
+// share if it has one.
+fn reindent(text: &str, indent: &str, first: bool) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let last = lines.len() - 1;
+    // the indent they share, as text, since the same length could be spaces in one and a tab or
+    // some wider whitespace in another
+    let base = lines
+        .iter()
+        .enumerate()
+        .filter(|&(i, line)| !line.trim().is_empty() && (i > 0 || first && indent_len(line) > 0))
+        .map(|(_, line)| indent_of(line))
+        .reduce(common_prefix)
+        .unwrap_or("");
+    // a first line that doesn't share it has nothing to strip
+    let strip = |line: &str| line.strip_prefix(base).unwrap_or(line).to_string();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, &line)| match i {
+            // the cursor is already after the indent
+            0 if first => strip(line),
+            0 => line.to_string(),
+            // whole lines end with an empty one, before what was after the cursor
+            _ if i == last && line.trim().is_empty() => indent.to_string(),
+            _ if line.trim().is_empty() => String::new(),
+            _ => format!("{}{}", indent, strip(line)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl State {
+    // Text of several lines, reindented to match the line it's pasted into. Pasting anywhere in
+    // the indent pastes after it, so the first line lines up with the rest.
+    pub fn fit_indent(&mut self, text: &str) -> String {
+        let line = &self.text[self.cursor.y].0;
+        let len = indent_len(line);
+        let indent = line[..len].to_string();
+        let first = self.cursor.x <= len;
+        if first {
+            self.move_cursor(Point {
+                x: len,
+                y: self.cursor.y,
+            });
+        }
+        reindent(text, &indent, first)
+    }
+
+    // the lines covered by the selection, or the cursor's line without one. A selection ending at
+    // the very start of a line doesn't cover that line.
+    pub fn selected_lines(&self) -> (usize, usize) {
+        match self.selection() {
+            Some((start, end)) if end.y > start.y && end.x == 0 => (start.y, end.y - 1),
+            Some((start, end)) => (start.y, end.y),
+            None => (self.cursor.y, self.cursor.y),
+        }
+    }
+
+    // replaces each selected line with f applied to it, keeping the selection
+    pub fn map_selected_lines(&mut self, f: impl Fn(&str) -> String) {
+        let (first, last) = self.selected_lines();
+        self.map_lines(first, last, f);
+    }
+
+    pub fn map_lines(&mut self, first: usize, last: usize, f: impl Fn(&str) -> String) {
+        let (y, x) = (self.cursor.y, self.cursor.x);
+        let before = self.text[y].0.len();
+        self.transform(Point { x: 0, y: first }, self.end_of_line(last), |text| {
+            text.split('\n').map(&f).collect::<Vec<_>>().join("\n")
+        });
+        if self.anchor.is_none() {
+            // keep the cursor on the same character, which transform doesn't know to do
+            let after = self.text[y].0.len();
+            let x = (x + after).saturating_sub(before);
+            self.move_cursor(self.clamp(Point { x, y }));
+        }
+    }
+
+    pub fn indent(&mut self) {
+        let unit = self.config.indent();
+        self.map_selected_lines(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", unit, line)
+            }
+        });
+    }
+
+    pub fn dedent(&mut self) {
+        let width = self.config.indent_width;
+        self.map_selected_lines(|line| line[dedent_len(line, width)..].to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reindents_relative_to_the_shared_indent() {
+        assert_eq!(
+            reindent("a\n    b\n      c\n", "  ", false),
+            "a\n  b\n    c\n  "
+        );
+        assert_eq!(reindent("  a\n    b", "\t", true), "a\n\t  b");
+    }
+
+    #[test]
+    fn reindents_unicode_whitespace() {
+        // the same length in bytes, but not the same indent
+        assert_eq!(
+            reindent("x\n \u{3000}a\n  b", "\t", false),
+            "x\n\t\u{3000}a\n\t b"
+        );
+        assert_eq!(
+            reindent("\u{3000}\u{3000}a\n\u{3000}b", "", true),
+            "\u{3000}a\nb"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use {
+    crate::{
+        config, log,
+        state::{Cursor, Mode, State},
+    },
+    crossterm::event::KeyEvent,
+};
+
+impl State {
+    fn check_cursor(&self, name: &str, cursor: Cursor, violations: &mut Vec<String>) {
+        let line = match self.text.get(cursor.y) {
+            Some(line) => &line.0,
+            None => {
+                violations.push(format!("{} is past the last line", name));
+                return;
+            }
+        };
+        if cursor.x > line.len() {
+            violations.push(format!("{} is past the end of its line", name));
+        } else if !line.is_char_boundary(cursor.x) {
+            violations.push(format!("{} is inside a char", name));
+        }
+    }
+
+    // everything that should be true between keys, described for each thing that isn't
+    pub fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        if self.text.is_empty() {
+            violations.push("there are no lines".to_string());
+        }
+        self.check_cursor("cursor", self.cursor, &mut violations);
+        if let Some(anchor) = self.anchor {
+            self.check_cursor("anchor", anchor, &mut violations);
+        }
+        if self.windows[self.window].buffer != self.active {
+            violations.push(format!(
+                "focused window shows buffer {} while {} is being edited",
+                self.windows[self.window].buffer, self.active
+            ));
+        }
+        if self.windows.iter().any(|w| w.buffer >= self.buffers.len()) {
+            violations.push("a window shows a buffer that isn't open".to_string());
+        }
+        if (self.mode == Mode::Dialog) != self.dialog.is_some() {
+            violations.push(format!(
+                "{} mode with dialog {}",
+                self.mode,
+                self.dialog.is_some()
+            ));
+        }
+        if (self.mode == Mode::Pager) != self.pager.is_some() {
+            violations.push(format!(
+                "{} mode with pager {}",
+                self.mode,
+                self.pager.is_some()
+            ));
+        }
+        if (self.mode == Mode::Search) != self.search_prompt.is_some() {
+            violations.push(format!(
+                "{} mode with search prompt {}",
+                self.mode,
+                self.search_prompt.is_some()
+            ));
+        }
+        if (self.mode == Mode::Session) != self.session_prompt.is_some() {
+            violations.push(format!(
+                "{} mode with session prompt {}",
+                self.mode,
+                self.session_prompt.is_some()
+            ));
+        }
+        if matches!(self.mode, Mode::Goto | Mode::Filetype) != self.status_prompt.is_some() {
+            violations.push(format!(
+                "{} mode with status prompt {}",
+                self.mode,
+                self.status_prompt.is_some()
+            ));
+        }
+        if matches!(self.mode, Mode::Replace | Mode::Confirm) != self.replace.is_some() {
+            violations.push(format!(
+                "{} mode with replace {}",
+                self.mode,
+                self.replace.is_some()
+            ));
+        }
+        if (self.mode == Mode::Filter) != self.filter.is_some() {
+            violations.push(format!(
+                "{} mode with filter {}",
+                self.mode,
+                self.filter.is_some()
+            ));
+        }
+        if (self.mode == Mode::Path) != self.path_prompt.is_some() {
+            violations.push(format!(
+                "{} mode with path prompt {}",
+                self.mode,
+                self.path_prompt.is_some()
+            ));
+        }
+        violations
+    }
+
+    // Logs a snapshot of the state when an invariant doesn't hold, since the panic that follows
+    // (if there is one) often happens keys later, far from the cause.
+    pub fn check_invariants(&mut self, event: KeyEvent) {
+        let violations = self.violations();
+        if violations.is_empty() {
+            return;
+        }
+        log!("INVARIANT after {:?}", event);
+        for violation in &violations {
+            log!("  {}", violation);
+        }
+        log!(
+            "  mode {} file {} lines {}",
+            self.mode,
+            self.file,
+            self.text.len()
+        );
+        log!("  cursor {:?} anchor {:?}", self.cursor, self.anchor);
+        // the log is plain text on disk, so encrypted text stays out of it
+        if self.encryption.is_none() {
+            for cursor in [Some(self.cursor), self.anchor].into_iter().flatten() {
+                if let Some(line) = self.text.get(cursor.y) {
+                    log!("  line {} {:?}", cursor.y, line.0);
+                }
+            }
+        }
+        self.message = Some(match config::state_path("log") {
+            Some(log) => format!(
+                "Invariant violated: {} (see {})",
+                violations[0],
+                log.display()
+            ),
+            None => format!("Invariant violated: {}", violations[0]),
+        });
+    }
+}
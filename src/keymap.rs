@@ -0,0 +1,763 @@
+use {
+    crate::{
+        command::{self, Command},
+        config,
+        state::{Mode, State},
+    },
+    anyhow::{anyhow, bail, Context as _, Result},
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    std::{
+        collections::{BTreeMap, HashMap},
+        fs, io,
+    },
+};
+
+// Layers stack over the base map for their mode. When resolving a key, active layers are
+// consulted in this priority order (highest first), falling back to the base map:
+//
+// 1. Filetype (e.g. only in .rs files, `filetype:rs` in the keymap file)
+// 2. Busy (when a long running task is in progress)
+// 3. Selections (when there's more than one selection)
+// 4. Selection (when the anchor is set)
+// 5. Search (when a search is active)
+// 6. Base
+//
+// Within a context, layers added later take priority over those added earlier.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum Context {
+    Base,
+    Search,
+    Selection,
+    Selections,
+    Busy,
+    Filetype(String),
+}
+
+impl Context {
+    fn active(&self, s: &State) -> bool {
+        match self {
+            Context::Base => true,
+            Context::Search => s.search.is_some(),
+            Context::Selection => s.anchor.is_some(),
+            Context::Selections => !s.selections.is_empty(),
+            Context::Busy => s.idle.running().is_some(),
+            Context::Filetype(filetype) => s.filetype() == Some(filetype),
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "base" => Context::Base,
+            "search" => Context::Search,
+            "selection" => Context::Selection,
+            "selections" => Context::Selections,
+            "busy" => Context::Busy,
+            _ => match s.strip_prefix("filetype:") {
+                Some(filetype) => Context::Filetype(filetype.to_string()),
+                None => bail!("Unknown context: {}", s),
+            },
+        })
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Context::Base => "base".to_string(),
+            Context::Search => "search".to_string(),
+            Context::Selection => "selection".to_string(),
+            Context::Selections => "selections".to_string(),
+            Context::Busy => "busy".to_string(),
+            Context::Filetype(filetype) => format!("filetype:{}", filetype),
+        }
+    }
+}
+
+// the modes in the order the keymap listing shows them
+const MODES: &[(Mode, &str)] = &[
+    (Mode::Normal, "normal"),
+    (Mode::Insert, "insert"),
+    (Mode::System, "system"),
+    (Mode::Register, "register"),
+    (Mode::Dialog, "dialog"),
+    (Mode::Goto, "goto"),
+    (Mode::Filetype, "filetype"),
+    (Mode::Command, "command"),
+    (Mode::Pager, "pager"),
+    (Mode::Search, "search"),
+    (Mode::Session, "session"),
+    (Mode::Replace, "replace"),
+    (Mode::Confirm, "confirm"),
+    (Mode::Filter, "filter"),
+    (Mode::Path, "path"),
+];
+
+fn parse_mode(s: &str) -> Result<Mode> {
+    MODES
+        .iter()
+        .find(|(_, name)| *name == s)
+        .map(|&(mode, _)| mode)
+        .ok_or(anyhow!("Unknown mode: {}", s))
+}
+
+const KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Esc, "esc"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::BackTab, "backtab"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+    (KeyCode::PageUp, "pageup"),
+    (KeyCode::PageDown, "pagedown"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Char(' '), "space"),
+];
+
+// A key as bindings see it: the code, and whether control is held. Shift is already part of
+// the code for characters, so it's ignored.
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+}
+
+impl From<KeyCode> for Key {
+    fn from(code: KeyCode) -> Self {
+        Key { code, ctrl: false }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Key {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        }
+    }
+}
+
+fn ctrl(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        ctrl: true,
+    }
+}
+
+fn parse_key(s: &str) -> Result<Key> {
+    if let Some(rest) = s.strip_prefix("ctrl-") {
+        let code = parse_key(rest)?.code;
+        return Ok(Key { code, ctrl: true });
+    }
+    if let Some(&(code, _)) = KEYS.iter().find(|(_, name)| *name == s) {
+        return Ok(code.into());
+    }
+    if let Some(Ok(n)) = s.strip_prefix('f').map(str::parse) {
+        return Ok(KeyCode::F(n).into());
+    }
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(KeyCode::Char(c).into()),
+        _ => bail!("Unknown key: {}", s),
+    }
+}
+
+fn key_name(key: Key) -> String {
+    let name = match (KEYS.iter().find(|&&(c, _)| c == key.code), key.code) {
+        (Some((_, name)), _) => name.to_string(),
+        (None, KeyCode::Char(c)) => c.to_string(),
+        (None, KeyCode::F(n)) => format!("f{}", n),
+        (None, code) => format!("{:?}", code).to_lowercase(),
+    };
+    if key.ctrl {
+        format!("ctrl-{}", name)
+    } else {
+        name
+    }
+}
+
+// What typing a character does in this mode when it isn't bound, if anything, so that binding
+// it can be flagged as hiding that.
+fn unbound_char(mode: Mode, c: char) -> Option<&'static str> {
+    match mode {
+        Mode::Insert => Some("inserting it"),
+        Mode::Command => Some("typing it in the prompt"),
+        Mode::Search => Some("typing it in the search"),
+        Mode::Goto => Some("typing it in the line number"),
+        Mode::Filetype => Some("typing it in the filetype"),
+        Mode::Session => Some("typing it in the session name"),
+        Mode::Replace => Some("typing it in the replacement"),
+        Mode::Filter => Some("typing it in the pattern"),
+        Mode::Path => Some("typing it in the path"),
+        Mode::Register if c.is_ascii_lowercase() => Some("choosing that register"),
+        Mode::Normal if c.is_ascii_digit() => Some("typing a count"),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Source {
+    Default,
+    // the line of the keymap file the binding came from
+    Config(usize),
+}
+
+#[derive(Clone)]
+pub struct Layer {
+    pub mode: Mode,
+    pub context: Context,
+    pub source: Source,
+    pub bindings: HashMap<Key, &'static Command>,
+}
+
+impl Layer {
+    fn new<K>(mode: Mode, context: Context, bindings: &[(K, &str)]) -> Self
+    where
+        K: Into<Key> + Copy,
+    {
+        Layer {
+            mode,
+            context,
+            source: Source::Default,
+            bindings: bindings
+                .iter()
+                .map(|&(key, name)| (key.into(), command::lookup(name).expect(name)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Keymap {
+    layers: Vec<Layer>,
+    // problems found while loading the keymap file, which don't stop it loading
+    pub warnings: Vec<String>,
+}
+
+impl Keymap {
+    pub fn lookup(&self, s: &State, key: Key) -> Option<&'static Command> {
+        let mut layers: Vec<&Layer> = self
+            .layers
+            .iter()
+            .filter(|layer| layer.mode == s.mode && layer.context.active(s))
+            .collect();
+        // stable sort, so reversing afterwards puts later layers first within a context
+        layers.sort_by(|a, b| a.context.cmp(&b.context));
+        layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.bindings.get(&key).copied())
+    }
+
+    // The defaults, then one binding per line of keymap in the config directory, in the form
+    // `mode context key command`, for example `normal filetype:rs x delete-selection`. Blank
+    // lines and lines starting with `#` are ignored.
+    pub fn load() -> Result<Self> {
+        let mut keymap = Keymap::default();
+        let path = match config::path("keymap") {
+            Some(path) => path,
+            None => return Ok(keymap),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(keymap),
+            Err(err) => return Err(err.into()),
+        };
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            keymap
+                .bind(i + 1, line)
+                .with_context(|| format!("{}:{}", path.display(), i + 1))?;
+        }
+        Ok(keymap)
+    }
+
+    fn bind(&mut self, n: usize, line: &str) -> Result<()> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (mode, context, key, command) = match words[..] {
+            [mode, context, key, command] => (
+                parse_mode(mode)?,
+                Context::parse(context)?,
+                parse_key(key)?,
+                command::lookup(command).ok_or(anyhow!("Unknown command: {}", command))?,
+            ),
+            _ => bail!("Expected `mode context key command`"),
+        };
+        let here = format!("line {}: {} {} {}", n, words[0], words[1], words[2]);
+        if let (KeyCode::Char(c), false) = (key.code, key.ctrl) {
+            if let Some(hidden) = unbound_char(mode, c) {
+                self.warnings.push(format!("{} hides {}", here, hidden));
+            }
+        }
+        for layer in &self.layers {
+            if layer.mode != mode || layer.context != context {
+                continue;
+            }
+            if let (Some(old), Source::Config(m)) = (layer.bindings.get(&key), layer.source) {
+                self.warnings.push(format!(
+                    "{} replaces {} from line {}, which is unreachable",
+                    here, old.name, m,
+                ));
+            }
+        }
+        let mut bindings = HashMap::new();
+        bindings.insert(key, command);
+        self.layers.push(Layer {
+            mode,
+            context,
+            source: Source::Config(n),
+            bindings,
+        });
+        Ok(())
+    }
+
+    // Every binding, by mode and then context, with where it came from. A binding replaced by a
+    // later one in the same mode and context is listed under the one that replaced it.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = self.warnings.clone();
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        for &(mode, mode_name) in MODES {
+            let mut effective: BTreeMap<(&Context, String), Vec<(&Command, Source)>> =
+                BTreeMap::new();
+            for layer in self.layers.iter().filter(|layer| layer.mode == mode) {
+                for (&key, &command) in &layer.bindings {
+                    effective
+                        .entry((&layer.context, key_name(key)))
+                        .or_default()
+                        .insert(0, (command, layer.source));
+                }
+            }
+            lines.push(mode_name.to_string());
+            for ((context, key), bindings) in effective {
+                for (i, (command, source)) in bindings.into_iter().enumerate() {
+                    let source = match source {
+                        Source::Default => "default".to_string(),
+                        Source::Config(n) => format!("config line {}", n),
+                    };
+                    lines.push(format!(
+                        "  {:<12} {:<10} {:<26} {}{}",
+                        context.name(),
+                        key,
+                        command.name,
+                        source,
+                        if i > 0 { " (replaced)" } else { "" },
+                    ));
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl State {
+    pub fn start_binding(&mut self) {
+        self.mode = Mode::Key;
+        self.message = Some("Press the key to bind".to_string());
+    }
+
+    // Types the start of a line of the keymap file binding key, in normal mode and the base
+    // context, ready for the command. The name is the one loading the file would read back.
+    pub fn insert_binding(&mut self, key: Key) {
+        self.mode = Mode::Normal;
+        let name = key_name(key);
+        if parse_key(&name).ok() != Some(key) {
+            self.message = Some(format!("Can't bind {}", name));
+            return;
+        }
+        self.anchor = None;
+        self.insert(&format!("normal base {} ", name));
+        // terminals send the same bytes for these as for the control keys, so they can't be told
+        // apart
+        let alias = match (key.code, key.ctrl) {
+            (KeyCode::Tab, false) => Some("ctrl-i"),
+            (KeyCode::Enter, false) => Some("ctrl-m"),
+            (KeyCode::Esc, false) => Some("ctrl-["),
+            (KeyCode::Backspace, false) => Some("ctrl-h in some terminals"),
+            _ => None,
+        };
+        self.message = Some(match alias {
+            Some(alias) => format!("Inserted a binding for {}, which is also {}", name, alias),
+            None => format!("Inserted a binding for {}", name),
+        });
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use KeyCode::*;
+        Keymap {
+            warnings: Vec::new(),
+            layers: vec![
+                Layer::new(
+                    Mode::Normal,
+                    Context::Base,
+                    &[
+                        (Char('q'), "select-inside-quotes"),
+                        (Char('w'), "select-word"),
+                        (Char('e'), "select-inside-brackets"),
+                        (Char('r'), "select-line"),
+                        (Char('y'), "move-start-of-line"),
+                        (Char('u'), "move-left-word"),
+                        (Char('i'), "move-right-word"),
+                        (Char('o'), "move-end-of-line"),
+                        (Char('p'), "move-bracket-inside"),
+                        (Char('s'), "set-anchor"),
+                        (Char('f'), "begin-edit"),
+                        (Char('b'), "open-line-below"),
+                        (Char('B'), "open-line-above"),
+                        (Char('c'), "yank"),
+                        (Char('d'), "delete-selection"),
+                        (Char('a'), "change-selection"),
+                        (Char('v'), "paste"),
+                        (Char('V'), "paste-cycle"),
+                        (Char('"'), "choose-register"),
+                        (Char('g'), "repeat-edit"),
+                        (Char('x'), "undo"),
+                        (Char('X'), "redo"),
+                        (Char('z'), "record-macro"),
+                        (Char('Z'), "play-macro"),
+                        (Char('M'), "set-mark"),
+                        (Char('\''), "jump-to-mark"),
+                        (Char('h'), "move-left"),
+                        (Left, "move-left"),
+                        (Char('j'), "move-down"),
+                        (Down, "move-down"),
+                        (Char('k'), "move-up"),
+                        (Up, "move-up"),
+                        (Char('l'), "move-right"),
+                        (Right, "move-right"),
+                        (Char('n'), "move-start-of-file"),
+                        (Char('m'), "move-next-match"),
+                        (Char(','), "move-prev-match"),
+                        (Char('.'), "move-end-of-file"),
+                        (Char('/'), "search"),
+                        (Char('?'), "search-raw"),
+                        (Char('*'), "search-word"),
+                        (Char('A'), "select-matches"),
+                        (Char('C'), "add-cursor-below"),
+                        (Char('D'), "add-cursor-above"),
+                        (Char('S'), "replace"),
+                        (Char('G'), "grep"),
+                        (Char('%'), "select-all"),
+                        (Char('='), "expand-selection"),
+                        (Char('_'), "shrink-selection"),
+                        (Char('>'), "indent"),
+                        (Char('<'), "dedent"),
+                        (Char('#'), "toggle-comment"),
+                        (Char('t'), "join-lines"),
+                        (Char('+'), "uppercase"),
+                        (Char('-'), "lowercase"),
+                        (Char('~'), "swap-case"),
+                        (Char('Q'), "select-outside-quotes"),
+                        (Char('W'), "select-big-word"),
+                        (Char('E'), "select-outside-brackets"),
+                        (Char('R'), "select-para"),
+                        (Char('Y'), "move-start-of-para"),
+                        (Char('U'), "move-left-big-word"),
+                        (Char('I'), "move-right-big-word"),
+                        (Char('O'), "move-end-of-para"),
+                        (Char('P'), "move-bracket-outside"),
+                        (Char('H'), "move-left-5"),
+                        (Char('J'), "move-down-5"),
+                        (Char('K'), "move-up-5"),
+                        (Char('L'), "move-right-5"),
+                        (Char(' '), "system-mode"),
+                        (Char(':'), "palette"),
+                        (Char(';'), "repeat-last-command"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Normal,
+                    Context::Base,
+                    &[
+                        (ctrl('v'), "paste-verbatim"),
+                        (ctrl('s'), "set-block-anchor"),
+                    ],
+                ),
+                Layer::new(Mode::Normal, Context::Search, &[(Esc, "cancel-search")]),
+                Layer::new(
+                    Mode::Normal,
+                    Context::Selection,
+                    &[(Esc, "clear-anchor"), (Char('S'), "replace-selection")],
+                ),
+                Layer::new(
+                    Mode::Normal,
+                    Context::Selections,
+                    &[
+                        (Esc, "clear-selections"),
+                        (Char('&'), "keep-selections"),
+                        (Char('!'), "drop-selections"),
+                        (Char(')'), "rotate-selections"),
+                        (Char('('), "rotate-selections-back"),
+                    ],
+                ),
+                Layer::new(Mode::Normal, Context::Busy, &[(Esc, "cancel-task")]),
+                Layer::new(
+                    Mode::Insert,
+                    Context::Base,
+                    &[
+                        (Esc, "end-edit"),
+                        (Enter, "newline"),
+                        (Backspace, "backspace"),
+                        (Delete, "delete"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::System,
+                    Context::Base,
+                    &[
+                        (Char('q'), "quit"),
+                        (Char('s'), "split-window"),
+                        (Char('v'), "split-window-right"),
+                        (Char('f'), "next-window"),
+                        (Char('k'), "close-window"),
+                        (Char('g'), "goto-line"),
+                        (Char('t'), "pick-filetype"),
+                        (Char('Q'), "force-quit"),
+                        (Char('c'), "quit-with-error"),
+                        (Char('x'), "save-and-quit"),
+                        (Char('o'), "open-file"),
+                        (Char('e'), "browse"),
+                        (Char('n'), "next-buffer"),
+                        (Char('p'), "previous-buffer"),
+                        (Char('w'), "save"),
+                        (Char('r'), "reload"),
+                    ],
+                ),
+                Layer::new(Mode::Register, Context::Base, &[(Esc, "register-cancel")]),
+                Layer::new(
+                    Mode::Dialog,
+                    Context::Base,
+                    &[
+                        (Char('y'), "dialog-yes"),
+                        (Char('n'), "dialog-no"),
+                        (Esc, "dialog-cancel"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Command,
+                    Context::Base,
+                    &[
+                        (Enter, "palette-run"),
+                        (Esc, "palette-cancel"),
+                        (Backspace, "palette-backspace"),
+                        (Delete, "palette-delete"),
+                        (Left, "palette-left"),
+                        (Right, "palette-right"),
+                        (Home, "palette-home"),
+                        (End, "palette-end"),
+                        (Up, "palette-prev"),
+                        (Down, "palette-next"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Command,
+                    Context::Base,
+                    &[
+                        (ctrl('a'), "palette-home"),
+                        (ctrl('e'), "palette-end"),
+                        (ctrl('b'), "palette-left"),
+                        (ctrl('f'), "palette-right"),
+                        (ctrl('p'), "palette-prev"),
+                        (ctrl('n'), "palette-next"),
+                        (ctrl('w'), "palette-delete-word"),
+                        (ctrl('u'), "palette-kill-start"),
+                        (ctrl('r'), "palette-search"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Search,
+                    Context::Base,
+                    &[
+                        (Enter, "search-run"),
+                        (Esc, "search-cancel"),
+                        (Backspace, "search-backspace"),
+                        (Up, "search-prev"),
+                        (Down, "search-next"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Search,
+                    Context::Base,
+                    &[(ctrl('t'), "toggle-search-case")],
+                ),
+                Layer::new(
+                    Mode::Session,
+                    Context::Base,
+                    &[
+                        (Enter, "session-run"),
+                        (Esc, "session-cancel"),
+                        (Backspace, "session-backspace"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Goto,
+                    Context::Base,
+                    &[
+                        (Enter, "status-prompt-run"),
+                        (Esc, "status-prompt-cancel"),
+                        (Backspace, "status-prompt-backspace"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Filetype,
+                    Context::Base,
+                    &[
+                        (Enter, "status-prompt-run"),
+                        (Esc, "status-prompt-cancel"),
+                        (Backspace, "status-prompt-backspace"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Replace,
+                    Context::Base,
+                    &[
+                        (Enter, "replace-start"),
+                        (Esc, "replace-cancel"),
+                        (Backspace, "replace-backspace"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Filter,
+                    Context::Base,
+                    &[
+                        (Enter, "filter-run"),
+                        (Esc, "filter-cancel"),
+                        (Backspace, "filter-backspace"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Path,
+                    Context::Base,
+                    &[
+                        (Enter, "path-run"),
+                        (Esc, "path-cancel"),
+                        (Backspace, "path-backspace"),
+                        (Tab, "path-complete"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Confirm,
+                    Context::Base,
+                    &[
+                        (Char('y'), "replace-yes"),
+                        (Char('n'), "replace-skip"),
+                        (Char('a'), "replace-all"),
+                        (Char('q'), "replace-cancel"),
+                        (Esc, "replace-cancel"),
+                    ],
+                ),
+                Layer::new(
+                    Mode::Pager,
+                    Context::Base,
+                    &[
+                        (Char('j'), "pager-down"),
+                        (Down, "pager-down"),
+                        (Char('k'), "pager-up"),
+                        (Up, "pager-up"),
+                        (Char('J'), "pager-page-down"),
+                        (PageDown, "pager-page-down"),
+                        (Char('K'), "pager-page-up"),
+                        (PageUp, "pager-page-up"),
+                        (Char('q'), "pager-close"),
+                        (Esc, "pager-close"),
+                        (Enter, "pager-open"),
+                        (Char('n'), "browse-create"),
+                        (Char('r'), "browse-rename"),
+                        (Char('d'), "browse-delete"),
+                    ],
+                ),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, state::Point},
+    };
+
+    #[test]
+    fn higher_contexts_shadow_lower_ones() {
+        let mut keymap = Keymap::default();
+        for (n, line) in [
+            "normal base f5 select-line",
+            "normal selection f5 select-all",
+            "normal base f6 move-end-of-file",
+            "normal filetype:rs f5 select-word",
+        ]
+        .iter()
+        .enumerate()
+        {
+            keymap.bind(n + 1, line).unwrap();
+        }
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            b"one\ntwo\n",
+            Config::default(),
+            keymap.clone(),
+        );
+        let name = |s: &State, n| keymap.lookup(s, KeyCode::F(n).into()).map(|c| c.name);
+        assert_eq!(name(&s, 5), Some("select-line"));
+        s.anchor = Some(s.cursor);
+        s.move_cursor(Point { x: 1, y: 0 });
+        assert_eq!(name(&s, 5), Some("select-all"));
+        // nothing in the selection layer, so the base map has it
+        assert_eq!(name(&s, 6), Some("move-end-of-file"));
+        assert_eq!(name(&s, 7), None);
+        // and a filetype over both, but only in its own files
+        s.set_filetype("rs".to_string());
+        assert_eq!(name(&s, 5), Some("select-word"));
+        assert_eq!(name(&s, 6), Some("move-end-of-file"));
+    }
+
+    #[test]
+    fn warns_about_conflicts() {
+        let mut keymap = Keymap::default();
+        for (n, line) in [
+            "insert base x select-line",
+            "insert base ctrl-x select-line",
+            "normal base 5 select-line",
+            "normal base f5 select-line",
+            "normal selection f5 select-all",
+            "normal base f5 select-word",
+        ]
+        .iter()
+        .enumerate()
+        {
+            keymap.bind(n + 1, line).unwrap();
+        }
+        assert_eq!(
+            keymap.warnings,
+            [
+                "line 1: insert base x hides inserting it",
+                "line 3: normal base 5 hides typing a count",
+                "line 6: normal base f5 replaces select-line from line 4, which is unreachable",
+            ],
+        );
+        let lines = keymap.describe();
+        assert_eq!(lines[3], "");
+        let f5: Vec<&str> = lines
+            .iter()
+            .filter(|line| line.starts_with("  base         f5 "))
+            .map(|line| line.split_whitespace().nth(2).unwrap())
+            .collect();
+        assert_eq!(f5, ["select-word", "select-line"]);
+        assert!(lines
+            .iter()
+            .any(|line| line.ends_with("config line 4 (replaced)")));
+        // and nothing to say about the defaults
+        assert!(Keymap::default().warnings.is_empty());
+        assert_eq!(Keymap::default().describe()[0], MODES[0].1);
+    }
+}
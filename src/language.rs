@@ -0,0 +1,236 @@
+use {lazy_static::lazy_static, regex::Regex};
+
+// What we know about each language, looked up by file extension.
+pub struct Language {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub line_comment: Option<&'static str>,
+    // characters that are part of words along with letters, digits and underscores, for word
+    // motions, which config can override per filetype
+    pub word_chars: &'static str,
+}
+
+pub static LANGUAGES: &[Language] = &[
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "c++",
+        extensions: &["cc", "cpp", "hpp", "cxx"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "go",
+        extensions: &["go"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "java",
+        extensions: &["java"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "kotlin",
+        extensions: &["kt"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "scala",
+        extensions: &["scala"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "swift",
+        extensions: &["swift"],
+        line_comment: Some("//"),
+        word_chars: "",
+    },
+    Language {
+        name: "javascript",
+        extensions: &["js", "jsx", "mjs"],
+        line_comment: Some("//"),
+        word_chars: "$",
+    },
+    Language {
+        name: "typescript",
+        extensions: &["ts", "tsx"],
+        line_comment: Some("//"),
+        word_chars: "$",
+    },
+    Language {
+        name: "php",
+        extensions: &["php"],
+        line_comment: Some("//"),
+        word_chars: "$",
+    },
+    Language {
+        name: "css",
+        extensions: &["css", "scss", "less"],
+        // only block comments
+        line_comment: None,
+        word_chars: "-",
+    },
+    Language {
+        name: "python",
+        extensions: &["py"],
+        line_comment: Some("#"),
+        word_chars: "",
+    },
+    Language {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh", "fish"],
+        line_comment: Some("#"),
+        word_chars: "$",
+    },
+    Language {
+        name: "ruby",
+        extensions: &["rb"],
+        line_comment: Some("#"),
+        word_chars: "",
+    },
+    Language {
+        name: "toml",
+        extensions: &["toml"],
+        line_comment: Some("#"),
+        word_chars: "",
+    },
+    Language {
+        name: "yaml",
+        extensions: &["yaml", "yml"],
+        line_comment: Some("#"),
+        word_chars: "",
+    },
+    Language {
+        name: "config",
+        extensions: &["conf"],
+        line_comment: Some("#"),
+        word_chars: "",
+    },
+    Language {
+        name: "ini",
+        extensions: &["ini"],
+        line_comment: Some(";"),
+        word_chars: "",
+    },
+    Language {
+        name: "haskell",
+        extensions: &["hs"],
+        line_comment: Some("--"),
+        word_chars: "",
+    },
+    Language {
+        name: "lua",
+        extensions: &["lua"],
+        line_comment: Some("--"),
+        word_chars: "",
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        line_comment: Some("--"),
+        word_chars: "",
+    },
+    Language {
+        name: "elm",
+        extensions: &["elm"],
+        line_comment: Some("--"),
+        word_chars: "",
+    },
+    Language {
+        name: "lisp",
+        extensions: &["lisp", "el", "clj", "cljs", "scm", "rkt"],
+        line_comment: Some(";"),
+        word_chars: "-?!*",
+    },
+    Language {
+        name: "tex",
+        extensions: &["tex"],
+        line_comment: Some("%"),
+        word_chars: "",
+    },
+    Language {
+        name: "erlang",
+        extensions: &["erl"],
+        line_comment: Some("%"),
+        word_chars: "",
+    },
+    // commit messages, rebase todo lists and the like, see temp_file.rs
+    Language {
+        name: "git",
+        extensions: &[],
+        line_comment: Some("#"),
+        word_chars: "",
+    },
+    Language {
+        name: "text",
+        extensions: &["txt", "md"],
+        line_comment: None,
+        word_chars: "",
+    },
+];
+
+// files we don't recognise keep the C style comments that used to be highlighted everywhere
+pub static DEFAULT: Language = Language {
+    name: "default",
+    extensions: &[],
+    line_comment: Some("//"),
+    word_chars: "",
+};
+
+lazy_static! {
+    // highlighting for the comment syntax of each language, in the same order as LANGUAGES
+    static ref COMMENTS: Vec<Option<Regex>> = LANGUAGES
+        .iter()
+        .map(comment_regex)
+        .collect();
+    static ref DEFAULT_COMMENT: Option<Regex> = comment_regex(&DEFAULT);
+}
+
+fn comment_regex(language: &Language) -> Option<Regex> {
+    language
+        .line_comment
+        .map(|marker| Regex::new(&format!("{}.*", regex::escape(marker))).unwrap())
+}
+
+impl Language {
+    pub fn from_filetype(filetype: Option<&str>) -> &'static Language {
+        filetype
+            .and_then(|ext| {
+                LANGUAGES
+                    .iter()
+                    .find(|language| language.extensions.contains(&ext))
+            })
+            .unwrap_or(&DEFAULT)
+    }
+
+    pub fn from_name(name: &str) -> &'static Language {
+        LANGUAGES
+            .iter()
+            .find(|language| language.name == name)
+            .unwrap_or(&DEFAULT)
+    }
+
+    pub fn comment(&'static self) -> Option<&'static Regex> {
+        match LANGUAGES
+            .iter()
+            .position(|language| std::ptr::eq(language, self))
+        {
+            Some(i) => COMMENTS[i].as_ref(),
+            None => DEFAULT_COMMENT.as_ref(),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+pub mod annotate;
+pub mod announce;
+pub mod ansi;
+pub mod bench;
+pub mod block;
+pub mod browse;
+pub mod buffer;
+pub mod cancel;
+pub mod command;
+pub mod comment;
+pub mod completions;
+pub mod config;
+pub mod coverage;
+pub mod crypt;
+pub mod dialog;
+pub mod disk;
+pub mod edit;
+pub mod exec;
+pub mod expand;
+pub mod file_drop;
+pub mod git;
+pub mod grep;
+pub mod highlight;
+pub mod hover;
+pub mod idle;
+pub mod indent;
+pub mod invariant;
+pub mod keymap;
+pub mod language;
+pub mod line;
+pub mod log;
+pub mod macros;
+pub mod mark;
+pub mod minimap;
+pub mod operator;
+pub mod pager;
+pub mod palette;
+pub mod path_prompt;
+pub mod recent;
+pub mod recovery;
+pub mod replace;
+pub mod ring;
+pub mod screen;
+pub mod search;
+pub mod selections;
+pub mod session;
+pub mod sign;
+pub mod sort;
+pub mod state;
+pub mod status;
+pub mod temp_file;
+pub mod transaction;
+pub mod trust;
+pub mod tutor;
+pub mod typeahead;
+pub mod ui;
+pub mod undo;
+pub mod virtual_edit;
+pub mod window;
+pub mod zen;
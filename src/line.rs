@@ -1,29 +1,60 @@
-use {lazy_static::lazy_static, regex::Regex, std::collections::HashSet};
-
-lazy_static! {
-    static ref COMMENT: Regex = Regex::new("//.*").unwrap();
-}
+use {
+    regex::Regex,
+    std::{collections::HashSet, str},
+};
 
 pub struct Annotations {
     pub matches: Vec<(usize, usize)>,
     pub match_indices: HashSet<usize>,
     pub comment_indices: HashSet<usize>,
+    // placeholders for invalid UTF-8, with the original bytes to write back
+    pub invalid: Vec<(usize, Vec<u8>)>,
 }
 
 pub struct Line(pub String, pub Annotations);
 
 impl Line {
-    pub fn new(s: String, re: Option<&Regex>) -> Self {
-        let a = Annotations {
-            matches: Vec::new(),
-            match_indices: HashSet::new(),
-            comment_indices: COMMENT.find_iter(&s).flat_map(|m| m.range()).collect(),
-        };
-        let mut line = Line(s, a);
+    // comment matches the language's line comments, and re the search
+    pub fn new(s: String, comment: Option<&Regex>, re: Option<&Regex>) -> Self {
+        let mut line = Line::plain(s);
+        line.highlight_comments(comment);
         line.annotate(re);
         line
     }
 
+    pub fn highlight_comments(&mut self, comment: Option<&Regex>) {
+        self.1.comment_indices = match comment {
+            Some(comment) => comment.find_iter(&self.0).flat_map(|m| m.range()).collect(),
+            None => HashSet::new(),
+        };
+    }
+
+    // a line without syntax highlighting, for files too large to highlight
+    pub fn plain(s: String) -> Self {
+        Line(
+            s,
+            Annotations {
+                matches: Vec::new(),
+                match_indices: HashSet::new(),
+                comment_indices: HashSet::new(),
+                invalid: Vec::new(),
+            },
+        )
+    }
+
+    // the line as it should be written to disk, with the original bytes of any placeholders
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut x = 0;
+        for (i, original) in &self.1.invalid {
+            bytes.extend(&self.0.as_bytes()[x..*i]);
+            bytes.extend(original);
+            x = i + char::REPLACEMENT_CHARACTER.len_utf8();
+        }
+        bytes.extend(&self.0.as_bytes()[x..]);
+        bytes
+    }
+
     pub fn annotate(&mut self, re: Option<&Regex>) {
         self.1.matches.clear();
         self.1.match_indices.clear();
@@ -35,3 +66,72 @@ impl Line {
         }
     }
 }
+
+// Splits a file into lines, without their line endings, and says whether the last one had one.
+// A trailing newline ends the last line rather than starting another, and an empty file is one
+// empty line without one, so that there's always a line to be on and it's written back empty.
+fn split(bytes: &[u8]) -> (Vec<&[u8]>, bool) {
+    let mut lines: Vec<&[u8]> = bytes.split(|&b| b == b'\n').collect();
+    let eol = bytes.ends_with(b"\n");
+    if eol {
+        lines.pop();
+    }
+    (lines, eol)
+}
+
+// How a file's lines end: whether the last one has a newline (None for an empty file, which
+// has no last line to end either way), whether they end in \r\n (None for a file without any
+// newlines to go by), and whether some do and some don't.
+pub struct Endings {
+    pub eol: Option<bool>,
+    pub crlf: Option<bool>,
+    pub mixed: bool,
+}
+
+// Splits a file into decoded lines. The \r of \r\n is only taken off when every line has one,
+// so that a file that mixes them, or has the odd stray \r, is written back the way it was.
+#[allow(clippy::type_complexity)]
+pub fn decode_file(bytes: &[u8]) -> (Vec<(String, Vec<(usize, Vec<u8>)>)>, Endings) {
+    let (lines, eol) = split(bytes);
+    // the lines that ended in \n, which is all of them but an unfinished last one
+    let ended = if eol { lines.len() } else { lines.len() - 1 };
+    let with_cr = lines[..ended]
+        .iter()
+        .filter(|line| line.ends_with(b"\r"))
+        .count();
+    let crlf = (ended > 0).then_some(with_cr == ended);
+    let lines = lines
+        .iter()
+        .enumerate()
+        .map(|(y, &line)| match line.strip_suffix(b"\r") {
+            Some(stripped) if crlf == Some(true) && y < ended => decode(stripped),
+            _ => decode(line),
+        })
+        .collect();
+    let mixed = with_cr > 0 && with_cr < ended;
+    let eol = (!bytes.is_empty()).then_some(eol);
+    (lines, Endings { eol, crlf, mixed })
+}
+
+// Decodes a line of a file, replacing each invalid UTF-8 sequence with U+FFFD and recording
+// where the replacement went and which bytes it stands for.
+pub fn decode(mut bytes: &[u8]) -> (String, Vec<(usize, Vec<u8>)>) {
+    let mut s = String::new();
+    let mut invalid = Vec::new();
+    loop {
+        match str::from_utf8(bytes) {
+            Ok(valid) => {
+                s.push_str(valid);
+                return (s, invalid);
+            }
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                s.push_str(str::from_utf8(valid).unwrap());
+                let len = err.error_len().unwrap_or(rest.len());
+                invalid.push((s.len(), rest[..len].to_vec()));
+                s.push(char::REPLACEMENT_CHARACTER);
+                bytes = &rest[len..];
+            }
+        }
+    }
+}
@@ -1,27 +1,47 @@
 use {
+    crate::config,
     lazy_static::lazy_static,
     std::{
-        fs::{File, OpenOptions},
-        sync::Mutex,
+        fs::{self, File, OpenOptions},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex,
+        },
     },
 };
 
+// set for --private, before anything is logged, so that the file is never created
+pub static DISABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+// Kept in the state directory rather than wherever vee was started, which is often a repo. If
+// there's nowhere to put it, nothing is logged.
+fn open() -> Option<File> {
+    let path = config::state_path("log")?;
+    fs::create_dir_all(path.parent()?).ok()?;
+    OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)
+        .ok()
+}
+
 lazy_static! {
-    pub static ref LOG: Mutex<File> = Mutex::new(
-        OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(".vee.log")
-            .unwrap()
-    );
+    pub static ref LOG: Mutex<Option<File>> = Mutex::new(open());
 }
 
+#[macro_export]
 macro_rules! log {
     ($($t:tt)*) => {{
         use ::std::io::Write;
-        writeln!($crate::log::LOG.lock().unwrap(), $($t)*).unwrap();
+        if !$crate::log::DISABLED.load(::std::sync::atomic::Ordering::Relaxed) {
+            if let Some(log) = &mut *$crate::log::LOG.lock().unwrap() {
+                writeln!(log, $($t)*).unwrap();
+            }
+        }
     }}
 }
-
-pub(crate) use log;
@@ -0,0 +1,72 @@
+use {crate::state::State, crossterm::event::KeyEvent, std::collections::HashMap};
+
+// Keyboard macros are raw key events, replayed through the same dispatch as typed keys. They're
+// named by register, or None for the default macro when no register is chosen.
+#[derive(Default)]
+pub struct Macros {
+    recording: Option<(Option<char>, Vec<KeyEvent>)>,
+    saved: HashMap<Option<char>, Vec<KeyEvent>>,
+    playing: bool,
+}
+
+impl Macros {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // called for every key handled, but keys fed back in by playback aren't recorded again
+    pub fn capture(&mut self, event: KeyEvent) {
+        if self.playing {
+            return;
+        }
+        if let Some((_, events)) = &mut self.recording {
+            events.push(event);
+        }
+    }
+}
+
+impl State {
+    pub fn toggle_macro_recording(&mut self) {
+        match self.macros.recording.take() {
+            Some((register, mut events)) => {
+                // the key that stopped recording
+                events.pop();
+                self.message = Some(format!("Recorded {} keys", events.len()));
+                self.macros.saved.insert(register, events);
+            }
+            None => {
+                self.macros.recording = Some((self.register, Vec::new()));
+                self.message = Some(match self.register {
+                    Some(c) => format!("Recording macro into {}", c),
+                    None => "Recording macro".to_string(),
+                });
+            }
+        }
+    }
+
+    pub fn play_macro(&mut self) {
+        if self.macros.playing {
+            // a macro that plays a macro would never finish if it plays itself
+            return;
+        }
+        let events = match self.macros.saved.get(&self.register) {
+            Some(events) => events.clone(),
+            None => {
+                self.message = Some("No macro recorded".to_string());
+                return;
+            }
+        };
+        self.register = None;
+        let count = self.count.unwrap_or(1);
+        self.count = None;
+        self.macros.playing = true;
+        'outer: for _ in 0..count {
+            for &event in &events {
+                if !self.handle(event) {
+                    break 'outer;
+                }
+            }
+        }
+        self.macros.playing = false;
+    }
+}
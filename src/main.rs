@@ -1,25 +1,35 @@
 mod defer;
-mod line;
-mod log;
-mod state;
-mod ui;
 
 use {
-    anyhow::{anyhow, Result},
+    anyhow::{bail, Result},
     crossterm::{
-        cursor,
-        event::{self, Event},
-        execute, terminal,
+        event::{self, Event, MouseButton, MouseEventKind},
+        terminal,
     },
     defer::defer,
-    log::log,
-    state::State,
-    std::{env, io, panic},
+    std::{env, io, panic, path::Path, process, time::Instant},
+    vee::{
+        annotate::{self, Server},
+        announce, bench, completions,
+        config::Config,
+        exec, file_drop,
+        idle::Task,
+        keymap::Keymap,
+        log,
+        recent::Recent,
+        screen,
+        session::Session,
+        state::{Point, State},
+        typeahead::{self, Typeahead},
+        ui,
+    },
 };
 
 fn main() -> Result<()> {
-    // Normal panic reporting gets mangled when we're in raw mode, so write to log instead
+    // Normal panic reporting gets mangled when we're in raw mode, so restore the terminal
+    // first and write to log as well
     panic::set_hook(Box::new(|panic_info| {
+        screen::restore();
         match (
             panic_info.location(),
             panic_info.payload().downcast_ref::<&str>(),
@@ -30,26 +40,203 @@ fn main() -> Result<()> {
             (None, None) => log!("PANIC ? ?"),
         };
     }));
-    terminal::enable_raw_mode()?;
-    defer! { terminal::disable_raw_mode().unwrap(); }
-    execute!(io::stdout(), terminal::EnterAlternateScreen)?;
-    defer! { execute!(io::stdout(), terminal::LeaveAlternateScreen).unwrap(); }
-    let mut s = State::new(env::args().nth(1).ok_or(anyhow!("File required"))?)?;
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // --private goes with any of the others, and leaves nothing behind on disk
+    let private = args.iter().any(|arg| arg == "--private");
+    // as do --no-color and --accessible, which override the config
+    let no_color = args.iter().any(|arg| arg == "--no-color");
+    let accessible = args.iter().any(|arg| arg == "--accessible");
+    // We always wait for the file to be closed, but EDITOR is sometimes set with --wait for
+    // editors that don't, so it's accepted and ignored.
+    args.retain(|arg| {
+        !["--private", "--no-color", "--accessible", "--wait"].contains(&arg.as_str())
+    });
+    if private {
+        log::disable();
+    }
+    if args.first().map(String::as_str) == Some("completions") {
+        return match args.get(1) {
+            Some(shell) => {
+                print!("{}", completions::script(shell)?);
+                Ok(())
+            }
+            None => bail!("Usage: vee completions SHELL"),
+        };
+    }
+    // +LINE starts on that line, counting from 1
+    let line = match args.iter().position(|arg| arg.starts_with('+')) {
+        Some(i) => match args.remove(i)[1..].parse::<usize>() {
+            Ok(line) => Some(line),
+            Err(err) => bail!("Invalid line: {}", err),
+        },
+        None => None,
+    };
+    let mut config = Config::load()?;
+    // unsaved changes are only ever in memory, and what a file was before saving is gone
+    if private {
+        config.recovery = false;
+        config.backup = false;
+    }
+    if no_color {
+        config.color = false;
+    }
+    if accessible {
+        config.make_accessible();
+    }
+    let keymap = Keymap::load()?;
+    if args.first().map(String::as_str) == Some("--bench") {
+        return bench::run(args.get(1).cloned(), config);
+    }
+    if args.first().map(String::as_str) == Some("--exec") {
+        return match (args.get(1), args.get(2)) {
+            (Some(script), Some(file)) => exec::run(script, file.clone(), config, keymap),
+            _ => bail!("Usage: vee --exec SCRIPT FILE"),
+        };
+    }
+    screen::restore_on_signals()?;
+    screen::enter()?;
+    defer! { screen::restore(); }
+    let mut s = match args.first().cloned() {
+        Some(arg) if arg == "--tutor" => State::tutor(config, keymap),
+        Some(arg) if arg == "--encrypt" => match args.get(1).cloned() {
+            Some(file) => State::open(file, true, config, keymap)?,
+            None => bail!("File required"),
+        },
+        Some(dir) if Path::new(&dir).is_dir() => State::open_dir(dir, config, keymap),
+        Some(file) => State::open(file, false, config, keymap)?,
+        // picking up where we left off
+        None => match State::restore_session(config, keymap)? {
+            Some(s) => s,
+            None => bail!("File required\n\n{}", completions::usage()),
+        },
+    };
+    s.check_recovery();
+    s.idle.schedule(Task::GitStatus);
+    if let Some(line) = line {
+        let point = s.clamp(Point {
+            x: 0,
+            y: line.saturating_sub(1),
+        });
+        s.move_cursor(point);
+    }
+    // without history files, history is only kept until we exit
+    if !private {
+        s.palette.load_history()?;
+        // search patterns are kept in the state directory, one per line
+        s.search_history = Recent::load("search_history")?;
+        s.session = Session::project();
+    }
+    // repositories that have been trusted are kept in trusted, next to search_history
+    s.trusted = Some(if private {
+        Recent::default()
+    } else {
+        Recent::load("trusted")?
+    });
+    // asking now, on opening, rather than once something needs to run
+    let file = s.file.clone();
+    s.trusts(&file);
+    // tools run from inside the editor find the socket through VEE_SOCKET
+    let server = if private || !annotate::listener().available() {
+        None
+    } else {
+        match Server::start() {
+            Ok(server) => {
+                env::set_var("VEE_SOCKET", server.path());
+                Some(server)
+            }
+            Err(err) => {
+                s.message = Some(format!("Couldn't listen for annotations: {}", err));
+                None
+            }
+        }
+    };
+    let mut channel = announce::Channel::open(s.config.announce.as_deref())?;
     let mut out = io::stdout();
-    execute!(out, cursor::SetCursorShape(cursor::CursorShape::Line))?;
-    let mut size = terminal::size()?;
-    ui::draw(&mut out, &s, size)?;
+    s.resize(terminal::size()?);
+    if s.config.accessible {
+        s.announce(&mut channel)?;
+    }
+    ui::draw(&mut out, &mut s)?;
+    let mut typeahead = Typeahead::default();
     loop {
-        match event::read()? {
+        let event = match typeahead.pop() {
+            Some(event) => event,
+            None => {
+                if let Some(server) = &server {
+                    if s.serve_annotations(server) {
+                        ui::draw(&mut out, &mut s)?;
+                    }
+                }
+                if !s.idle.is_empty() && !event::poll(s.idle.delay())? {
+                    if s.run_idle() {
+                        ui::draw(&mut out, &mut s)?;
+                    }
+                    continue;
+                }
+                if server.is_some() && !event::poll(annotate::POLL)? {
+                    continue;
+                }
+                let event = event::read()?;
+                typeahead.read_pending()?;
+                if let Some((path, text)) = typeahead
+                    .typed(&event)
+                    .and_then(|text| Some((file_drop::dropped_path(&text)?, text)))
+                {
+                    if s.offer_drop(path, text) {
+                        typeahead.clear();
+                        ui::draw(&mut out, &mut s)?;
+                        continue;
+                    }
+                }
+                event
+            }
+        };
+        match event {
             Event::Key(event) => {
+                let start = Instant::now();
                 if !s.handle(event) {
                     break;
                 }
+                if start.elapsed() > typeahead::SLOW {
+                    typeahead.read_pending()?;
+                    if typeahead.take_cancel() {
+                        s.interrupt();
+                    }
+                }
             }
-            Event::Mouse(_) => continue,
-            Event::Resize(x, y) => size = (x, y),
+            Event::Mouse(event) => match event.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    if !s.grab_divider(event.column, event.row) {
+                        s.click(event.column, event.row);
+                    }
+                }
+                MouseEventKind::Drag(MouseButton::Left) if s.dragging.is_some() => {
+                    s.drag_divider(event.column, event.row)
+                }
+                MouseEventKind::Up(MouseButton::Left) if s.dragging.is_some() => s.drop_divider(),
+                MouseEventKind::Moved => {
+                    if !s.hover(event.column, event.row) {
+                        continue;
+                    }
+                }
+                _ => continue,
+            },
+            Event::Resize(x, y) => s.resize((x, y)),
         }
-        ui::draw(&mut out, &s, size)?;
+        typeahead.read_pending()?;
+        if typeahead.is_empty() {
+            if s.config.accessible {
+                s.announce(&mut channel)?;
+            }
+            ui::draw(&mut out, &mut s)?;
+        }
+    }
+    let status = s.exit_status();
+    if status != 0 {
+        // exiting here skips the cleanup on the way out of main, so it's done first
+        drop(server);
+        screen::restore();
+        process::exit(status);
     }
     Ok(())
 }
@@ -0,0 +1,71 @@
+use {
+    crate::state::{self, Point, State},
+    std::collections::HashMap,
+};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct MarkId(usize);
+
+// Positions that move with the text around them, so that features can hold on to a place in
+// the file across edits. Marks inside replaced text collapse to the start of the replacement.
+#[derive(Default)]
+pub struct Marks {
+    next: usize,
+    points: HashMap<MarkId, Point>,
+}
+
+impl Marks {
+    pub fn add(&mut self, point: Point) -> MarkId {
+        let id = MarkId(self.next);
+        self.next += 1;
+        self.points.insert(id, point);
+        id
+    }
+
+    pub fn get(&self, id: MarkId) -> Option<Point> {
+        self.points.get(&id).copied()
+    }
+
+    pub fn remove(&mut self, id: MarkId) {
+        self.points.remove(&id);
+    }
+
+    pub fn map(&mut self, f: impl Fn(Point) -> Point) {
+        for point in self.points.values_mut() {
+            *point = f(*point);
+        }
+    }
+
+    // called by splice for every edit
+    pub fn shift(&mut self, start: Point, end: Point, new_end: Point) {
+        self.map(|point| state::shift(point, start, end, new_end));
+    }
+}
+
+impl State {
+    // remembers the cursor under the chosen register, or the default mark without one
+    pub fn set_mark(&mut self) {
+        let register = self.register;
+        if let Some(id) = self.bookmarks.remove(&register) {
+            self.marks.remove(id);
+        }
+        let cursor = self.cursor.into();
+        let id = self.marks.add(cursor);
+        self.bookmarks.insert(register, id);
+        self.message = Some(match self.register {
+            Some(c) => format!("Set mark {}", c),
+            None => "Set mark".to_string(),
+        });
+    }
+
+    pub fn jump_to_mark(&mut self) {
+        match self
+            .bookmarks
+            .get(&self.register)
+            .and_then(|&id| self.marks.get(id))
+        {
+            Some(point) => self.move_cursor(point),
+            None => self.message = Some("No mark set".to_string()),
+        }
+    }
+}
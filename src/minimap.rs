@@ -0,0 +1,105 @@
+use crate::state::{Point, State};
+
+// columns taken by the minimap itself, not counting the space separating it from the text
+pub const WIDTH: u16 = 10;
+// text columns covered by each minimap column
+const SCALE: usize = 4;
+
+pub struct MinimapRow {
+    pub cells: String,
+    // covers some of the lines on screen
+    pub viewport: bool,
+    // covers a search match
+    pub matches: bool,
+}
+
+impl State {
+    pub fn toggle_minimap(&mut self) {
+        self.config.minimap = !self.config.minimap;
+    }
+
+    // Columns taken up by the minimap, on the right of the text. It's left out of zen mode and
+    // large files, where scanning every line on every draw would be too slow, and when there
+    // wouldn't be much room left for the text.
+    pub fn minimap_width(&self) -> u16 {
+        if self.config.minimap && !self.zen && !self.large && self.size.0 >= 4 * (WIDTH + 1) {
+            WIDTH + 1
+        } else {
+            0
+        }
+    }
+
+    // Each row of the minimap has a top and bottom half, drawn with half blocks, and each half
+    // covers this many lines, so that the whole file fits.
+    fn lines_per_half(&self) -> usize {
+        usize::max(1, self.text.len().div_ceil(2 * self.height().max(1)))
+    }
+
+    // which minimap columns have something other than whitespace in them, over these lines
+    fn filled(&self, ys: impl Iterator<Item = usize>) -> [bool; WIDTH as usize] {
+        let mut filled = [false; WIDTH as usize];
+        for y in ys {
+            for (i, c) in self.text[y]
+                .0
+                .chars()
+                .take(WIDTH as usize * SCALE)
+                .enumerate()
+            {
+                if !c.is_whitespace() {
+                    filled[i / SCALE] = true;
+                }
+            }
+        }
+        filled
+    }
+
+    pub fn minimap(&self) -> Vec<MinimapRow> {
+        let per = self.lines_per_half();
+        let visible = self.visible();
+        (0..self.height())
+            .map(|row| row * 2 * per)
+            .take_while(|&start| start < self.text.len())
+            .map(|start| {
+                let mid = usize::min(start + per, self.text.len());
+                let end = usize::min(start + 2 * per, self.text.len());
+                let (top, bottom) = (self.filled(start..mid), self.filled(mid..end));
+                let cells = top
+                    .iter()
+                    .zip(bottom)
+                    .map(|(&top, bottom)| match (top, bottom) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    })
+                    .collect();
+                MinimapRow {
+                    cells,
+                    viewport: start < visible.end && visible.start < end,
+                    matches: (start..end).any(|y| !self.text[y].1.matches.is_empty()),
+                }
+            })
+            .collect()
+    }
+
+    // the first line covered by the minimap at the given screen position, if any
+    pub fn minimap_line(&self, column: u16, row: u16) -> Option<usize> {
+        let width = self.minimap_width();
+        if width == 0 || column < self.size.0 - WIDTH || row as usize >= self.height() {
+            return None;
+        }
+        let y = row as usize * 2 * self.lines_per_half();
+        (y < self.text.len()).then_some(y)
+    }
+
+    pub fn click_minimap(&mut self, column: u16, row: u16) -> bool {
+        match self.minimap_line(column, row) {
+            Some(y) => {
+                self.anchor = None;
+                self.move_cursor(Point { x: 0, y });
+                true
+            }
+            None => false,
+        }
+    }
+}
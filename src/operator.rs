@@ -0,0 +1,90 @@
+use crate::{
+    edit::EditOp,
+    state::{Mode, Point, State},
+};
+
+// Operators are the verbs of the grammar. Selection commands pick the object (or a motion
+// after `s` extends one), then an operator acts on whatever is selected. With no selection,
+// operators act on the character under the cursor.
+#[derive(Clone, Copy)]
+pub enum Operator {
+    Yank,
+    Delete,
+    // delete, then insert in its place
+    Change,
+    Uppercase,
+    Lowercase,
+    SwapCase,
+    // comments out the target's lines, or uncomments them, see comment.rs
+    Comment,
+}
+
+// Unicode aware, so a single character can become several (ß uppercases to SS)
+pub fn swap_case(text: &str) -> String {
+    let mut swapped = String::new();
+    for c in text.chars() {
+        if c.is_lowercase() {
+            swapped.extend(c.to_uppercase());
+        } else if c.is_uppercase() {
+            swapped.extend(c.to_lowercase());
+        } else {
+            swapped.push(c);
+        }
+    }
+    swapped
+}
+
+impl Operator {
+    fn apply(self, s: &mut State, start: Point, end: Point) {
+        match self {
+            Operator::Yank => s.kill(s.text_between(start, end)),
+            Operator::Delete => {
+                // an empty target, like a cursor past the end of its line, has nothing to kill
+                if start != end {
+                    s.kill(s.text_between(start, end));
+                    s.splice(start, end, "");
+                }
+                s.anchor = None;
+            }
+            Operator::Change => {
+                Operator::Delete.apply(s, start, end);
+                // not begin_edit, which would record a separate edit
+                s.mode = Mode::Insert;
+            }
+            Operator::Uppercase => s.transform(start, end, str::to_uppercase),
+            Operator::Lowercase => s.transform(start, end, str::to_lowercase),
+            Operator::SwapCase => s.transform(start, end, swap_case),
+            // not the line the end of a selection of whole lines is at the start of
+            Operator::Comment if end.y > start.y && end.x == 0 => {
+                s.comment_lines(start.y, end.y - 1)
+            }
+            Operator::Comment => s.comment_lines(start.y, end.y),
+        }
+    }
+}
+
+impl State {
+    pub fn target(&self) -> (Point, Point) {
+        match self.selection() {
+            Some((start, end)) => (start.into(), end.into()),
+            // nothing under a cursor past the end of its line
+            None if self.virtual_columns() > 0 => (self.cursor.into(), self.cursor.into()),
+            None => {
+                let cursor = self.cursor.into();
+                (cursor, self.next_point(cursor).unwrap_or(cursor))
+            }
+        }
+    }
+
+    pub fn operate(&mut self, op: Operator) {
+        if let Some(block) = self.block() {
+            return self.operate_block(op, block);
+        }
+        let (start, end) = self.target();
+        op.apply(self, start, end);
+        // after applying, so that a change keeps recording through the insert that follows
+        if !matches!(op, Operator::Yank) {
+            self.record(EditOp::Operate(op));
+        }
+    }
+}
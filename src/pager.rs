@@ -0,0 +1,62 @@
+use {
+    crate::state::{Mode, Point, State},
+    std::{ops::Range, path::PathBuf},
+};
+
+// Read only text shown in place of the file, for output too long for the message line. Some
+// lines can also be places in files, like grep results, to choose between and open, with the
+// lines around them for context.
+pub struct Pager {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub top: usize,
+    // in the order of their lines, along with the one chosen
+    pub targets: Vec<Target>,
+    pub selected: usize,
+    // the directory listed, see browse.rs
+    pub dir: Option<PathBuf>,
+}
+
+pub struct Target {
+    // the pager line it's listed on, and the part of that to highlight
+    pub line: usize,
+    pub span: Range<usize>,
+    pub file: String,
+    pub point: Point,
+}
+
+impl State {
+    pub fn open_pager(&mut self, title: String, lines: Vec<String>) {
+        self.pager = Some(Pager {
+            title,
+            lines,
+            top: 0,
+            targets: Vec::new(),
+            selected: 0,
+            dir: None,
+        });
+        self.mode = Mode::Pager;
+    }
+
+    pub fn close_pager(&mut self) {
+        self.pager = None;
+        self.mode = Mode::Normal;
+    }
+
+    // Scrolls by dy lines or, for a list of places, moves the choice by dy and scrolls to keep it
+    // on screen.
+    pub fn scroll_pager(&mut self, dy: isize) {
+        let height = self.height();
+        if let Some(pager) = &mut self.pager {
+            if pager.targets.is_empty() {
+                let max = pager.lines.len().saturating_sub(height);
+                pager.top = usize::min(pager.top.saturating_add_signed(dy), max);
+            } else {
+                let last = pager.targets.len() - 1;
+                pager.selected = usize::min(pager.selected.saturating_add_signed(dy), last);
+                let line = pager.targets[pager.selected].line;
+                pager.top = pager.top.clamp((line + 1).saturating_sub(height), line);
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use {
+    crate::{
+        command,
+        recent::Recent,
+        state::{Mode, State},
+    },
+    anyhow::Result,
+};
+
+// The command palette's input line, edited readline style, and the commands run from it.
+#[derive(Default)]
+pub struct Palette {
+    pub input: String,
+    // byte index into input
+    pub cursor: usize,
+    history: Recent,
+    // the entry shown while stepping through history with up and down
+    recall: Option<usize>,
+    // the reverse search query, and the entry it matched
+    pub search: Option<(String, Option<usize>)>,
+}
+
+impl Palette {
+    // history is kept in the state directory, one command per line
+    pub fn load_history(&mut self) -> Result<()> {
+        self.history = Recent::load("history")?;
+        Ok(())
+    }
+
+    fn set_input(&mut self, input: String) {
+        self.cursor = input.len();
+        self.input = input;
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.input[..self.cursor]
+            .char_indices()
+            .last()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.input[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    // finds the most recent entry before `before` containing the query
+    fn search_again(&mut self, before: usize) {
+        if let Some((query, found)) = &mut self.search {
+            if let Some(i) = self.history.entries()[..before]
+                .iter()
+                .rposition(|entry| entry.contains(query.as_str()))
+            {
+                *found = Some(i);
+                let entry = self.history.entries()[i].clone();
+                self.set_input(entry);
+            }
+        }
+    }
+}
+
+impl State {
+    pub fn open_palette(&mut self) {
+        self.palette.set_input(String::new());
+        self.palette.recall = None;
+        self.palette.search = None;
+        self.mode = Mode::Command;
+    }
+
+    pub fn close_palette(&mut self) {
+        self.palette.set_input(String::new());
+        self.palette.search = None;
+        self.mode = Mode::Normal;
+    }
+
+    // Esc stops a search, keeping what it found, and otherwise closes the palette
+    pub fn cancel_palette(&mut self) {
+        if self.palette.search.take().is_none() {
+            self.close_palette();
+        }
+    }
+
+    pub fn run_palette(&mut self) {
+        let name = self.palette.input.trim().to_string();
+        let remembered = self.palette.history.remember(&name);
+        self.close_palette();
+        match command::lookup(&name) {
+            Some(command) => {
+                // repeating the repeat would recurse forever
+                if command.name != "repeat-last-command" {
+                    self.last_command = Some(command);
+                }
+                self.run(command);
+            }
+            None => self.message = Some(format!("Unknown command: {}", name)),
+        }
+        if let Err(err) = remembered {
+            self.message = Some(format!("Couldn't save command history: {}", err));
+        }
+    }
+
+    pub fn palette_insert(&mut self, c: char) {
+        let palette = &mut self.palette;
+        match &mut palette.search {
+            Some((query, _)) => {
+                query.push(c);
+                palette.search_again(palette.history.entries().len());
+            }
+            None => {
+                palette.input.insert(palette.cursor, c);
+                palette.cursor += c.len_utf8();
+            }
+        }
+    }
+
+    pub fn palette_backspace(&mut self) {
+        let palette = &mut self.palette;
+        match &mut palette.search {
+            Some((query, _)) => {
+                query.pop();
+                palette.search_again(palette.history.entries().len());
+            }
+            None => {
+                let prev = palette.prev_boundary();
+                palette.input.replace_range(prev..palette.cursor, "");
+                palette.cursor = prev;
+            }
+        }
+    }
+
+    pub fn palette_delete(&mut self) {
+        self.palette.search = None;
+        let palette = &mut self.palette;
+        let next = palette.next_boundary();
+        palette.input.replace_range(palette.cursor..next, "");
+    }
+
+    // deletes back to the start of the word before the cursor
+    pub fn palette_delete_word(&mut self) {
+        self.palette.search = None;
+        let palette = &mut self.palette;
+        let before = &palette.input[..palette.cursor];
+        let start = before
+            .trim_end()
+            .rfind(|c: char| !command::is_word(c) && c != '-')
+            .map_or(0, |i| i + 1);
+        palette.input.replace_range(start..palette.cursor, "");
+        palette.cursor = start;
+    }
+
+    pub fn palette_kill_start(&mut self) {
+        self.palette.search = None;
+        let palette = &mut self.palette;
+        palette.input.replace_range(..palette.cursor, "");
+        palette.cursor = 0;
+    }
+
+    // editing or moving ends a search, keeping what it found, as in a shell
+    pub fn palette_left(&mut self) {
+        self.palette.search = None;
+        self.palette.cursor = self.palette.prev_boundary();
+    }
+
+    pub fn palette_right(&mut self) {
+        self.palette.search = None;
+        self.palette.cursor = self.palette.next_boundary();
+    }
+
+    pub fn palette_home(&mut self) {
+        self.palette.search = None;
+        self.palette.cursor = 0;
+    }
+
+    pub fn palette_end(&mut self) {
+        self.palette.search = None;
+        self.palette.cursor = self.palette.input.len();
+    }
+
+    pub fn palette_prev(&mut self) {
+        let palette = &mut self.palette;
+        palette.search = None;
+        if let Some((i, entry)) = palette.history.prev(palette.recall) {
+            let entry = entry.to_string();
+            palette.recall = Some(i);
+            palette.set_input(entry);
+        }
+    }
+
+    pub fn palette_next(&mut self) {
+        let palette = &mut self.palette;
+        palette.search = None;
+        match palette.history.next(palette.recall) {
+            Some((i, entry)) => {
+                let entry = entry.to_string();
+                palette.recall = Some(i);
+                palette.set_input(entry);
+            }
+            None if palette.recall.is_some() => {
+                palette.recall = None;
+                palette.set_input(String::new());
+            }
+            None => (),
+        }
+    }
+
+    // starts a reverse search, or finds the next older match if one is going
+    pub fn palette_search(&mut self) {
+        let palette = &mut self.palette;
+        match &palette.search {
+            Some((_, found)) => {
+                let before = found.unwrap_or(palette.history.entries().len());
+                palette.search_again(before);
+            }
+            None => palette.search = Some((String::new(), None)),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use {
+    crate::state::{Mode, State},
+    std::{
+        fs, io,
+        path::{self, Path, PathBuf},
+    },
+};
+
+// What to do with the path once it's typed.
+pub enum PathAction {
+    Open,
+    // files in a directory listing, see browse.rs, with a separator on the end for a directory
+    Create,
+    Rename(String),
+    Delete,
+}
+
+// A file path being typed, to open or to make changes to.
+pub struct PathPrompt {
+    pub input: String,
+    pub action: PathAction,
+    // the directory listing to go back to afterwards, if it was started from one
+    pub listing: Option<PathBuf>,
+}
+
+impl PathPrompt {
+    pub fn label(&self) -> String {
+        match &self.action {
+            PathAction::Open => "Open".to_string(),
+            PathAction::Create => "Create".to_string(),
+            PathAction::Rename(from) => format!("Rename {} to", from),
+            PathAction::Delete => "Delete".to_string(),
+        }
+    }
+}
+
+impl State {
+    pub fn open_path_prompt(&mut self, action: PathAction, input: String) {
+        let listing = self.pager.take().and_then(|pager| pager.dir);
+        self.path_prompt = Some(PathPrompt {
+            input,
+            action,
+            listing,
+        });
+        self.mode = Mode::Path;
+    }
+
+    pub fn path_insert(&mut self, c: char) {
+        if let Some(prompt) = &mut self.path_prompt {
+            prompt.input.push(c);
+        }
+    }
+
+    pub fn path_backspace(&mut self) {
+        if let Some(prompt) = &mut self.path_prompt {
+            prompt.input.pop();
+        }
+    }
+
+    pub fn cancel_path_prompt(&mut self) {
+        self.mode = Mode::Normal;
+        if let Some(dir) = self.path_prompt.take().and_then(|prompt| prompt.listing) {
+            self.browse(dir);
+        }
+    }
+
+    pub fn run_path_prompt(&mut self) {
+        let prompt = match self.path_prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        self.mode = Mode::Normal;
+        if let Some(dir) = prompt.listing {
+            self.browse(dir);
+        }
+        let path = prompt.input;
+        if path.is_empty() {
+            return;
+        }
+        let done = match prompt.action {
+            PathAction::Open => {
+                self.close_pager();
+                self.open_file(path);
+                return;
+            }
+            PathAction::Create if path.ends_with(path::is_separator) => fs::create_dir_all(&path),
+            PathAction::Create => fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map(drop),
+            PathAction::Rename(from) => fs::rename(from, &path),
+            PathAction::Delete => {
+                let question = format!("Delete {}?", path);
+                self.deleting = Some(path);
+                self.confirm(question, State::delete_path, |_| ());
+                return;
+            }
+        };
+        self.after_path_action(&path, done);
+    }
+
+    // the yes to run_path_prompt's question, since there's no getting it back
+    fn delete_path(&mut self) {
+        let path = match self.deleting.take() {
+            Some(path) => path,
+            None => return,
+        };
+        let done = if Path::new(&path).is_dir() {
+            fs::remove_dir(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        self.after_path_action(&path, done);
+    }
+
+    fn after_path_action(&mut self, path: &str, done: io::Result<()>) {
+        match done {
+            Ok(()) => {
+                // the listing is out of date now
+                if let Some(dir) = self.pager.as_ref().and_then(|pager| pager.dir.clone()) {
+                    self.browse(dir);
+                }
+            }
+            Err(err) => self.message = Some(format!("{}: {}", path, err)),
+        }
+    }
+
+    // Completes the file name being typed as far as it goes without choosing between files,
+    // with a separator on the end of a directory.
+    pub fn complete_path(&mut self) {
+        let input = match &self.path_prompt {
+            Some(prompt) => prompt.input.clone(),
+            None => return,
+        };
+        let (dir, name) = match input.rfind(path::is_separator) {
+            Some(i) => input.split_at(i + 1),
+            None => ("", input.as_str()),
+        };
+        let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.message = Some(format!("Couldn't list {}: {}", dir, err));
+                return;
+            }
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|entry| {
+                entry.starts_with(name) && (!entry.starts_with('.') || name.starts_with('.'))
+            })
+            .collect();
+        names.sort();
+        let (first, last) = match (names.first(), names.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => {
+                self.message = Some("No matching files".to_string());
+                return;
+            }
+        };
+        // sorted, so what the first and last have in common, they all do
+        let common: String = first
+            .chars()
+            .zip(last.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect();
+        let mut completed = format!("{}{}", dir, common);
+        if names.len() == 1 && Path::new(&completed).is_dir() {
+            completed.push(path::MAIN_SEPARATOR);
+        }
+        if let Some(prompt) = &mut self.path_prompt {
+            prompt.input = completed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+        std::env,
+    };
+
+    #[test]
+    fn deleting_asks_first() {
+        let dir = env::temp_dir().join(format!("vee-delete-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("doomed");
+        fs::write(&file, "").unwrap();
+        let mut s = State::open_dir(
+            dir.to_string_lossy().into_owned(),
+            Config::default(),
+            Keymap::default(),
+        );
+        let path = file.to_string_lossy().into_owned();
+        for answer in [None, Some(false), Some(true)] {
+            assert!(file.exists());
+            s.open_path_prompt(PathAction::Delete, path.clone());
+            s.run_path_prompt();
+            assert!(s.mode == Mode::Dialog);
+            assert_eq!(
+                s.dialog.as_ref().unwrap().question,
+                format!("Delete {}?", path)
+            );
+            s.answer(answer);
+        }
+        assert!(!file.exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn directories_are_not_saved() {
+        let dir = env::temp_dir();
+        let mut s = State::open_dir(
+            dir.to_string_lossy().into_owned(),
+            Config::default(),
+            Keymap::default(),
+        );
+        s.close_pager();
+        s.insert_char('x');
+        s.save();
+        assert!(dir.is_dir());
+        assert!(s.dirty);
+    }
+}
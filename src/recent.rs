@@ -0,0 +1,76 @@
+use {
+    crate::config,
+    anyhow::Result,
+    std::{
+        fs::{self, OpenOptions},
+        io::{self, Write},
+        path::PathBuf,
+    },
+};
+
+// how many past entries to keep
+const SIZE: usize = 1000;
+
+// Lines entered at a prompt, oldest first, saved to a file in the state directory (see
+// config::state_path) one line per entry so that they're still there next time.
+#[derive(Default)]
+pub struct Recent {
+    entries: Vec<String>,
+    // where entries are saved, once they've been loaded from there
+    file: Option<PathBuf>,
+}
+
+impl Recent {
+    pub fn load(name: &str) -> Result<Self> {
+        let path = match config::state_path(name) {
+            Some(path) => path,
+            None => return Ok(Recent::default()),
+        };
+        let mut entries: Vec<String> = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(String::from).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let excess = entries.len().saturating_sub(SIZE);
+        entries.drain(..excess);
+        Ok(Recent {
+            entries,
+            file: Some(path),
+        })
+    }
+
+    pub fn remember(&mut self, line: &str) -> io::Result<()> {
+        if line.is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return Ok(());
+        }
+        self.entries.push(line.to_string());
+        if let Some(path) = &self.file {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            writeln!(
+                OpenOptions::new().create(true).append(true).open(path)?,
+                "{}",
+                line
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    // The entry before recall, stepping back from the newest one without a recall. Returns the
+    // new recall and its entry.
+    pub fn prev(&self, recall: Option<usize>) -> Option<(usize, &str)> {
+        let i = recall.unwrap_or(self.entries.len()).checked_sub(1)?;
+        Some((i, &self.entries[i]))
+    }
+
+    // the entry after recall, if there is one
+    pub fn next(&self, recall: Option<usize>) -> Option<(usize, &str)> {
+        let i = recall? + 1;
+        self.entries.get(i).map(|entry| (i, entry.as_str()))
+    }
+}
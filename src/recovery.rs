@@ -0,0 +1,167 @@
+use {
+    crate::{config, idle::Task, line, state::State},
+    std::{
+        env,
+        fs::{self, OpenOptions},
+        io::{self, Write},
+        path::{self, Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+// With config.recovery, unsaved changes are written to a file in the state directory whenever
+// there's a pause in typing, and the file is removed once they're saved or thrown away. If it's
+// still there when the file is next opened, vee must have exited without doing either, and
+// offers to restore them. Encrypted files are left out, since the copy would be in the clear,
+// and so are temp files, whose names don't outlive whatever's waiting on them.
+
+// pauses closer together than this wait for the next one, rather than each writing the file
+const INTERVAL: Duration = Duration::from_secs(2);
+
+// named after the whole path of the file, with % in place of separators
+fn recovery_path(file: &str) -> Option<PathBuf> {
+    let path = env::current_dir().ok()?.join(file);
+    let name: String = path
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if path::is_separator(c) || c == ':' {
+                '%'
+            } else {
+                c
+            }
+        })
+        .collect();
+    config::state_path("recovery").map(|dir| dir.join(name))
+}
+
+// readable only by us, like the file it's a copy of might not be
+fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(bytes)
+}
+
+pub fn discard(file: &str) {
+    if let Some(path) = recovery_path(file) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl State {
+    fn recovery_path(&self) -> Option<PathBuf> {
+        recovery_path(&self.file)
+    }
+
+    fn keeps_recovery(&self) -> bool {
+        self.config.recovery
+            && self.encryption.is_none()
+            && self.temp_file.is_none()
+            && !self.scratch
+    }
+
+    pub fn write_recovery(&mut self) {
+        if !self.keeps_recovery() {
+            return;
+        }
+        if !self.dirty {
+            self.discard_recovery();
+            return;
+        }
+        if self
+            .recovered
+            .is_some_and(|recovered| recovered.elapsed() < INTERVAL)
+        {
+            self.idle.schedule(Task::Recover);
+            return;
+        }
+        let path = match self.recovery_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let bytes = self.encode();
+        let written = match path.parent() {
+            Some(dir) => fs::create_dir_all(dir).and_then(|()| write_private(&path, &bytes)),
+            None => write_private(&path, &bytes),
+        };
+        self.recovered = Some(Instant::now());
+        if let Err(err) = written {
+            self.message = Some(format!("Error writing {}: {}", path.display(), err));
+        }
+    }
+
+    pub fn discard_recovery(&mut self) {
+        if self.keeps_recovery() {
+            discard(&self.file);
+        }
+    }
+
+    // Asks whether to restore what's left in the recovery file from last time, if there is one
+    // and it isn't what's already there.
+    pub fn check_recovery(&mut self) {
+        if !self.keeps_recovery() {
+            return;
+        }
+        let bytes = match self.recovery_path().and_then(|path| fs::read(path).ok()) {
+            Some(bytes) => bytes,
+            None => return,
+        };
+        if bytes == self.encode() {
+            self.discard_recovery();
+            return;
+        }
+        self.confirm(
+            format!(
+                "{} has unsaved changes from last time. Restore them?",
+                self.file
+            ),
+            State::restore_recovery,
+            State::discard_recovery,
+        );
+    }
+
+    // as an edit like any other, so that it can be undone, and then saved or not
+    fn restore_recovery(&mut self) {
+        let bytes = match self.recovery_path().and_then(|path| fs::read(path).ok()) {
+            Some(bytes) => bytes,
+            None => return,
+        };
+        // written the same way as saving, so read back the same way as opening
+        let (lines, endings) = line::decode_file(&bytes);
+        let (lines, invalid): (Vec<String>, Vec<_>) = lines.into_iter().unzip();
+        self.break_undo();
+        self.transform(self.start_of_file(), self.end_of_file(), |_| {
+            lines.join("\n")
+        });
+        self.break_undo();
+        for (line, invalid) in self.text.iter_mut().zip(invalid) {
+            line.1.invalid = invalid;
+        }
+        self.eol = endings.eol;
+        self.crlf = endings.crlf.unwrap_or(self.crlf);
+        self.anchor = None;
+        self.message = Some(format!("Restored unsaved changes to {}", self.file));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_privately() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = env::temp_dir().join(format!("vee-recovery-{}", std::process::id()));
+        write_private(&path, b"secret\r\n").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"secret\r\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
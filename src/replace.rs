@@ -0,0 +1,320 @@
+use {
+    crate::{
+        mark::MarkId,
+        search::{self, point_in},
+        state::{Mode, Point, State},
+        transaction::Transaction,
+    },
+    regex::{Captures, Regex},
+};
+
+// Replacing matches of the search one at a time, asking about each. The replacement is typed
+// first, in replace mode, and can refer to the search's capture groups as $1, $name, and so on.
+// Then confirm mode steps through the matches from the cursor, wrapping round to the start of
+// the file and stopping back where it began. Replacing within the selection steps through it
+// from start to end instead, and only takes matches that are entirely inside it.
+pub struct Replace {
+    pub input: String,
+    pub re: Regex,
+    // the match the cursor is on while stepping, and the text it would be replaced with
+    current: Option<(Point, Point, String)>,
+    // where stepping starts, and where it stops once it's wrapped, which moves with the text as
+    // it's edited
+    start: Point,
+    stop: MarkId,
+    // selections never wrap, so they start out wrapped
+    wrapped: bool,
+    pub selection: bool,
+    pub replaced: usize,
+}
+
+// The replacement as typed, with \n for a newline, as in search patterns, and \\ for a
+// backslash.
+fn unescape(input: &str) -> String {
+    let mut s = String::new();
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                s.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                s.push('\\');
+                chars.next();
+            }
+            _ => s.push(c),
+        }
+    }
+    s
+}
+
+impl State {
+    pub fn open_replace(&mut self, selection: bool) {
+        let re = match &self.search {
+            Some(Ok(re)) => re.clone(),
+            _ => {
+                self.message = Some("No search to replace".to_string());
+                return;
+            }
+        };
+        let (start, stop) = match (selection, self.selection()) {
+            (true, Some((start, end))) => (start.into(), end.into()),
+            (true, None) => {
+                self.message = Some("No selection to replace in".to_string());
+                return;
+            }
+            (false, _) => (self.cursor.into(), self.cursor.into()),
+        };
+        let stop = self.marks.add(stop);
+        self.replace = Some(Replace {
+            input: String::new(),
+            re,
+            current: None,
+            start,
+            stop,
+            wrapped: selection,
+            selection,
+            replaced: 0,
+        });
+        self.mode = Mode::Replace;
+    }
+
+    pub fn replace_insert(&mut self, c: char) {
+        if let Some(replace) = &mut self.replace {
+            replace.input.push(c);
+        }
+    }
+
+    pub fn replace_backspace(&mut self) {
+        if let Some(replace) = &mut self.replace {
+            replace.input.pop();
+        }
+    }
+
+    // with the replacement typed, moves to the first match to ask about it
+    pub fn start_replace(&mut self) {
+        let start = match &self.replace {
+            Some(replace) => replace.start,
+            None => return,
+        };
+        self.mode = Mode::Confirm;
+        self.step_replace(start, false);
+    }
+
+    // Moves on to the next match at or after from, or finishes if there isn't one. After an
+    // empty match, another empty match at the same place is the same one again, so strict skips
+    // it.
+    fn step_replace(&mut self, from: Point, strict: bool) {
+        let next = match &self.replace {
+            Some(replace) => self.next_replacement(replace, from, strict),
+            None => return,
+        };
+        let next = match next {
+            Some(next) => Some(next),
+            None => match &mut self.replace {
+                Some(replace) if !replace.wrapped => {
+                    replace.wrapped = true;
+                    let replace = self.replace.as_ref().unwrap();
+                    self.next_replacement(replace, Point { x: 0, y: 0 }, false)
+                }
+                _ => None,
+            },
+        };
+        match next {
+            Some((start, end, text)) => {
+                self.anchor = None;
+                self.move_cursor(start);
+                if let Some(replace) = &mut self.replace {
+                    replace.current = Some((start, end, text));
+                }
+            }
+            None => self.finish_replace(),
+        }
+    }
+
+    // The first match of the replace's pattern at or after from, short of where stepping stops
+    // once it's wrapped (or, for a selection, ending before it), and what it would be replaced
+    // with.
+    fn next_replacement(
+        &self,
+        replace: &Replace,
+        from: Point,
+        strict: bool,
+    ) -> Option<(Point, Point, String)> {
+        let stop = replace
+            .wrapped
+            .then(|| self.marks.get(replace.stop))
+            .flatten();
+        let template = unescape(&replace.input);
+        let expand = |caps: &Captures| {
+            let mut text = String::new();
+            caps.expand(&template, &mut text);
+            text
+        };
+        let after =
+            |start: Point, end: Point| start >= from && !(strict && start == from && start == end);
+        let found = if search::is_multi_line(&replace.re) {
+            let (text, starts) = self.joined();
+            replace.re.captures_iter(&text).find_map(|caps| {
+                let m = caps.get(0).unwrap();
+                let (start, end) = (point_in(&starts, m.start()), point_in(&starts, m.end()));
+                after(start, end).then(|| (start, end, expand(&caps)))
+            })
+        } else {
+            (from.y..self.text.len()).find_map(|y| {
+                replace.re.captures_iter(&self.text[y].0).find_map(|caps| {
+                    let m = caps.get(0).unwrap();
+                    let start = Point { x: m.start(), y };
+                    let end = Point { x: m.end(), y };
+                    after(start, end).then(|| (start, end, expand(&caps)))
+                })
+            })
+        };
+        found.filter(|&(start, end, _)| match stop {
+            Some(stop) if replace.selection => end <= stop,
+            Some(stop) => start < stop,
+            None => true,
+        })
+    }
+
+    pub fn replace_yes(&mut self) {
+        let current = self
+            .replace
+            .as_mut()
+            .and_then(|replace| replace.current.take());
+        if let Some((start, end, text)) = current {
+            let new_end = self.splice(start, end, &text);
+            if let Some(replace) = &mut self.replace {
+                replace.replaced += 1;
+            }
+            self.step_replace(new_end, start == end);
+        }
+    }
+
+    pub fn replace_skip(&mut self) {
+        let current = self
+            .replace
+            .as_mut()
+            .and_then(|replace| replace.current.take());
+        if let Some((start, end, _)) = current {
+            self.step_replace(end, start == end);
+        }
+    }
+
+    // replaces this match and every one after it, as a single edit
+    pub fn replace_all(&mut self) {
+        self.break_undo();
+        let mut transaction = Transaction::default();
+        // stepping on without changing anything, so every match is found in the text as it is
+        while let Some(replace) = &mut self.replace {
+            let (start, end, text) = match replace.current.take() {
+                Some(current) => current,
+                None => break,
+            };
+            transaction.replace(start, end, text);
+            replace.replaced += 1;
+            self.step_replace(end, start == end);
+        }
+        match self.apply(transaction) {
+            Ok(ranges) => {
+                if let Some(&(start, _)) = ranges.last() {
+                    self.move_cursor(start);
+                }
+            }
+            Err(err) => self.message = Some(format!("Couldn't replace: {}", err)),
+        }
+    }
+
+    fn finish_replace(&mut self) {
+        let stepping = self.mode == Mode::Confirm;
+        self.mode = Mode::Normal;
+        if let Some(replace) = self.replace.take() {
+            self.marks.remove(replace.stop);
+            if stepping {
+                self.message = Some(format!("Replaced {}", replace.replaced));
+            }
+        }
+    }
+
+    pub fn cancel_replace(&mut self) {
+        self.finish_replace();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    fn state(text: &str, pattern: &str) -> State {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            text.as_bytes(),
+            Config::default(),
+            Keymap::default(),
+        );
+        s.search = Some(Regex::new(pattern));
+        s
+    }
+
+    fn lines(s: &State) -> Vec<&str> {
+        s.text.iter().map(|line| line.0.as_str()).collect()
+    }
+
+    fn replacing(s: &mut State, selection: bool, input: &str) {
+        s.open_replace(selection);
+        for c in input.chars() {
+            s.replace_insert(c);
+        }
+        s.start_replace();
+    }
+
+    #[test]
+    fn asks_about_each_match() {
+        // the last line has no newline after it
+        let mut s = state("ab\nab\nab", "a(b)");
+        s.move_cursor(Point { x: 0, y: 1 });
+        replacing(&mut s, false, "[$1]");
+        assert!(s.mode == Mode::Confirm);
+        s.replace_yes();
+        s.replace_skip();
+        // wrapped round to the start, and stops short of where it began
+        assert_eq!(Point::from(s.cursor), Point { x: 0, y: 0 });
+        s.replace_yes();
+        assert!(s.mode == Mode::Normal && s.replace.is_none());
+        assert_eq!(lines(&s), ["[b]", "[b]", "ab"]);
+        assert_eq!(s.message.as_deref(), Some("Replaced 2"));
+        assert!(s.violations().is_empty());
+
+        let mut s = state("ab\n", "z");
+        replacing(&mut s, false, "y");
+        assert!(s.mode == Mode::Normal);
+        assert_eq!(s.message.as_deref(), Some("Replaced 0"));
+        s.search = None;
+        s.open_replace(false);
+        assert_eq!(s.message.as_deref(), Some("No search to replace"));
+    }
+
+    #[test]
+    fn replaces_all_within_the_selection() {
+        let mut s = state("aaa\naaa\n", "a");
+        s.open_replace(true);
+        assert_eq!(s.message.as_deref(), Some("No selection to replace in"));
+        assert!(s.replace.is_none());
+        s.move_cursor(Point { x: 1, y: 0 });
+        s.anchor = Some(s.cursor);
+        s.move_cursor(Point { x: 2, y: 1 });
+        replacing(&mut s, true, "b\\n");
+        s.replace_all();
+        assert_eq!(lines(&s), ["ab", "b", "", "b", "b", "a"]);
+        assert!(s.mode == Mode::Normal && s.replace.is_none());
+        assert!(s.violations().is_empty());
+        // in one edit
+        s.commit();
+        s.undo();
+        assert_eq!(lines(&s), ["aaa", "aaa"]);
+    }
+}
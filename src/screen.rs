@@ -0,0 +1,95 @@
+use {
+    anyhow::Result,
+    crossterm::{
+        cursor,
+        event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+        execute, queue, style,
+        terminal::{self, ClearType},
+    },
+    std::{
+        io::{self, Write},
+        sync::atomic::{AtomicBool, Ordering},
+    },
+};
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Puts the terminal into the state the editor needs. Pair with a deferred restore.
+pub fn enter() -> Result<()> {
+    ACTIVE.store(true, Ordering::SeqCst);
+    terminal::enable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        terminal::EnterAlternateScreen,
+        event::EnableMouseCapture,
+        cursor::SetCursorShape(cursor::CursorShape::Line),
+    )?;
+    Ok(())
+}
+
+// Puts the terminal back how we found it. This runs from the panic hook and signal handlers as
+// well as on a normal exit, so it's safe to call more than once and never panics.
+pub fn restore() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        let _ = execute!(
+            io::stdout(),
+            cursor::SetCursorShape(cursor::CursorShape::Block),
+            event::DisableMouseCapture,
+            terminal::LeaveAlternateScreen,
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Restores the terminal before exiting on signals that would otherwise kill us with the screen
+// still in raw mode. (Ctrl-C doesn't send SIGINT in raw mode, so that's covered by the keymap.)
+#[cfg(unix)]
+pub fn restore_on_signals() -> Result<()> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGQUIT, SIGTERM},
+        iterator::Signals,
+    };
+    let mut signals = Signals::new([SIGHUP, SIGQUIT, SIGTERM])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            restore();
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn restore_on_signals() -> Result<()> {
+    Ok(())
+}
+
+// Reads a line on the bottom row with each character shown as *, for passphrases. This is for
+// before there's any text to draw, so it takes over the whole screen. Returns None if cancelled.
+pub fn read_secret(prompt: &str) -> Result<Option<String>> {
+    let mut out = io::stdout();
+    let mut secret = String::new();
+    loop {
+        let (_, rows) = terminal::size()?;
+        queue!(
+            out,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, rows.saturating_sub(1)),
+            style::Print(prompt),
+            style::Print("*".repeat(secret.chars().count())),
+        )?;
+        out.flush()?;
+        if let Event::Key(KeyEvent { code, modifiers }) = event::read()? {
+            match code {
+                KeyCode::Enter => return Ok(Some(secret)),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+                KeyCode::Backspace => {
+                    secret.pop();
+                }
+                KeyCode::Char(c) => secret.push(c),
+                _ => (),
+            }
+        }
+    }
+}
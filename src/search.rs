@@ -0,0 +1,434 @@
+use {
+    crate::{
+        idle::Task,
+        state::{Cursor, Mode, Point, State},
+    },
+    regex::{Regex, RegexBuilder},
+    regex_syntax::{
+        hir::{Class, Hir, HirKind, Literal},
+        Parser,
+    },
+    std::{ops::Range, result},
+};
+
+// Lines either side of an edit that are matched again along with it, for multi-line searches. A
+// match that reaches further than this from the edit is only found when the whole text is next
+// annotated, like when the search changes.
+const CONTEXT: usize = 100;
+
+// Patterns that can match a newline have to be matched against the whole text rather than a line
+// at a time. That's more than \n: \s, [^x], and (?s). all match one too.
+pub fn is_multi_line(re: &Regex) -> bool {
+    Parser::new()
+        .parse(re.as_str())
+        .map_or(true, |hir| matches_newline(&hir))
+}
+
+fn matches_newline(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => false,
+        HirKind::Literal(Literal::Unicode(c)) => *c == '\n',
+        HirKind::Literal(Literal::Byte(b)) => *b == b'\n',
+        HirKind::Class(Class::Unicode(class)) => class
+            .iter()
+            .any(|range| (range.start()..=range.end()).contains(&'\n')),
+        HirKind::Class(Class::Bytes(class)) => class
+            .iter()
+            .any(|range| (range.start()..=range.end()).contains(&b'\n')),
+        HirKind::Repetition(repetition) => matches_newline(&repetition.hir),
+        HirKind::Group(group) => matches_newline(&group.hir),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(matches_newline),
+    }
+}
+
+// the point at index i of the joined text, given where each line starts in it
+pub fn point_in(starts: &[usize], i: usize) -> Point {
+    let y = starts.partition_point(|&start| start <= i) - 1;
+    Point {
+        x: i - starts[y],
+        y,
+    }
+}
+
+// The pattern being typed in search mode. The search is updated as it's typed, so we keep what
+// it replaced, and where the cursor was, to put back on cancel.
+pub struct SearchPrompt {
+    pub input: String,
+    // the entry shown while stepping through history with up and down
+    recall: Option<usize>,
+    before: Option<result::Result<Regex, regex::Error>>,
+    cursor: Cursor,
+    anchor: Option<Cursor>,
+}
+
+impl State {
+    // Starts typing a search pattern, beginning with the selected text if there is any. Raw
+    // takes the selection as a regex itself, rather than searching for it as it is.
+    pub fn open_search(&mut self, raw: bool) {
+        let input = match self.selection() {
+            Some((start, end)) => {
+                let text = self.text_between(start.into(), end.into());
+                let pattern = if raw { text } else { regex::escape(&text) };
+                // newlines are spelled out, to keep the prompt on one line
+                pattern.replace('\n', "\\n")
+            }
+            None => String::new(),
+        };
+        self.search_prompt = Some(SearchPrompt {
+            input,
+            recall: None,
+            before: self.search.take(),
+            cursor: self.cursor,
+            anchor: self.anchor,
+        });
+        self.mode = Mode::Search;
+        self.update_search();
+    }
+
+    pub fn search_insert(&mut self, c: char) {
+        if let Some(prompt) = &mut self.search_prompt {
+            prompt.input.push(c);
+        }
+        self.update_search();
+    }
+
+    pub fn search_backspace(&mut self) {
+        if let Some(prompt) = &mut self.search_prompt {
+            prompt.input.pop();
+        }
+        self.update_search();
+    }
+
+    pub fn search_prev(&mut self) {
+        if let Some(prompt) = &mut self.search_prompt {
+            if let Some((i, entry)) = self.search_history.prev(prompt.recall) {
+                prompt.recall = Some(i);
+                prompt.input = entry.to_string();
+            }
+        }
+        self.update_search();
+    }
+
+    pub fn search_next(&mut self) {
+        if let Some(prompt) = &mut self.search_prompt {
+            match self.search_history.next(prompt.recall) {
+                Some((i, entry)) => {
+                    prompt.recall = Some(i);
+                    prompt.input = entry.to_string();
+                }
+                None if prompt.recall.is_some() => {
+                    prompt.recall = None;
+                    prompt.input.clear();
+                }
+                None => (),
+            }
+        }
+        self.update_search();
+    }
+
+    // Searches for the pattern typed so far, highlighting the matches on screen straight away and
+    // moving to the first one after where the search started.
+    fn update_search(&mut self) {
+        let prompt = match &self.search_prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let (cursor, anchor) = (prompt.cursor, prompt.anchor);
+        self.search = if prompt.input.is_empty() {
+            None
+        } else {
+            Some(self.compile_search(&prompt.input))
+        };
+        self.reannotate();
+        self.cursor = cursor;
+        self.anchor = anchor;
+        let found = match &self.search {
+            Some(Ok(re)) => self.find_from(re, cursor.into()),
+            _ => None,
+        };
+        if let Some(point) = found {
+            self.anchor = None;
+            self.move_cursor(point);
+        }
+    }
+
+    pub fn compile_search(&self, pattern: &str) -> result::Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(self.config.search_case.ignored(pattern))
+            .build()
+    }
+
+    // moves on to the next way of treating case, searching again with it
+    pub fn toggle_search_case(&mut self) {
+        self.config.search_case = self.config.search_case.next();
+        if self.search_prompt.is_some() {
+            self.update_search();
+        } else if let Some(Ok(re)) = &self.search {
+            self.search = Some(self.compile_search(re.as_str()));
+            self.reannotate();
+        }
+    }
+
+    // the first match of re at or after p, wrapping round to the start of the file
+    fn find_from(&self, re: &Regex, p: Point) -> Option<Point> {
+        let multi_line = is_multi_line(re);
+        (p.y..self.text.len())
+            .chain(0..=p.y)
+            .enumerate()
+            .find_map(|(i, y)| {
+                let from = if i == 0 { p.x } else { 0 };
+                // multi-line matches are all annotated already, the rest might not be
+                let x = if multi_line {
+                    let matches = &self.text[y].1.matches;
+                    matches.iter().map(|&(x, _)| x).find(|&x| x >= from)
+                } else {
+                    re.find_at(&self.text[y].0, from).map(|m| m.start())
+                }?;
+                // the wrapped pass over the starting line only looks behind
+                if i > 0 && y == p.y && x >= p.x {
+                    None
+                } else {
+                    Some(Point { x, y })
+                }
+            })
+    }
+
+    // the search, if it's one that's matched a line at a time
+    pub fn line_search(&self) -> Option<Regex> {
+        match &self.search {
+            Some(Ok(re)) if !is_multi_line(re) => Some(re.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn multi_line_search(&self) -> Option<Regex> {
+        match &self.search {
+            Some(Ok(re)) if is_multi_line(re) => Some(re.clone()),
+            _ => None,
+        }
+    }
+
+    // The whole text as one string, for multi-line patterns, and where each line starts in it.
+    pub fn joined(&self) -> (String, Vec<usize>) {
+        let mut text = String::new();
+        let mut starts = Vec::new();
+        for line in &self.text {
+            if !starts.is_empty() {
+                text.push('\n');
+            }
+            starts.push(text.len());
+            text.push_str(&line.0);
+        }
+        (text, starts)
+    }
+
+    // Matches re against the whole text at once, since a match could cross any number of lines.
+    // Each match is recorded on the line it starts on, and highlighted on every line it covers,
+    // including the newlines, which are drawn as the space after the end of each line.
+    pub fn annotate_multi_line(&mut self, re: &Regex) {
+        self.annotate_multi_line_between(re, 0, self.text.len() - 1);
+    }
+
+    // After an edit to lines start to end, as they are now, matches the lines around them again,
+    // along with the whole of any match that already reached into those.
+    pub fn reannotate_multi_line(&mut self, re: &Regex, start: usize, end: usize) {
+        // whether a match carries on from the end of line y to the next
+        let crosses = |s: &Self, y: usize| s.text[y].1.match_indices.contains(&s.text[y].0.len());
+        let mut lo = start.saturating_sub(CONTEXT);
+        while lo > 0 && crosses(self, lo - 1) {
+            lo -= 1;
+        }
+        let mut hi = usize::min(end + CONTEXT, self.text.len() - 1);
+        while hi + 1 < self.text.len() && crosses(self, hi) {
+            hi += 1;
+        }
+        self.annotate_multi_line_between(re, lo, hi);
+    }
+
+    fn annotate_multi_line_between(&mut self, re: &Regex, lo: usize, hi: usize) {
+        // with the newlines either side, so that anchors and matches ending in a newline see
+        // the lines as they are in the whole text
+        let mut text = String::new();
+        let mut starts = Vec::new();
+        if lo > 0 {
+            text.push('\n');
+        }
+        for line in &mut self.text[lo..=hi] {
+            if !starts.is_empty() {
+                text.push('\n');
+            }
+            starts.push(text.len());
+            text.push_str(&line.0);
+            line.1.matches.clear();
+            line.1.match_indices.clear();
+        }
+        // matches starting after this start on a line that wasn't asked for
+        let last = text.len();
+        if hi + 1 < self.text.len() {
+            text.push('\n');
+        }
+        let mut at = starts[0];
+        let mut prev = None;
+        while at <= text.len() {
+            let m = match re.find_at(&text, at) {
+                Some(m) if m.start() <= last => m,
+                _ => break,
+            };
+            // step over empty matches, and skip one straight after another match, as find_iter
+            // does
+            if m.start() == m.end() {
+                at = m.end() + text[m.end()..].chars().next().map_or(1, char::len_utf8);
+                if prev == Some(m.end()) {
+                    continue;
+                }
+            } else {
+                at = m.end();
+            }
+            prev = Some(m.end());
+            let (start, end) = (point_in(&starts, m.start()), point_in(&starts, m.end()));
+            for y in start.y..=end.y {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y {
+                    end.x
+                } else {
+                    self.text[lo + y].0.len() + 1
+                };
+                if y == start.y {
+                    self.text[lo + y].1.matches.push((from, to));
+                }
+                self.text[lo + y].1.match_indices.extend(from..to);
+            }
+        }
+    }
+
+    // keeps the search, and the cursor on the match it moved to
+    pub fn run_search(&mut self) {
+        self.mode = Mode::Normal;
+        if let Some(prompt) = self.search_prompt.take() {
+            self.remember_search(&prompt.input);
+        }
+    }
+
+    fn remember_search(&mut self, pattern: &str) {
+        // patterns searched for in an encrypted file could give away what's in it
+        if self.encryption.is_some() {
+            return;
+        }
+        if let Err(err) = self.search_history.remember(pattern) {
+            self.message = Some(format!("Couldn't save search history: {}", err));
+        }
+    }
+
+    // The word the cursor is on, or failing that the next one along the line, as a range of the
+    // cursor's line.
+    fn word_at_cursor(&self, wordish: impl Fn(char) -> bool) -> Option<Range<usize>> {
+        let line = &self.text[self.cursor.y].0;
+        let x = self.cursor.x + line[self.cursor.x..].find(&wordish)?;
+        let start = line[..x]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| wordish(c))
+            .last()
+            .map_or(x, |(i, _)| i);
+        let end = line[x..]
+            .find(|c| !wordish(c))
+            .map_or(line.len(), |i| x + i);
+        Some(start..end)
+    }
+
+    // Searches for the word under the cursor, as a whole word, and moves to the next match.
+    pub fn search_word(&mut self, wordish: impl Fn(char) -> bool) {
+        let range = match self.word_at_cursor(wordish) {
+            Some(range) => range,
+            None => {
+                self.message = Some("No word under cursor".to_string());
+                return;
+            }
+        };
+        let y = self.cursor.y;
+        let pattern = format!("\\b{}\\b", regex::escape(&self.text[y].0[range.clone()]));
+        self.search = Some(self.compile_search(&pattern));
+        self.remember_search(&pattern);
+        self.reannotate();
+        self.anchor = None;
+        self.move_cursor(Point { x: range.start, y });
+        self.repeat(State::move_next_match);
+    }
+
+    // puts back the search and cursor from before the prompt was opened
+    pub fn cancel_search_prompt(&mut self) {
+        self.mode = Mode::Normal;
+        if let Some(prompt) = self.search_prompt.take() {
+            self.search = prompt.before;
+            self.cursor = prompt.cursor;
+            self.anchor = prompt.anchor;
+            self.reannotate();
+        }
+    }
+
+    // How many matches there are, and which one the cursor is on if any. Only known once every
+    // line has been annotated.
+    pub fn match_position(&self) -> Option<(Option<usize>, usize)> {
+        if !matches!(self.search, Some(Ok(_))) || self.idle.is_pending(Task::Reannotate) {
+            return None;
+        }
+        let cursor = Point::from(self.cursor);
+        let mut current = None;
+        let mut total = 0;
+        for (y, line) in self.text.iter().enumerate() {
+            for &(start, end) in &line.1.matches {
+                if y == cursor.y && start <= cursor.x && (cursor.x < end || cursor.x == start) {
+                    current = Some(total + 1);
+                }
+                total += 1;
+            }
+        }
+        Some((current, total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    #[test]
+    fn knows_which_patterns_cross_lines() {
+        for pattern in [r"a\nb", r"a\sb", "a[^x]b", "(?s)a.b", r"a[\n]b", "a(b|\n)*"] {
+            assert!(is_multi_line(&Regex::new(pattern).unwrap()), "{}", pattern);
+        }
+        for pattern in ["a.b", r"a\Sb", "^a$", r"\bab", "(?m)a$", r"a[^x\n]b"] {
+            assert!(!is_multi_line(&Regex::new(pattern).unwrap()), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn reannotates_around_edits() {
+        let text: String = (0..500).map(|y| format!("{}\n", y % 7)).collect();
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            text.as_bytes(),
+            Config::default(),
+            Keymap::default(),
+        );
+        let re = Regex::new(r"(?m)^3\s4|6$|\A0").unwrap();
+        s.search = Some(Ok(re.clone()));
+        s.annotate_multi_line(&re);
+        for (start, end, new) in [
+            ((0, 250), (0, 250), "3\n"),
+            ((1, 100), (0, 102), "\n"),
+            ((0, 3), (0, 3), "x"),
+            ((0, 0), (0, 0), "6"),
+        ] {
+            let at = |(x, y)| Point { x, y };
+            s.splice(at(start), at(end), new);
+            let edited: Vec<_> = s.text.iter().map(|line| line.1.matches.clone()).collect();
+            s.annotate_multi_line(&re);
+            let all: Vec<_> = s.text.iter().map(|line| line.1.matches.clone()).collect();
+            assert_eq!(edited, all);
+            assert!(all.iter().any(|matches| !matches.is_empty()));
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use {
+    crate::{
+        edit::EditOp,
+        mark::MarkId,
+        ring::Ring,
+        search::{self, point_in},
+        state::{Mode, Point, State},
+        transaction::Transaction,
+    },
+    regex::Regex,
+    std::{iter, mem},
+};
+
+// Selections besides the main one, made by selecting every match of the search at once. Each
+// edit recorded at the main selection is done again at each of these, so typing, deleting,
+// changing, pasting and so on happen at every match, and so is each command that moves or
+// selects (see Command::moves). They're held by marks, so they follow the text as it's edited.
+pub struct Selection {
+    anchor: Option<MarkId>,
+    cursor: MarkId,
+}
+
+// The pattern being typed to keep only the selections that match, or to drop them.
+pub struct Filter {
+    pub input: String,
+    pub keep: bool,
+}
+
+impl State {
+    // Selects every match of the search in the selection, or in the whole file without one,
+    // the first as the main selection.
+    pub fn select_matches(&mut self) {
+        let re = match &self.search {
+            Some(Ok(re)) => re.clone(),
+            _ => {
+                self.message = Some("No search to select".to_string());
+                return;
+            }
+        };
+        let (start, end) = match self.selection() {
+            Some((start, end)) => (start.into(), end.into()),
+            None => (self.start_of_file(), self.end_of_file()),
+        };
+        let matches = self.matches_between(&re, start, end);
+        let (first, rest) = match matches.split_first() {
+            Some(split) => split,
+            None => {
+                self.message = Some("No matches to select".to_string());
+                return;
+            }
+        };
+        self.clear_selections();
+        self.select(first.0, first.1);
+        for &(start, end) in rest {
+            self.add_selection(start, end, false);
+        }
+        self.message = Some(format!("{} selections", matches.len()));
+    }
+
+    // Leaves the main selection where it is, as one of the others, and moves on to a cursor on
+    // the next line, aiming for the same column as moving down would.
+    pub fn add_cursor(&mut self, below: bool) {
+        let y = self.cursor.y;
+        if below && y + 1 == self.text.len() || !below && y == 0 {
+            self.message = Some(format!("No line {}", if below { "below" } else { "above" }));
+            return;
+        }
+        let selection = self.mark_selection();
+        self.selections.push(selection);
+        self.anchor = None;
+        if below {
+            self.move_down(1);
+        } else {
+            self.move_up(1);
+        }
+        self.message = Some(format!("{} selections", self.selections.len() + 1));
+    }
+
+    // every match of re that's entirely between start and end
+    fn matches_between(&self, re: &Regex, start: Point, end: Point) -> Vec<(Point, Point)> {
+        let matches: Vec<(Point, Point)> = if search::is_multi_line(re) {
+            let (text, starts) = self.joined();
+            re.find_iter(&text)
+                .map(|m| (point_in(&starts, m.start()), point_in(&starts, m.end())))
+                .collect()
+        } else {
+            (start.y..=end.y)
+                .flat_map(|y| {
+                    re.find_iter(&self.text[y].0)
+                        .map(move |m| (Point { x: m.start(), y }, Point { x: m.end(), y }))
+                })
+                .collect()
+        };
+        matches
+            .into_iter()
+            .filter(|&(a, b)| a >= start && b <= end)
+            .collect()
+    }
+
+    // selects from anchor to cursor as the main selection, or just moves the cursor if they're
+    // the same
+    fn select(&mut self, anchor: Point, cursor: Point) {
+        self.anchor = None;
+        if anchor != cursor {
+            self.move_cursor(anchor);
+            self.anchor = Some(self.cursor);
+        }
+        self.move_cursor(cursor);
+    }
+
+    pub fn clear_selections(&mut self) {
+        for selection in mem::take(&mut self.selections) {
+            self.forget_selection(&selection);
+        }
+    }
+
+    fn forget_selection(&mut self, selection: &Selection) {
+        self.marks.remove(selection.cursor);
+        if let Some(anchor) = selection.anchor {
+            self.marks.remove(anchor);
+        }
+    }
+
+    // where the other selections are, for drawing, with a cursor on its own as the char under it
+    pub fn other_selections(&self) -> Vec<(Point, Point)> {
+        self.selections
+            .iter()
+            .filter_map(|selection| {
+                let cursor = self.marks.get(selection.cursor)?;
+                Some(match selection.anchor.and_then(|id| self.marks.get(id)) {
+                    Some(anchor) if anchor < cursor => (anchor, cursor),
+                    Some(anchor) => (cursor, anchor),
+                    None => (cursor, self.next_point(cursor).unwrap_or(cursor)),
+                })
+            })
+            .collect()
+    }
+
+    // Does op again at each of the other selections. Kills and registers are left as the main
+    // selection's edit leaves them.
+    pub fn edit_selections(&mut self, op: EditOp) {
+        if self.selections.is_empty() {
+            return;
+        }
+        let kills = mem::replace(&mut self.kills, Ring::new(1));
+        let registers = self.registers.clone();
+        self.each_selection(|s| s.replay(op));
+        self.kills = kills;
+        self.registers = registers;
+    }
+
+    // Runs f with each of the other selections as the main one in turn, then puts the main one
+    // back.
+    pub fn each_selection(&mut self, f: impl Fn(&mut State)) {
+        let (mode, count, register) = (self.mode, self.count, self.register);
+        let cursor = self.cursor;
+        let main = self.mark_selection();
+        let selections = mem::take(&mut self.selections);
+        let mut done = Vec::new();
+        // the ones still to do follow each edit, since they're marks
+        for selection in &selections {
+            self.take_selection(selection);
+            (self.mode, self.count, self.register) = (mode, count, register);
+            f(self);
+            done.push(self.mark_selection());
+        }
+        self.take_selection(&main);
+        // still aiming for the same column if the others' edits didn't move it
+        if Point::from(self.cursor) == Point::from(cursor) {
+            self.cursor = cursor;
+        }
+        (self.mode, self.count, self.register) = (mode, count, register);
+        self.selections = done;
+        self.merge_selections();
+    }
+
+    // makes selection the main one, removing its marks
+    fn take_selection(&mut self, selection: &Selection) {
+        let cursor = self.marks.get(selection.cursor);
+        let anchor = selection.anchor.and_then(|id| self.marks.get(id));
+        self.forget_selection(selection);
+        if let Some(cursor) = cursor {
+            self.select(anchor.unwrap_or(cursor), cursor);
+        }
+    }
+
+    fn mark_selection(&mut self) -> Selection {
+        let cursor = self.cursor.into();
+        Selection {
+            anchor: self.anchor.map(|anchor| self.marks.add(anchor.into())),
+            cursor: self.marks.add(cursor),
+        }
+    }
+
+    // Edits can leave selections on top of each other, deleting the lines they were on, say.
+    // Those are the same selection now, so only one is kept.
+    fn merge_selections(&mut self) {
+        let main = (self.anchor.map(Point::from), Point::from(self.cursor));
+        let mut seen = vec![main];
+        for selection in mem::take(&mut self.selections) {
+            let at = (
+                selection.anchor.and_then(|id| self.marks.get(id)),
+                self.marks.get(selection.cursor).unwrap_or(main.1),
+            );
+            if seen.contains(&at) {
+                self.forget_selection(&selection);
+            } else {
+                seen.push(at);
+                self.selections.push(selection);
+            }
+        }
+    }
+
+    pub fn open_filter(&mut self, keep: bool) {
+        self.filter = Some(Filter {
+            input: String::new(),
+            keep,
+        });
+        self.mode = Mode::Filter;
+    }
+
+    pub fn filter_insert(&mut self, c: char) {
+        if let Some(filter) = &mut self.filter {
+            filter.input.push(c);
+        }
+    }
+
+    pub fn filter_backspace(&mut self) {
+        if let Some(filter) = &mut self.filter {
+            filter.input.pop();
+        }
+    }
+
+    pub fn cancel_filter(&mut self) {
+        self.filter = None;
+        self.mode = Mode::Normal;
+    }
+
+    // Keeps the selections whose text matches the pattern, or drops them, the main one included.
+    // A cursor on its own is judged by its line. If none would be left, all of them stay.
+    pub fn run_filter(&mut self) {
+        let filter = match self.filter.take() {
+            Some(filter) => filter,
+            None => return,
+        };
+        self.mode = Mode::Normal;
+        let re = match self.compile_search(&filter.input) {
+            Ok(re) => re,
+            Err(err) => {
+                self.message = Some(format!("Invalid pattern: {}", err));
+                return;
+            }
+        };
+        let main = self.mark_selection();
+        let others = mem::take(&mut self.selections);
+        let (mut kept, dropped): (Vec<Selection>, Vec<Selection>) = iter::once(main)
+            .chain(others)
+            .partition(|selection| re.is_match(&self.selected_text(selection)) == filter.keep);
+        if kept.is_empty() {
+            kept = dropped;
+            self.message = Some("No selections would be left".to_string());
+        } else {
+            for selection in &dropped {
+                self.forget_selection(selection);
+            }
+            self.message = Some(format!("{} selections", kept.len()));
+        }
+        let main = kept.remove(0);
+        self.take_selection(&main);
+        self.selections = kept;
+    }
+
+    fn selected_text(&self, selection: &Selection) -> String {
+        match (selection.anchor, self.range_of(selection)) {
+            (Some(_), Some((start, end))) => self.text_between(start, end),
+            (None, Some((cursor, _))) => self.text[cursor.y].0.clone(),
+            (_, None) => String::new(),
+        }
+    }
+
+    // the points a selection is between, start first
+    fn range_of(&self, selection: &Selection) -> Option<(Point, Point)> {
+        let cursor = self.marks.get(selection.cursor)?;
+        let anchor = selection.anchor.and_then(|id| self.marks.get(id));
+        Some(match anchor {
+            Some(anchor) if anchor < cursor => (anchor, cursor),
+            Some(anchor) => (cursor, anchor),
+            None => (cursor, cursor),
+        })
+    }
+
+    // Moves the text of each selection, in order through the file, into the next one, and the
+    // last one's into the first. Backwards goes the other way. Each selection ends up over its
+    // new text.
+    pub fn rotate_selections(&mut self, forwards: bool) {
+        if self.selections.is_empty() {
+            self.message = Some("Only one selection".to_string());
+            return;
+        }
+        let main = self.mark_selection();
+        let others = mem::take(&mut self.selections);
+        let mut ranges = Vec::new();
+        for (i, selection) in iter::once(main).chain(others).enumerate() {
+            if let Some((start, end)) = self.range_of(&selection) {
+                ranges.push((start, end, i == 0));
+            }
+            self.forget_selection(&selection);
+        }
+        ranges.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+        let texts: Vec<String> = ranges
+            .iter()
+            .map(|&(start, end, _)| self.text_between(start, end))
+            .collect();
+        let n = ranges.len();
+        let mut transaction = Transaction::default();
+        for (i, &(start, end, _)) in ranges.iter().enumerate() {
+            let from = if forwards {
+                (i + n - 1) % n
+            } else {
+                (i + 1) % n
+            };
+            transaction.replace(start, end, texts[from].clone());
+        }
+        match self.apply(transaction) {
+            Ok(new) => {
+                for (&(_, _, main), (start, end)) in ranges.iter().zip(new) {
+                    self.add_selection(start, end, main);
+                }
+            }
+            Err(err) => {
+                self.message = Some(format!("Couldn't rotate: {}", err));
+                for &(start, end, main) in &ranges {
+                    self.add_selection(start, end, main);
+                }
+            }
+        }
+    }
+
+    pub fn add_selection(&mut self, start: Point, end: Point, main: bool) {
+        if main {
+            self.select(start, end);
+        } else {
+            let anchor = (start != end).then(|| self.marks.add(start));
+            let cursor = self.marks.add(end);
+            self.selections.push(Selection { anchor, cursor });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    fn filter(s: &mut State, keep: bool, pattern: &str) {
+        s.open_filter(keep);
+        for c in pattern.chars() {
+            s.filter_insert(c);
+        }
+        s.run_filter();
+    }
+
+    // the text of every selection, the main one first
+    fn selected(s: &State) -> Vec<String> {
+        let (start, end) = s.selection().unwrap();
+        iter::once(s.text_between(start.into(), end.into()))
+            .chain(
+                s.other_selections()
+                    .into_iter()
+                    .map(|(start, end)| s.text_between(start, end)),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn keeps_and_drops_selections() {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            b"foo1 bar foo2\nfoo3",
+            Config::default(),
+            Keymap::default(),
+        );
+        s.search = Some(Regex::new("baz"));
+        s.select_matches();
+        assert_eq!(s.message.as_deref(), Some("No matches to select"));
+        s.search = Some(Regex::new(r"foo\d"));
+        s.select_matches();
+        assert_eq!(selected(&s), ["foo1", "foo2", "foo3"]);
+
+        filter(&mut s, false, "o");
+        assert_eq!(s.message.as_deref(), Some("No selections would be left"));
+        assert_eq!(selected(&s), ["foo1", "foo2", "foo3"]);
+        filter(&mut s, true, "(");
+        assert!(s.message.as_deref().unwrap().starts_with("Invalid pattern"));
+        assert_eq!(s.selections.len(), 2);
+        filter(&mut s, false, "1");
+        assert_eq!(selected(&s), ["foo2", "foo3"]);
+        filter(&mut s, true, "3$");
+        assert_eq!(selected(&s), ["foo3"]);
+        assert!(s.mode == Mode::Normal && s.selections.is_empty());
+        assert!(s.violations().is_empty());
+
+        // a cursor with nothing selected goes by its whole line
+        s.clear_selections();
+        s.anchor = None;
+        s.move_cursor(Point { x: 0, y: 0 });
+        s.add_cursor(true);
+        filter(&mut s, true, "bar");
+        assert_eq!(s.selections.len(), 0);
+        assert_eq!(Point::from(s.cursor), Point { x: 0, y: 0 });
+        assert!(s.violations().is_empty());
+    }
+}
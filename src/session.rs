@@ -0,0 +1,389 @@
+use {
+    crate::{
+        annotate,
+        config::{self, Config},
+        idle::Task,
+        keymap::Keymap,
+        state::{Mode, Point, State},
+    },
+    anyhow::Result,
+    std::{
+        env, fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+// The files that are open, where the cursor is in each, and how the windows are arranged, saved
+// under a name in the state directory to load again later alongside whatever's open. Each
+// working directory keeps one of its own as well, written when idle and loaded by giving no
+// name, which running vee without a file opens, so that each project picks up where it left
+// off. Files that aren't on disk, and temp files that something is waiting on, are left out,
+// since there'd be nothing to reopen.
+
+// where named sessions are kept, in the state directory
+const NAMED: &str = "sessions";
+
+// the working directory's own session, as it's kept up to date
+#[derive(Default)]
+pub struct Session {
+    // where it's written, or None to keep nothing, like with --private
+    path: Option<PathBuf>,
+    // as last written, so that it's only written again once it's changed
+    written: String,
+}
+
+// one buffer: its file, and where the cursor was
+struct Entry {
+    file: String,
+    cursor: Point,
+}
+
+// A name typed to save the session as, or to load one by.
+pub struct SessionPrompt {
+    pub input: String,
+    pub save: bool,
+}
+
+// The first line is which of the others was being edited, and each of those is y, x, and the
+// whole path of a file, separated by tabs, but for the windows, on a line of their own after
+// "layout" and a tab, see State::encode_layout.
+fn parse(contents: &str) -> (usize, Vec<Entry>, Option<&str>) {
+    let mut lines = contents.lines();
+    let active = lines.next().and_then(|line| line.parse().ok()).unwrap_or(0);
+    let layout = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("layout\t"));
+    let entries = lines
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let y = fields.next()?.parse().ok()?;
+            let x = fields.next()?.parse().ok()?;
+            Some(Entry {
+                file: fields.next()?.to_string(),
+                cursor: Point { x, y },
+            })
+        })
+        .collect();
+    (active, entries, layout)
+}
+
+// the session for the working directory, by its path, with separators that can't be in a name
+fn project() -> String {
+    let dir = env::current_dir().unwrap_or_default();
+    format!(
+        "projects/{}",
+        dir.to_string_lossy().replace(['/', '\\', ':'], "%")
+    )
+}
+
+// The project's session for no name, and None for names that aren't a plain file name.
+fn path(name: &str) -> Option<PathBuf> {
+    if name.is_empty() {
+        return config::state_path(&project());
+    }
+    if name.starts_with('.') || name.contains(['/', '\\']) {
+        return None;
+    }
+    config::state_path(&format!("{}/{}", NAMED, name))
+}
+
+// None if there's no such file
+fn read(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).and_then(|()| fs::write(path, contents)),
+        None => fs::write(path, contents),
+    }
+}
+
+// relative to the working directory again, where it's under it, as it would have been typed
+fn relative(file: &str) -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|dir| Some(Path::new(file).strip_prefix(dir).ok()?.to_path_buf()))
+        .map_or(file.to_string(), |path| path.to_string_lossy().into_owned())
+}
+
+// the session called name, as a message would say it
+fn describe(name: &str) -> &str {
+    if name.is_empty() {
+        "for this directory"
+    } else {
+        name
+    }
+}
+
+impl Session {
+    pub fn project() -> Self {
+        Session {
+            path: path(""),
+            written: String::new(),
+        }
+    }
+
+    pub fn is_kept(&self) -> bool {
+        self.path.is_some()
+    }
+}
+
+impl State {
+    // Opens the files in the project's session as they were left, or returns None if there
+    // isn't one, or none of its files are left to open.
+    pub fn restore_session(config: Config, keymap: Keymap) -> Result<Option<State>> {
+        let contents = match path("") {
+            Some(path) => read(&path)?,
+            None => None,
+        };
+        let contents = match contents {
+            Some(contents) => contents,
+            None => return Ok(None),
+        };
+        let (_, entries, _) = parse(&contents);
+        let first = match entries
+            .iter()
+            .find(|entry| Path::new(&entry.file).is_file())
+        {
+            Some(entry) => relative(&entry.file),
+            None => return Ok(None),
+        };
+        let mut s = State::open(first, false, config, keymap)?;
+        s.open_session(&contents);
+        Ok(Some(s))
+    }
+
+    // Opens the files in a session, alongside any already open, each where it was left, and
+    // arranges the windows as they were.
+    fn open_session(&mut self, contents: &str) {
+        let (active, entries, layout) = parse(contents);
+        self.only_window();
+        // the buffer each entry was opened in, if it could be
+        let mut buffers = Vec::new();
+        for entry in entries {
+            // deleted since, most likely
+            if !Path::new(&entry.file).is_file() {
+                buffers.push(None);
+                continue;
+            }
+            let file = relative(&entry.file);
+            self.open_file(file.clone());
+            if !annotate::same_file(&self.file, &file) {
+                buffers.push(None);
+                continue;
+            }
+            let point = self.clamp(entry.cursor);
+            self.anchor = None;
+            self.move_cursor(point);
+            buffers.push(Some(self.active));
+        }
+        if let Some(&Some(to)) = buffers.get(active) {
+            if to != self.active {
+                self.switch_buffer(to);
+            }
+        }
+        if let Some(layout) = layout {
+            self.restore_layout(layout, &buffers);
+        }
+    }
+
+    fn encode_session(&self) -> String {
+        let dir = env::current_dir().unwrap_or_default();
+        let buffers = self.reopenable();
+        let kept: Vec<usize> = (0..buffers.len())
+            .filter(|&i| buffers[i].is_some())
+            .collect();
+        let active = kept.iter().position(|&i| i == self.active).unwrap_or(0);
+        let mut contents = format!("{}\n", active);
+        for (file, cursor) in buffers.into_iter().flatten() {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\n",
+                cursor.y,
+                cursor.x,
+                dir.join(file).display(),
+            ));
+        }
+        if self.windows.len() > 1 {
+            let entry = |i| kept.iter().position(|&j| j == i);
+            if let Some(layout) = self.encode_layout(entry) {
+                contents.push_str(&format!("layout\t{}\n", layout));
+            }
+        }
+        contents
+    }
+
+    pub fn write_session(&mut self) {
+        let path = match &self.session.path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let contents = self.encode_session();
+        if contents == self.session.written {
+            return;
+        }
+        match write(&path, &contents) {
+            Ok(()) => self.session.written = contents,
+            Err(err) => self.message = Some(format!("Error writing {}: {}", path.display(), err)),
+        }
+    }
+
+    // after anything that might have opened, closed, or moved around in a file
+    pub fn schedule_session(&mut self) {
+        if self.session.is_kept() {
+            self.idle.schedule(Task::Session);
+        }
+    }
+
+    pub fn open_session_prompt(&mut self, save: bool) {
+        self.session_prompt = Some(SessionPrompt {
+            input: String::new(),
+            save,
+        });
+        self.mode = Mode::Session;
+    }
+
+    pub fn session_insert(&mut self, c: char) {
+        if let Some(prompt) = &mut self.session_prompt {
+            prompt.input.push(c);
+        }
+    }
+
+    pub fn session_backspace(&mut self) {
+        if let Some(prompt) = &mut self.session_prompt {
+            prompt.input.pop();
+        }
+    }
+
+    pub fn cancel_session_prompt(&mut self) {
+        self.session_prompt = None;
+        self.mode = Mode::Normal;
+    }
+
+    pub fn run_session_prompt(&mut self) {
+        let prompt = match self.session_prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        self.mode = Mode::Normal;
+        if prompt.save {
+            self.save_session(&prompt.input);
+        } else {
+            self.load_session(&prompt.input);
+        }
+    }
+
+    pub fn save_session(&mut self, name: &str) {
+        let path = match path(name) {
+            Some(path) => path,
+            None => {
+                self.message = Some(format!("Invalid session name: {}", name));
+                return;
+            }
+        };
+        self.message = Some(match write(&path, &self.encode_session()) {
+            Ok(()) => format!("Saved session {}", describe(name)),
+            Err(err) => format!("Error writing {}: {}", path.display(), err),
+        });
+    }
+
+    pub fn load_session(&mut self, name: &str) {
+        let path = match path(name) {
+            Some(path) => path,
+            None => {
+                self.message = Some(format!("Invalid session name: {}", name));
+                return;
+            }
+        };
+        match read(&path) {
+            Ok(Some(contents)) => {
+                self.open_session(&contents);
+                self.message = Some(format!("Loaded session {}", describe(name)));
+            }
+            Ok(None) => self.message = Some(format!("No session {}", describe(name))),
+            Err(err) => self.message = Some(format!("Error reading {}: {}", path.display(), err)),
+        }
+    }
+
+    // lists the named sessions in the pager
+    pub fn list_sessions(&mut self) {
+        let mut names: Vec<String> = config::state_path(NAMED)
+            .and_then(|dir| fs::read_dir(dir).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        if names.is_empty() {
+            self.message = Some("No saved sessions".to_string());
+            return;
+        }
+        names.sort();
+        self.open_pager("sessions".to_string(), names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_what_it_reads() {
+        let dir = env::temp_dir().join(format!("vee-session-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::write(&a, "one\n").unwrap();
+        fs::write(&b, "two\nthree\n").unwrap();
+        let mut s = State::open(
+            a.to_string_lossy().into_owned(),
+            false,
+            Config::default(),
+            Keymap::default(),
+        )
+        .unwrap();
+        s.open_file(b.to_string_lossy().into_owned());
+        s.move_cursor(Point { x: 2, y: 1 });
+        s.open_file(dir.join("new").to_string_lossy().into_owned());
+        s.prev_buffer();
+        let contents = s.encode_session();
+        let (active, entries, layout) = parse(&contents);
+        assert_eq!((active, layout), (1, None));
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (Path::new(&entry.file), entry.cursor))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (a.as_path(), Point { x: 0, y: 0 }),
+                (b.as_path(), Point { x: 2, y: 1 })
+            ],
+        );
+
+        s.resize((80, 24));
+        s.split_window(true);
+        s.prev_buffer();
+        let size = s.size;
+        let contents = s.encode_session();
+        assert_eq!(parse(&contents).2, Some("|0.5 1:1:2 *0"));
+        let mut s = State::open(
+            b.to_string_lossy().into_owned(),
+            false,
+            Config::default(),
+            Keymap::default(),
+        )
+        .unwrap();
+        s.resize((80, 24));
+        s.open_session(&contents);
+        assert_eq!((s.windows.len(), s.window, s.size), (2, 1, size));
+        assert!(annotate::same_file(&s.file, &a.to_string_lossy()));
+        let parked = s.with_window(0, |s| (s.file.clone(), Point::from(s.cursor)));
+        assert!(annotate::same_file(&parked.0, &b.to_string_lossy()));
+        assert_eq!(parked.1, Point { x: 2, y: 1 });
+        assert!(s.violations().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
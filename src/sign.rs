@@ -0,0 +1,35 @@
+use crossterm::style::Color;
+
+// Signs shown in the gutter, lowest priority first. A line can have signs from several
+// subsystems, but only the highest priority one is drawn.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Sign {
+    // from the coverage report, see coverage.rs
+    Covered,
+    Uncovered,
+    // put there by an external tool, see annotate.rs
+    Annotation(char),
+    Match,
+    Invalid,
+}
+
+impl Sign {
+    pub fn symbol(self) -> char {
+        match self {
+            Sign::Covered | Sign::Uncovered => '▌',
+            Sign::Annotation(c) => c,
+            Sign::Match => '*',
+            Sign::Invalid => '!',
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Sign::Covered => Color::DarkGreen,
+            Sign::Uncovered => Color::DarkRed,
+            Sign::Annotation(_) => Color::Cyan,
+            Sign::Match => Color::Red,
+            Sign::Invalid => Color::Magenta,
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::state::{Point, State};
+
+#[derive(Clone, Copy)]
+pub enum Sort {
+    Ascending,
+    Descending,
+    // ascending, keeping only the first of each run of equal lines
+    Unique,
+}
+
+impl State {
+    // Sorts the selected lines in one edit. Needs a selection over at least two lines, since
+    // sorting one line would do nothing.
+    pub fn sort_lines(&mut self, sort: Sort) {
+        let (first, last) = self.selected_lines();
+        if first == last {
+            self.message = Some("Select some lines to sort".to_string());
+            return;
+        }
+        self.break_undo();
+        self.transform(Point { x: 0, y: first }, self.end_of_line(last), |text| {
+            let mut lines: Vec<&str> = text.split('\n').collect();
+            lines.sort_unstable();
+            match sort {
+                Sort::Ascending => (),
+                Sort::Descending => lines.reverse(),
+                Sort::Unique => lines.dedup(),
+            }
+            lines.join("\n")
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    fn state(text: &str) -> State {
+        State::from_bytes(
+            "test.txt".to_string(),
+            text.as_bytes(),
+            Config::default(),
+            Keymap::default(),
+        )
+    }
+
+    fn lines(s: &State) -> Vec<&str> {
+        s.text.iter().map(|line| line.0.as_str()).collect()
+    }
+
+    #[test]
+    fn sorts_selected_lines() {
+        // the last line has no newline after it
+        let mut s = state("b\na\nc\na");
+        s.select_all();
+        s.sort_lines(Sort::Ascending);
+        assert_eq!(lines(&s), ["a", "a", "b", "c"]);
+        s.sort_lines(Sort::Descending);
+        assert_eq!(lines(&s), ["c", "b", "a", "a"]);
+        s.sort_lines(Sort::Unique);
+        assert_eq!(lines(&s), ["a", "b", "c"]);
+        assert!(s.violations().is_empty());
+        // each in one edit, once the key that did it is done
+        s.commit();
+        s.undo();
+        assert_eq!(lines(&s), ["c", "b", "a", "a"]);
+
+        // the line the selection ends at the start of isn't in it
+        let mut s = state("b\na\nc\n");
+        s.anchor = Some(s.cursor);
+        s.move_cursor(Point { x: 0, y: 2 });
+        s.sort_lines(Sort::Ascending);
+        assert_eq!(lines(&s), ["a", "b", "c"]);
+
+        // nothing, or less than two lines, selected
+        let mut s = state("b\na\n");
+        s.sort_lines(Sort::Ascending);
+        s.anchor = Some(s.cursor);
+        s.move_cursor(Point { x: 1, y: 0 });
+        s.sort_lines(Sort::Ascending);
+        assert_eq!(lines(&s), ["b", "a"]);
+        assert_eq!(s.message.as_deref(), Some("Select some lines to sort"));
+    }
+}
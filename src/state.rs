@@ -1,21 +1,85 @@
 use {
-    crate::line::Line,
-    anyhow::Result,
+    crate::{
+        announce::Announced,
+        buffer::Buffer,
+        command::{self, Command},
+        config::{Config, Normalization},
+        dialog::Dialog,
+        disk,
+        edit::{EditOp, Recorder},
+        expand::Expansions,
+        file_drop::Dropped,
+        hover::Tooltip,
+        idle::{self, Idle, Progress, Task},
+        keymap::{Key, Keymap},
+        language::Language,
+        line::{self, Line},
+        macros::Macros,
+        mark::Marks,
+        palette::Palette,
+        path_prompt::PathPrompt,
+        recent::Recent,
+        recovery,
+        ring::Ring,
+        search::SearchPrompt,
+        selections::Filter,
+        session::{Session, SessionPrompt},
+        sign::Sign,
+        temp_file::TempFile,
+        transaction::Transaction,
+        tutor::Tutor,
+        undo::History,
+        window::{Layout, Window},
+    },
     crossterm::event::{KeyCode, KeyEvent},
-    regex::Regex,
-    std::{fmt, fs, result},
-    unicode_width::{UnicodeWidthChar, UnicodeWidthStr},
+    std::{
+        collections::{BTreeMap, HashMap},
+        fmt, fs,
+        ops::Range,
+        path::Path,
+        time::{Duration, Instant},
+    },
+    unicode_normalization::{is_nfc, is_nfd},
 };
 
 // A comment with some 中文 to test proper unicode handling.
 // This line has fewer chars, but is the same visual length.
 
+// clicks closer together than this count towards a double or triple click
+const MULTI_CLICK: Duration = Duration::from_millis(400);
+
+// typing more digits than this leaves the count where it is, rather than running away
+const MAX_COUNT: usize = 9999;
+
+// with config.autosave, pauses closer together than this wait for the next one to save
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+// Where p ends up after the text between start and end is replaced by text ending at new_end.
+// Positions inside the replaced range collapse to its start.
+pub fn shift(p: Point, start: Point, end: Point, new_end: Point) -> Point {
+    if p <= start {
+        p
+    } else if p < end {
+        start
+    } else if p.y == end.y {
+        Point {
+            x: p.x - end.x + new_end.x,
+            y: new_end.y,
+        }
+    } else {
+        Point {
+            y: p.y + new_end.y - end.y,
+            ..p
+        }
+    }
+}
+
 // field order in Cursor and Point is important for the PartialOrd derivation
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub struct Cursor {
     pub y: usize, // row
     pub x: usize, // actual col (in bytes)
-    w: usize,     // target col (in visual width)
+    pub w: usize, // target col (in visual width)
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
@@ -33,12 +97,32 @@ impl From<Cursor> for Point {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum Mode {
     Normal,
     Insert,
     System,
-    // Search,
+    Command,
+    Goto,
+    Filetype,
+    Dialog,
+    // waiting for the name of a register
+    Register,
+    Pager,
+    // typing a search pattern
+    Search,
+    // typing the name of a session to save or load
+    Session,
+    // typing what to replace the search's matches with
+    Replace,
+    // stepping through the matches, replacing or skipping each
+    Confirm,
+    // typing a pattern to keep or drop selections by
+    Filter,
+    // waiting for a key to write a binding for
+    Key,
+    // typing the path of a file to open, or create, rename or delete
+    Path,
 }
 
 impl fmt::Display for Mode {
@@ -47,104 +131,807 @@ impl fmt::Display for Mode {
             Mode::Normal => write!(f, "NORMAL"),
             Mode::Insert => write!(f, "INSERT"),
             Mode::System => write!(f, "SYSTEM"),
-            // Mode::Search => write!(f, "SEARCH"),
+            Mode::Command => write!(f, "PROMPT"),
+            Mode::Goto => write!(f, "GOTO"),
+            Mode::Filetype => write!(f, "FTYPE"),
+            Mode::Dialog => write!(f, "DIALOG"),
+            Mode::Register => write!(f, "REGSTR"),
+            Mode::Pager => write!(f, "PAGER"),
+            Mode::Search => write!(f, "SEARCH"),
+            Mode::Session => write!(f, "SESSN"),
+            Mode::Replace => write!(f, "REPLCE"),
+            Mode::Confirm => write!(f, "CONFRM"),
+            Mode::Filter => write!(f, "FILTER"),
+            Mode::Key => write!(f, "KEY"),
+            Mode::Path => write!(f, "PATH"),
         }
     }
 }
 
 pub struct State {
+    pub config: Config,
     pub mode: Mode,
-    pub file: String,
-    pub text: Vec<Line>,
-    pub cursor: Cursor,
-    pub anchor: Option<Cursor>,
-    pub search: Option<result::Result<Regex, regex::Error>>,
+    // every open file, in the order they were opened, and the one being edited, see buffer.rs
+    pub buffers: Vec<Buffer>,
+    pub active: usize,
+    pub path_prompt: Option<PathPrompt>,
+    // the path the dialog is asking whether to delete, see path_prompt.rs
+    pub deleting: Option<String>,
+    pub filter: Option<Filter>,
+    pub search_prompt: Option<SearchPrompt>,
+    pub session_prompt: Option<SessionPrompt>,
+    pub search_history: Recent,
+    pub keymap: Keymap,
+    // what the screen is split into, and the window with the focus, see window.rs
+    pub windows: Vec<Window>,
+    pub layout: Layout,
+    pub window: usize,
+    // the split whose divider is being dragged, see Layout::divider_at
+    pub dragging: Option<Vec<bool>>,
+    // where the mouse last moved to, until it's been there a moment, see hover.rs
+    pub hovering: Option<(u16, u16)>,
+    pub tooltip: Option<Tooltip>,
+    // repositories that may run commands, or None where there's nobody to ask, like the
+    // tutor, see trust.rs
+    pub trusted: Option<Recent>,
+    // and the one the dialog is asking about, and those it's been told not to trust
+    pub trusting: Option<String>,
+    pub distrusted: Vec<String>,
+    // the terminal, and the focused window's part of it, see State::resize
+    pub screen: (u16, u16),
+    pub size: (u16, u16),
+    // yanked and deleted text, newest first
+    pub kills: Ring<String>,
+    // named registers, and the one chosen for the next yank, delete, or paste
+    pub registers: HashMap<char, String>,
+    pub register: Option<char>,
+    pub palette: Palette,
+    // the line number or filetype being typed, see status.rs
+    pub status_prompt: Option<String>,
+    pub message: Option<String>,
+    pub dialog: Option<Dialog>,
+    pub dropped: Option<Dropped>,
+    pub tutor: Option<Tutor>,
+    pub macros: Macros,
+    // typed digits, for the command straight after
+    pub count: Option<usize>,
+    // centred, soft wrapped, and without the gutter, see zen.rs
+    pub zen: bool,
+    pub last_command: Option<&'static Command>,
+    // the status to exit with, once quitting
+    quit: Option<i32>,
+    // for screen readers, see announce.rs
+    pub announced: Announced,
+    pub session: Session,
 }
 
 impl State {
-    pub fn new(file: String) -> Result<Self> {
-        let text = fs::read_to_string(&file)?
-            .lines()
-            .map(|s| Line::new(s.to_string(), None))
+    // for text that didn't come from disk, which is saved to file as normal
+    pub fn from_bytes(file: String, bytes: &[u8], config: Config, keymap: Keymap) -> Self {
+        let (lines, endings) = line::decode_file(bytes);
+        let crlf = endings.crlf.unwrap_or(config.crlf);
+        let large =
+            bytes.len() as u64 > config.max_file_size || lines.len() > config.max_file_lines;
+        let temp_file = TempFile::detect(&file);
+        let language = temp_file.and_then(TempFile::language).unwrap_or_else(|| {
+            Language::from_filetype(Path::new(&file).extension().and_then(|ext| ext.to_str()))
+        });
+        let ruler = temp_file
+            .and_then(|temp_file| temp_file.ruler)
+            .filter(|_| !config.accessible);
+        let text: Vec<Line> = lines
+            .into_iter()
+            .map(|(s, invalid)| {
+                let mut line = if large {
+                    Line::plain(s)
+                } else {
+                    Line::new(s, language.comment(), None)
+                };
+                line.1.invalid = invalid;
+                line
+            })
             .collect();
-        Ok(Self {
-            mode: Mode::Normal,
+        let ansi = text.iter().any(|line| line.0.contains("\x1b["));
+        let message = if large {
+            Some(format!(
+                "Large file ({} bytes, {} lines): highlighting disabled",
+                bytes.len(),
+                text.len(),
+            ))
+        } else if text.iter().any(|line| !line.1.invalid.is_empty()) {
+            Some("File contains invalid UTF-8, shown as \u{FFFD}".to_string())
+        } else if endings.mixed {
+            Some("File mixes CRLF and LF line endings, kept as they are".to_string())
+        } else if text.iter().any(|line| !is_nfc(&line.0))
+            && text.iter().any(|line| !is_nfd(&line.0))
+        {
+            Some("File mixes NFC and NFD normalization forms".to_string())
+        } else if ansi {
+            Some(
+                "File contains ANSI escapes, shown as colours (toggle-ansi for the raw text)"
+                    .to_string(),
+            )
+        } else if let Some(temp_file) = temp_file {
+            Some(format!(
+                "Editing for {}: quitting saves, quit-with-error aborts",
+                temp_file.name
+            ))
+        } else if !keymap.warnings.is_empty() {
+            Some(format!(
+                "Keymap has {} warnings, run keymap for details",
+                keymap.warnings.len(),
+            ))
+        } else {
+            None
+        };
+        let kill_ring_size = config.kill_ring_size;
+        let stamp = disk::stamp(&file);
+        let buffer = Buffer {
             file,
             text,
+            encryption: None,
+            dirty: false,
+            crlf,
+            eol: endings.eol,
+            new: false,
+            scratch: false,
+            stamp,
+            temp_file,
+            large,
+            language,
+            filetype_override: None,
+            ruler,
             cursor: Cursor { w: 0, x: 0, y: 0 },
             anchor: None,
+            block: false,
+            expansions: Expansions::default(),
+            selections: Vec::new(),
             search: None,
-        })
+            replace: None,
+            pager: None,
+            idle: Idle::default(),
+            edits: Recorder::default(),
+            history: History::default(),
+            marks: Marks::default(),
+            bookmarks: HashMap::new(),
+            annotations: BTreeMap::new(),
+            coverage: None,
+            ansi,
+            last_paste: None,
+            last_click: None,
+            recovered: None,
+            autosaved: None,
+            git: None,
+            git_checked: None,
+        };
+        Self {
+            config,
+            mode: Mode::Normal,
+            buffers: vec![buffer],
+            active: 0,
+            path_prompt: None,
+            deleting: None,
+            filter: None,
+            search_prompt: None,
+            session_prompt: None,
+            search_history: Recent::default(),
+            keymap,
+            windows: vec![Window::new(0)],
+            layout: Layout::Window(0),
+            window: 0,
+            dragging: None,
+            hovering: None,
+            tooltip: None,
+            trusted: None,
+            trusting: None,
+            distrusted: Vec::new(),
+            screen: (0, 0),
+            size: (0, 0),
+            kills: Ring::new(kill_ring_size),
+            registers: HashMap::new(),
+            register: None,
+            palette: Palette::default(),
+            status_prompt: None,
+            message,
+            dialog: None,
+            dropped: None,
+            tutor: None,
+            macros: Macros::default(),
+            count: None,
+            zen: false,
+            last_command: None,
+            quit: None,
+            announced: Announced::default(),
+            session: Session::default(),
+        }
     }
 
     pub fn handle(&mut self, event: KeyEvent) -> bool {
-        match self.mode {
-            Mode::Normal => {
-                match event.code {
-                    KeyCode::Char('q') => self.select_inside_quotes(),
-                    KeyCode::Char('w') => self.select_word(|c| c.is_alphanumeric() || c == '_'),
-                    KeyCode::Char('e') => self.select_inside_brackets(),
-                    KeyCode::Char('r') => self.select_line(),
-                    KeyCode::Char('y') => self.move_start_of_line(),
-                    KeyCode::Char('u') => self.move_left_word(|c| c.is_alphanumeric() || c == '_'),
-                    KeyCode::Char('i') => self.move_right_word(|c| c.is_alphanumeric() || c == '_'),
-                    KeyCode::Char('o') => self.move_end_of_line(),
-                    KeyCode::Char('p') => self.move_bracket_inside(),
-                    KeyCode::Char('s') => self.anchor = Some(self.cursor),
-                    KeyCode::Char('f') => self.begin_edit(),
-                    KeyCode::Char('h') | KeyCode::Left => self.move_left(1),
-                    KeyCode::Char('j') | KeyCode::Down => self.move_down(1),
-                    KeyCode::Char('k') | KeyCode::Up => self.move_up(1),
-                    KeyCode::Char('l') | KeyCode::Right => self.move_right(1),
-                    KeyCode::Char('n') => self.move_start_of_file(),
-                    KeyCode::Char('m') => self.move_next_match(),
-                    KeyCode::Char(',') => self.move_prev_match(),
-                    KeyCode::Char('.') => self.move_end_of_file(),
-                    KeyCode::Char('/') => self.search(),
-                    KeyCode::Char('Q') => self.select_outside_quotes(),
-                    KeyCode::Char('W') => self.select_word(|c| !c.is_whitespace()),
-                    KeyCode::Char('E') => self.select_outside_brackets(),
-                    KeyCode::Char('R') => self.select_para(),
-                    KeyCode::Char('Y') => self.move_start_of_para(),
-                    KeyCode::Char('U') => self.move_left_word(|c| !c.is_whitespace()),
-                    KeyCode::Char('I') => self.move_right_word(|c| !c.is_whitespace()),
-                    KeyCode::Char('O') => self.move_end_of_para(),
-                    KeyCode::Char('P') => self.move_bracket_outside(),
-                    KeyCode::Char('H') => self.move_left(5),
-                    KeyCode::Char('J') => self.move_down(5),
-                    KeyCode::Char('K') => self.move_up(5),
-                    KeyCode::Char('L') => self.move_right(5),
-                    KeyCode::Esc => {
-                        if self.anchor.is_some() {
-                            self.anchor = None
-                        } else {
-                            self.cancel_search()
-                        }
-                    }
-                    KeyCode::Char(' ') => {
-                        self.mode = Mode::System;
+        self.hide_tooltip();
+        self.macros.capture(event);
+        self.checkpoint();
+        let key = Key::from(event);
+        let command = self.keymap.lookup(self, key);
+        // System mode is a prefix, so any key returns us to normal mode
+        if self.mode == Mode::System {
+            self.mode = Mode::Normal;
+        }
+        self.message = None;
+        // a chosen register or count only applies to the command straight after choosing it
+        let register = self.register.take();
+        let count = self.count.take();
+        // characters typed without control, for the modes that take text
+        let typed = match key {
+            Key {
+                code: KeyCode::Char(c),
+                ctrl: false,
+            } => Some(c),
+            _ => None,
+        };
+        match (command, typed) {
+            _ if self.mode == Mode::Key => self.insert_binding(key),
+            (Some(command), _) => {
+                self.register = register;
+                self.count = count;
+                self.run(command);
+                self.register = None;
+                self.count = None;
+            }
+            (None, Some(c))
+                if self.mode == Mode::Normal
+                    && c.is_ascii_digit()
+                    && (c != '0' || count.is_some()) =>
+            {
+                self.register = register;
+                let count = count.unwrap_or(0).saturating_mul(10);
+                let count = count.saturating_add(c.to_digit(10).unwrap() as usize);
+                self.count = Some(usize::min(count, MAX_COUNT));
+            }
+            (None, Some(c)) if self.mode == Mode::Command => self.palette_insert(c),
+            (None, Some(c)) if self.mode == Mode::Search => self.search_insert(c),
+            (None, Some(c)) if self.mode == Mode::Session => self.session_insert(c),
+            (None, Some(c)) if self.status_prompt.is_some() => self.status_prompt_insert(c),
+            (None, Some(c)) if self.mode == Mode::Replace => self.replace_insert(c),
+            (None, Some(c)) if self.mode == Mode::Filter => self.filter_insert(c),
+            (None, Some(c)) if self.mode == Mode::Path => self.path_insert(c),
+            (None, Some(c)) if self.mode == Mode::Dialog => self.answer_other(c),
+            (None, Some(c)) if self.mode == Mode::Insert => self.insert_char(c),
+            (None, Some(c)) if self.mode == Mode::Register => {
+                self.mode = Mode::Normal;
+                if c.is_ascii_lowercase() {
+                    self.register = Some(c);
+                } else {
+                    self.message = Some(format!("Invalid register: {}", c));
+                }
+            }
+            _ => (),
+        }
+        self.fixup();
+        self.check_tutor();
+        self.commit();
+        self.schedule_session();
+        if self.config.check_invariants {
+            self.check_invariants(event);
+        }
+        self.quit.is_none()
+    }
+
+    // runs whatever's pending, returning whether anything on screen might have changed
+    pub fn run_idle(&mut self) -> bool {
+        let mut changed = false;
+        for task in self.idle.take() {
+            match task {
+                Task::Coverage => changed |= self.refresh_coverage(),
+                Task::Recover => self.write_recovery(),
+                Task::Autosave => changed |= self.autosave(),
+                Task::GitStatus => changed |= self.refresh_git(),
+                Task::Session => self.write_session(),
+                Task::Reannotate => {
+                    let token = self.idle.token(task);
+                    changed = true;
+                    let start = self.idle.progress.as_ref().map_or(0, |p| p.done);
+                    let end = usize::min(start + idle::CHUNK, self.text.len());
+                    self.annotate(start..end);
+                    if end < self.text.len() {
+                        self.idle.progress = Some(Progress {
+                            task,
+                            token,
+                            label: "Annotating",
+                            done: end,
+                            total: self.text.len(),
+                        });
+                        self.idle.schedule(task);
+                    } else {
+                        self.idle.progress = None;
                     }
-                    _ => (),
+                }
+                Task::Hover => changed |= self.show_tooltip(),
+            }
+        }
+        changed
+    }
+
+    pub fn cancel_task(&mut self) {
+        if let Some(progress) = self.idle.running() {
+            let (task, label) = (progress.task, progress.label);
+            self.idle.cancel(task);
+            self.message = Some(format!("Cancelled {}", label.to_lowercase()));
+        }
+    }
+
+    // Esc typed while a slow key was being handled. Whatever was typed before it was typed at a
+    // screen that was out of date, so it's dropped, and any background task is stopped too.
+    pub fn interrupt(&mut self) {
+        if self.idle.running().is_some() {
+            self.cancel_task();
+        } else {
+            self.message = Some("Interrupted, ignoring keys typed before esc".to_string());
+        }
+    }
+
+    // the first line to draw, keeping the cursor centred where possible
+    pub fn offset(&self) -> usize {
+        let h = self.height();
+        if self.cursor.y < h / 2 || self.text.len() <= h {
+            0
+        } else if self.cursor.y - h / 2 + h <= self.text.len() {
+            self.cursor.y - h / 2
+        } else {
+            self.text.len() - h
+        }
+    }
+
+    // number of lines available for text, leaving room for the status and search lines
+    pub fn height(&self) -> usize {
+        (self.size.1 as usize).saturating_sub(2)
+    }
+
+    pub fn visible(&self) -> Range<usize> {
+        let offset = if self.zen {
+            self.zen_top().0
+        } else {
+            self.offset()
+        };
+        offset..usize::min(offset + self.height(), self.text.len())
+    }
+
+    fn annotate(&mut self, range: Range<usize>) {
+        let re = self.line_search();
+        for line in &mut self.text[range] {
+            line.annotate(re.as_ref());
+        }
+    }
+
+    // Annotates visible lines immediately and leaves the rest until we're idle. Multi-line
+    // searches can't be split up like that, so they're done all at once.
+    pub fn reannotate(&mut self) {
+        if let Some(re) = self.multi_line_search() {
+            self.idle.cancel(Task::Reannotate);
+            self.annotate_multi_line(&re);
+            return;
+        }
+        self.annotate(self.visible());
+        // start again from the top, since lines already done are now out of date
+        self.idle.cancel(Task::Reannotate);
+        self.idle.schedule(Task::Reannotate);
+    }
+
+    // annotates lines in range with the search as it is now, for a window drawn with its own
+    pub fn annotate_lines(&mut self, range: Range<usize>) {
+        match self.multi_line_search() {
+            Some(re) if !range.is_empty() => {
+                self.reannotate_multi_line(&re, range.start, range.end - 1)
+            }
+            _ => self.annotate(range),
+        }
+    }
+
+    // match navigation needs every line annotated, so it can't wait
+    fn flush_reannotate(&mut self) {
+        if self.idle.cancel(Task::Reannotate) {
+            self.annotate(0..self.text.len());
+        }
+    }
+
+    // replaces the contents of line y, recomputing its annotations
+    fn set_line(&mut self, y: usize, s: String) {
+        self.dirty = true;
+        let re = self.line_search();
+        self.text[y] = if self.large {
+            let mut line = Line::plain(s);
+            line.annotate(re.as_ref());
+            line
+        } else {
+            Line::new(s, self.language.comment(), re.as_ref())
+        };
+    }
+
+    // Replaces the text between start and end with text, which may span several lines, and
+    // returns the end of the inserted text. The cursor and anchor are moved along with the text
+    // around them, and collapse to the start if they were inside the replaced range.
+    pub fn splice(&mut self, start: Point, end: Point, text: &str) -> Point {
+        self.record_change(start, end, text);
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let last = lines.len() - 1;
+        let new_end = Point {
+            x: lines[last].len() + if last == 0 { start.x } else { 0 },
+            y: start.y + last,
+        };
+        lines[0].insert_str(0, &self.text[start.y].0[..start.x]);
+        lines[last].push_str(&self.text[end.y].0[end.x..]);
+        // placeholders either side of the replaced text are still there, wherever they've moved
+        let mut before = self.text[start.y].1.invalid.clone();
+        before.retain(|&(i, _)| i < start.x);
+        let mut after = self.text[end.y].1.invalid.clone();
+        after.retain(|&(i, _)| i >= end.x);
+        for (i, _) in &mut after {
+            *i = *i - end.x + new_end.x;
+        }
+        self.text.splice(
+            start.y..=end.y,
+            lines.iter().map(|_| Line::plain(String::new())),
+        );
+        let shifted = lines.len() != end.y - start.y + 1;
+        for (i, line) in lines.into_iter().enumerate() {
+            self.set_line(start.y + i, line);
+        }
+        self.text[start.y].1.invalid = before;
+        self.text[new_end.y].1.invalid.extend(after);
+        if self.config.recovery {
+            self.idle.schedule(Task::Recover);
+        }
+        if self.config.autosave {
+            self.idle.schedule(Task::Autosave);
+        }
+        // lines have moved under any annotation in progress, so it has to start again
+        if shifted && self.idle.cancel(Task::Reannotate) {
+            self.idle.schedule(Task::Reannotate);
+        }
+        if let Some(re) = self.multi_line_search() {
+            self.reannotate_multi_line(&re, start.y, new_end.y);
+        }
+        self.marks.shift(start, end, new_end);
+        let shift = |p| shift(p, start, end, new_end);
+        if let Some(anchor) = self.anchor {
+            let Point { x, y } = shift(anchor.into());
+            self.anchor = Some(Cursor { x, y, ..anchor });
+        }
+        self.move_cursor(shift(self.cursor.into()));
+        new_end
+    }
+
+    // normalizes the selection, or the whole file if nothing is selected
+    pub fn normalize(&mut self, form: Option<Normalization>) {
+        let form = form
+            .or(self.config.normalization)
+            .unwrap_or(Normalization::Nfc);
+        self.break_undo();
+        let (start, end) = match self.selection() {
+            Some((start, end)) => (start.into(), end.into()),
+            None => (self.start_of_file(), self.end_of_file()),
+        };
+        self.transform(start, end, |text| form.apply(text));
+    }
+
+    // Replaces the text between start and end with f applied to it. Only the lines that
+    // actually change are replaced, so transforming the whole file is cheap when most of it is
+    // left alone. A selection is kept over the result so that transforms can be repeated.
+    pub fn transform(&mut self, start: Point, end: Point, f: impl FnOnce(&str) -> String) {
+        let old = self.text_between(start, end);
+        let new = f(&old);
+        if new == old {
+            return;
+        }
+        let old_lines: Vec<&str> = old.split('\n').collect();
+        let new_lines: Vec<&str> = new.split('\n').collect();
+        // unchanged lines at either end, leaving at least one line in the middle
+        let max = usize::min(old_lines.len(), new_lines.len()) - 1;
+        let head = old_lines
+            .iter()
+            .zip(&new_lines)
+            .take(max)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let tail = old_lines
+            .iter()
+            .rev()
+            .zip(new_lines.iter().rev())
+            .take(max - head)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let from = if head == 0 {
+            start
+        } else {
+            Point {
+                x: 0,
+                y: start.y + head,
+            }
+        };
+        let to = if tail == 0 {
+            end
+        } else {
+            self.end_of_line(end.y - tail)
+        };
+        let (cursor, anchor) = (self.cursor, self.anchor);
+        self.anchor = None;
+        let middle_end = self.splice(
+            from,
+            to,
+            &new_lines[head..new_lines.len() - tail].join("\n"),
+        );
+        let new_end = if tail == 0 {
+            middle_end
+        } else {
+            Point {
+                x: end.x,
+                y: end.y + new_lines.len() - old_lines.len(),
+            }
+        };
+        match anchor {
+            Some(anchor) => {
+                let (a, c) = if cursor < anchor {
+                    (new_end, start)
+                } else {
+                    (start, new_end)
                 };
+                self.move_cursor(a);
+                self.anchor = Some(self.cursor);
+                self.move_cursor(c);
             }
-            Mode::Insert => match event.code {
-                KeyCode::Esc => self.end_edit(),
-                _ => (),
-            },
-            Mode::System => match event.code {
-                KeyCode::Char('q') => {
-                    return false;
+            None => {
+                let y = usize::min(cursor.y, self.text.len() - 1);
+                self.move_cursor(self.clamp(Point { x: cursor.x, y }));
+            }
+        }
+    }
+
+    // the nearest valid position at or before point
+    pub fn clamp(&self, point: Point) -> Point {
+        let y = point.y.min(self.text.len() - 1);
+        let line = &self.text[y].0;
+        let mut x = if y < point.y {
+            line.len()
+        } else {
+            point.x.min(line.len())
+        };
+        while !line.is_char_boundary(x) {
+            x -= 1;
+        }
+        Point { x, y }
+    }
+
+    // Puts every position that points into the text back inside it. Edits made through splice
+    // keep positions up to date themselves, but anything that replaces lines wholesale can
+    // leave them dangling, so this runs after every key.
+    pub fn fixup(&mut self) {
+        if self.text.is_empty() {
+            let comment = self.language.comment();
+            self.text.push(Line::new(String::new(), comment, None));
+        }
+        let cursor = self.clamp(self.cursor.into());
+        if cursor != self.cursor.into() {
+            self.move_cursor(cursor);
+        }
+        if self.anchor.is_none() {
+            self.block = false;
+        }
+        if let Some(anchor) = self.anchor {
+            let point = self.clamp(anchor.into());
+            if point != anchor.into() {
+                self.anchor = Some(Cursor {
+                    y: point.y,
+                    x: point.x,
+                    w: self.drawn_width(point.y, 0..point.x),
+                });
+            }
+        }
+        if let Some((_, start, end, _)) = self.last_paste {
+            if self.clamp(start) != start || self.clamp(end) != end {
+                self.last_paste = None;
+            }
+        }
+        let mut marks = std::mem::take(&mut self.marks);
+        marks.map(|point| self.clamp(point));
+        self.marks = marks;
+    }
+
+    // the text as it's written to disk, before any encryption
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (y, line) in self.text.iter().enumerate() {
+            bytes.extend(line.bytes());
+            if y + 1 < self.text.len() || self.ends_in_newline() {
+                if self.crlf {
+                    bytes.push(b'\r');
+                }
+                bytes.push(b'\n');
+            }
+        }
+        bytes
+    }
+
+    pub fn save(&mut self) {
+        if self.scratch {
+            self.message = Some(format!("{} isn't a file, so can't be saved", self.file));
+            return;
+        }
+        if self.changed_on_disk() {
+            self.ask_changed_on_disk();
+            return;
+        }
+        if let Some(form) = self.config.normalization {
+            self.transform(self.start_of_file(), self.end_of_file(), |text| {
+                form.apply(text)
+            });
+        }
+        let mut bytes = self.encode();
+        if let Some(encryption) = &self.encryption {
+            bytes = match encryption.encrypt(&bytes) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    self.message = Some(format!("Error encrypting {}: {}", self.file, err));
+                    return;
                 }
-                _ => {
-                    self.mode = Mode::Normal;
+            };
+        }
+        // the last saved version, kept in case this one's a mistake
+        if self.config.backup && !self.new {
+            if let Err(err) = fs::copy(&self.file, format!("{}~", self.file)) {
+                self.message = Some(format!("Error backing up {}: {}", self.file, err));
+                return;
+            }
+        }
+        // along with any directories it's in that don't exist yet either
+        if self.new {
+            if let Some(dir) = Path::new(&self.file).parent() {
+                if let Err(err) = fs::create_dir_all(dir) {
+                    self.message = Some(format!("Error creating {}: {}", dir.display(), err));
+                    return;
                 }
-            },
+            }
         }
-        true
+        self.message = Some(match disk::write(&self.file, &bytes) {
+            Ok(()) => {
+                self.dirty = false;
+                self.new = false;
+                self.stamp = disk::stamp(&self.file);
+                self.discard_recovery();
+                self.idle.schedule(Task::GitStatus);
+                format!(
+                    "Wrote {} lines to {}{}",
+                    self.text.len(),
+                    self.file,
+                    if self.encryption.is_some() {
+                        " (encrypted)"
+                    } else {
+                        ""
+                    },
+                )
+            }
+            Err(err) => format!("Error writing {}: {}", self.file, err),
+        });
+    }
+
+    // Every sign that applies to line y. Subsystems that want a sign in the gutter add it here,
+    // and the gutter picks the highest priority one.
+    pub fn signs(&self, y: usize) -> Vec<Sign> {
+        let line = &self.text[y];
+        let mut signs: Vec<Sign> = self.annotation_signs(y).map(Sign::Annotation).collect();
+        match self.covered(y) {
+            Some(true) => signs.push(Sign::Covered),
+            Some(false) => signs.push(Sign::Uncovered),
+            None => (),
+        }
+        if !line.1.matches.is_empty() {
+            signs.push(Sign::Match);
+        }
+        if !line.1.invalid.is_empty() {
+            signs.push(Sign::Invalid);
+        }
+        signs
+    }
+
+    // columns taken up by the gutter, to the left of the text
+    pub fn gutter_width(&self) -> u16 {
+        if self.config.sign_column {
+            2
+        } else {
+            0
+        }
+    }
+
+    // the line drawn at the given row, if any
+    pub fn line_at(&self, row: u16) -> Option<usize> {
+        let y = self.offset() + row as usize;
+        ((row as usize) < self.height() && y < self.text.len()).then_some(y)
+    }
+
+    // the point in the text drawn at the given screen position, if any
+    pub fn point_at(&self, column: u16, row: u16) -> Option<Point> {
+        if self.zen {
+            return self.zen_point_at(column, row);
+        }
+        let y = self.offset() + row as usize;
+        if row as usize >= self.height() || y >= self.text.len() || column < self.gutter_width() {
+            return None;
+        }
+        let column = column - self.gutter_width();
+        let mut w = 0;
+        for (x, _, cw) in self.drawn_chars(y) {
+            w += cw;
+            if w > column as usize {
+                return Some(Point { x, y });
+            }
+        }
+        Some(self.end_of_line(y))
+    }
+
+    pub fn click(&mut self, column: u16, row: u16) {
+        self.hide_tooltip();
+        let (column, row) = match self.click_window(column, row) {
+            Some(at) => at,
+            None => return,
+        };
+        if row + 2 == self.size.1 {
+            self.click_status(column);
+            return;
+        }
+        if self.click_minimap(column, row) {
+            return;
+        }
+        if !self.zen && column < self.gutter_width() {
+            self.click_gutter(row);
+            return;
+        }
+        let point = match self.point_at(column, row) {
+            Some(point) => point,
+            None => return,
+        };
+        let now = Instant::now();
+        let clicks = match self.last_click {
+            Some((t, p, n)) if p == point && now - t < MULTI_CLICK => n % 3 + 1,
+            _ => 1,
+        };
+        self.last_click = Some((now, point, clicks));
+        self.anchor = None;
+        self.move_cursor(point);
+        match clicks {
+            2 => self.select_word(self.wordish()),
+            3 => self.select_line(),
+            _ => (),
+        }
+    }
+
+    pub fn filetype(&self) -> Option<&str> {
+        if let Some(filetype) = &self.filetype_override {
+            return Some(filetype);
+        }
+        Path::new(&self.file)
+            .extension()
+            .and_then(|ext| ext.to_str())
+    }
+
+    // Whether a character is part of a word, for word motions: letters, digits and underscores,
+    // and whatever else the language uses in identifiers, like - in CSS.
+    pub fn wordish(&self) -> impl Fn(char) -> bool {
+        let extra = self
+            .filetype()
+            .and_then(|filetype| self.config.word_chars.get(filetype))
+            .map_or(self.language.word_chars, String::as_str)
+            .to_string();
+        move |c| command::is_word(c) || extra.contains(c)
+    }
+
+    pub fn char_width(&self, c: char) -> usize {
+        self.config.ambiguous_width.char_width(c)
+    }
+
+    pub fn str_width(&self, s: &str) -> usize {
+        self.config.ambiguous_width.str_width(s)
     }
 
     pub fn cursor_width(&self) -> usize {
-        self.text[self.cursor.y].0[..self.cursor.x].width()
+        self.drawn_width(self.cursor.y, 0..self.cursor.x)
     }
 
     pub fn selection(&self) -> Option<(Cursor, Cursor)> {
@@ -157,27 +944,57 @@ impl State {
         })
     }
 
-    fn search(&mut self) {
-        if let Some(selection) = self.selection() {
-            if selection.0.y == selection.1.y {
-                self.search = Some(Regex::new(&regex::escape(
-                    &self.text[selection.0.y].0[selection.0.x..selection.1.x],
-                )));
-                for line in &mut self.text {
-                    line.annotate(self.search.as_ref().and_then(|r| r.as_ref().ok()));
-                }
-            }
+    pub fn move_next_match(&mut self) {
+        self.flush_reannotate();
+        let cursor = Point::from(self.cursor);
+        let next = (cursor.y..self.text.len())
+            .chain(0..=cursor.y)
+            .enumerate()
+            .find_map(|(i, y)| {
+                self.text[y].1.matches.iter().find_map(|&(x, _)| {
+                    let p = Point { x, y };
+                    // the first pass over the cursor's line only looks ahead, the wrapped one
+                    // only looks behind
+                    if (i == 0 && p > cursor) || (i > 0 && (y != cursor.y || p <= cursor)) {
+                        Some(p)
+                    } else {
+                        None
+                    }
+                })
+            });
+        if let Some(point) = next {
+            self.move_cursor(point);
         }
     }
 
-    fn cancel_search(&mut self) {
-        self.search = None;
-        for line in &mut self.text {
-            line.annotate(None);
+    pub fn move_prev_match(&mut self) {
+        self.flush_reannotate();
+        let cursor = Point::from(self.cursor);
+        let prev = (0..=cursor.y)
+            .rev()
+            .chain((cursor.y..self.text.len()).rev())
+            .enumerate()
+            .find_map(|(i, y)| {
+                self.text[y].1.matches.iter().rev().find_map(|&(x, _)| {
+                    let p = Point { x, y };
+                    if (i == 0 && p < cursor) || (i > 0 && (y != cursor.y || p >= cursor)) {
+                        Some(p)
+                    } else {
+                        None
+                    }
+                })
+            });
+        if let Some(point) = prev {
+            self.move_cursor(point);
         }
     }
 
-    fn move_cursor(&mut self, point: Point) {
+    pub fn cancel_search(&mut self) {
+        self.search = None;
+        self.reannotate();
+    }
+
+    pub fn move_cursor(&mut self, point: Point) {
         self.cursor.y = point.y;
         self.cursor.x = point.x;
         self.cursor.w = self.cursor_width();
@@ -205,20 +1022,54 @@ impl State {
         })
     }
 
+    // like right_of, but steps over line breaks
+    pub fn next_point(&self, point: Point) -> Option<Point> {
+        self.right_of(point).or(if point.y + 1 < self.text.len() {
+            Some(Point {
+                x: 0,
+                y: point.y + 1,
+            })
+        } else {
+            None
+        })
+    }
+
+    // like left_of, but steps over line breaks
+    pub fn prev_point(&self, point: Point) -> Option<Point> {
+        self.left_of(point).or(if point.y > 0 {
+            Some(self.end_of_line(point.y - 1))
+        } else {
+            None
+        })
+    }
+
+    pub fn text_between(&self, start: Point, end: Point) -> String {
+        if start.y == end.y {
+            return self.text[start.y].0[start.x..end.x].to_string();
+        }
+        let mut text = self.text[start.y].0[start.x..].to_string();
+        for line in &self.text[start.y + 1..end.y] {
+            text.push('\n');
+            text.push_str(&line.0);
+        }
+        text.push('\n');
+        text.push_str(&self.text[end.y].0[..end.x]);
+        text
+    }
+
     fn update_x(&mut self) {
         let mut w = 0;
-        self.cursor.x = 0;
-        for (x, c) in self.text[self.cursor.y].0.char_indices() {
-            self.cursor.x = x;
-            w += c.width().unwrap_or(0);
-            if w > self.cursor.w {
-                return;
-            }
-        }
-        self.cursor.x = self.text[self.cursor.y].0.len()
+        let x = self
+            .drawn_chars(self.cursor.y)
+            .find(|&(_, _, cw)| {
+                w += cw;
+                w > self.cursor.w
+            })
+            .map(|(x, _, _)| x);
+        self.cursor.x = x.unwrap_or(self.text[self.cursor.y].0.len());
     }
 
-    fn move_up(&mut self, dist: usize) {
+    pub fn move_up(&mut self, dist: usize) {
         if self.cursor.y > dist {
             self.cursor.y -= dist;
         } else {
@@ -227,36 +1078,41 @@ impl State {
         self.update_x();
     }
 
-    fn move_down(&mut self, dist: usize) {
-        if self.cursor.y + dist < self.text.len() {
-            self.cursor.y += dist;
-        } else {
-            self.cursor.y = self.text.len() - 1
-        }
+    pub fn move_down(&mut self, dist: usize) {
+        self.cursor.y = usize::min(self.cursor.y.saturating_add(dist), self.text.len() - 1);
         self.update_x();
     }
 
-    fn move_left(&mut self, dist: usize) {
+    pub fn move_left(&mut self, dist: usize) {
         for _ in 0..dist {
-            if let Some(c) = self.prev_char(self.cursor.into()) {
+            if self.virtual_columns() > 0 {
+                self.cursor.w -= 1;
+            } else if let Some(c) = self.prev_char(self.cursor.into()) {
                 self.cursor.x -= c.len_utf8();
+                self.cursor.w = self.cursor_width();
             }
         }
-        self.cursor.w = self.cursor_width();
     }
 
-    fn move_right(&mut self, dist: usize) {
+    // past the end of the line with virtual_edit on, see virtual_edit.rs
+    pub fn move_right(&mut self, dist: usize) {
         for _ in 0..dist {
-            if let Some(c) = self.next_char(self.cursor.into()) {
-                self.cursor.x += c.len_utf8();
+            match self.next_char(self.cursor.into()) {
+                Some(c) => {
+                    self.cursor.x += c.len_utf8();
+                    self.cursor.w = self.cursor_width();
+                }
+                None if self.config.virtual_edit => {
+                    self.cursor.w = usize::max(self.cursor.w, self.cursor_width()) + 1;
+                }
+                None => self.cursor.w = self.cursor_width(),
             }
         }
-        self.cursor.w = self.cursor_width();
     }
 
-    fn left_word(&self, mut wordish: impl FnMut(char) -> bool, point: Point) -> Option<Point> {
+    pub fn left_word(&self, mut wordish: impl FnMut(char) -> bool, point: Point) -> Option<Point> {
         let mut point = point;
-        let mut seen_word = self.next_char(point).map_or(false, &mut wordish);
+        let mut seen_word = self.next_char(point).is_some_and(&mut wordish);
         for c in self.text[point.y].0[..point.x].chars().rev() {
             if seen_word && !wordish(c) {
                 break;
@@ -272,9 +1128,9 @@ impl State {
         }
     }
 
-    fn right_word(&self, mut wordish: impl FnMut(char) -> bool, point: Point) -> Option<Point> {
+    pub fn right_word(&self, mut wordish: impl FnMut(char) -> bool, point: Point) -> Option<Point> {
         let mut point = point;
-        let mut seen_word = self.prev_char(point).map_or(false, &mut wordish);
+        let mut seen_word = self.prev_char(point).is_some_and(&mut wordish);
         for c in self.text[point.y].0[point.x..].chars() {
             if seen_word && !wordish(c) {
                 break;
@@ -290,7 +1146,7 @@ impl State {
         }
     }
 
-    fn move_left_word(&mut self, wordish: impl FnMut(char) -> bool) {
+    pub fn move_left_word(&mut self, wordish: impl FnMut(char) -> bool) {
         if let Some(left) = self.left_of(self.cursor.into()) {
             if let Some(point) = self.left_word(wordish, left) {
                 self.move_cursor(point);
@@ -298,7 +1154,7 @@ impl State {
         }
     }
 
-    fn move_right_word(&mut self, wordish: impl FnMut(char) -> bool) {
+    pub fn move_right_word(&mut self, wordish: impl FnMut(char) -> bool) {
         if let Some(right) = self.right_of(self.cursor.into()) {
             if let Some(point) = self.right_word(wordish, right) {
                 self.move_cursor(point);
@@ -306,7 +1162,7 @@ impl State {
         }
     }
 
-    fn start_of_line(&self, y: usize) -> Point {
+    pub fn start_of_line(&self, y: usize) -> Point {
         for (x, c) in self.text[y].0.char_indices() {
             if !c.is_whitespace() {
                 return Point { x, y };
@@ -315,22 +1171,22 @@ impl State {
         Point { x: 0, y }
     }
 
-    fn end_of_line(&self, y: usize) -> Point {
+    pub fn end_of_line(&self, y: usize) -> Point {
         Point {
             x: self.text[y].0.len(),
             y,
         }
     }
 
-    fn move_start_of_line(&mut self) {
+    pub fn move_start_of_line(&mut self) {
         self.move_cursor(self.start_of_line(self.cursor.y));
     }
 
-    fn move_end_of_line(&mut self) {
+    pub fn move_end_of_line(&mut self) {
         self.move_cursor(self.end_of_line(self.cursor.y));
     }
 
-    fn open_quote(&self, point: Point) -> Option<Point> {
+    pub fn open_quote(&self, point: Point) -> Option<Point> {
         for y in (0..=point.y).rev() {
             let mut x = if y == point.y {
                 point.x
@@ -347,7 +1203,7 @@ impl State {
         None
     }
 
-    fn close_quote(&self, point: Point) -> Option<Point> {
+    pub fn close_quote(&self, point: Point) -> Option<Point> {
         for y in point.y..self.text.len() {
             let mut x = if y == point.y { point.x } else { 0 };
             for c in self.text[y].0[x..].chars() {
@@ -360,7 +1216,7 @@ impl State {
         None
     }
 
-    fn close_bracket(&self, point: Point) -> Option<Point> {
+    pub fn close_bracket(&self, point: Point) -> Option<Point> {
         let mut pending = Vec::new();
         for y in point.y..self.text.len() {
             let mut x = if y == point.y { point.x } else { 0 };
@@ -381,7 +1237,7 @@ impl State {
         None
     }
 
-    fn open_bracket(&self, point: Point) -> Option<Point> {
+    pub fn open_bracket(&self, point: Point) -> Option<Point> {
         let mut pending = Vec::new();
         for y in (0..=point.y).rev() {
             let mut x = if y == point.y {
@@ -406,7 +1262,7 @@ impl State {
         None
     }
 
-    fn start_of_para(&self, point: Point) -> Point {
+    pub fn start_of_para(&self, point: Point) -> Point {
         let mut point = point;
         while point.y > 1 {
             if !self.text[point.y].0.is_empty() && self.text[point.y - 1].0.is_empty() {
@@ -417,7 +1273,7 @@ impl State {
         self.start_of_file()
     }
 
-    fn end_of_para(&self, point: Point) -> Point {
+    pub fn end_of_para(&self, point: Point) -> Point {
         let mut point = point;
         while point.y + 1 < self.text.len() {
             if !self.text[point.y].0.is_empty() && self.text[point.y + 1].0.is_empty() {
@@ -428,7 +1284,7 @@ impl State {
         self.end_of_file()
     }
 
-    fn move_bracket_inside(&mut self) {
+    pub fn move_bracket_inside(&mut self) {
         if let Some(']' | '}' | ')') = self.next_char(self.cursor.into()) {
             if let Some(Point { x, y }) = self.open_bracket(self.cursor.into()) {
                 self.move_cursor(Point { y, x: x + 1 });
@@ -438,7 +1294,7 @@ impl State {
         }
     }
 
-    fn move_bracket_outside(&mut self) {
+    pub fn move_bracket_outside(&mut self) {
         if let Some('[' | '{' | '(') = self.next_char(self.cursor.into()) {
             if let Some(Point { x, y }) = self.close_bracket(Point {
                 x: self.cursor.x + 1,
@@ -456,64 +1312,373 @@ impl State {
         }
     }
 
-    fn move_start_of_para(&mut self) {
+    pub fn move_start_of_para(&mut self) {
         self.move_up(1);
         self.move_cursor(self.start_of_para(self.cursor.into()));
     }
 
-    fn move_end_of_para(&mut self) {
+    pub fn move_end_of_para(&mut self) {
         self.move_down(1);
         self.move_cursor(self.end_of_para(self.cursor.into()));
     }
 
-    fn start_of_file(&self) -> Point {
+    pub fn start_of_file(&self) -> Point {
         Point { x: 0, y: 0 }
     }
 
-    fn end_of_file(&self) -> Point {
+    pub fn end_of_file(&self) -> Point {
         self.end_of_line(self.text.len() - 1)
     }
 
-    fn move_start_of_file(&mut self) {
+    // a single empty line, which is what an empty file is
+    pub fn is_empty(&self) -> bool {
+        self.text.len() == 1 && self.text[0].0.is_empty()
+    }
+
+    // An empty file has no last line to end, but whatever's typed into one does, unless it's
+    // been said otherwise. That's only decided on saving, so that typing and then undoing it
+    // leaves the file as empty as it was.
+    pub fn ends_in_newline(&self) -> bool {
+        self.eol.unwrap_or(!self.is_empty())
+    }
+
+    pub fn toggle_eol(&mut self) {
+        self.eol = Some(!self.ends_in_newline());
+        self.dirty = true;
+        self.message = Some(if self.ends_in_newline() {
+            "File ends in a newline".to_string()
+        } else {
+            "File doesn't end in a newline".to_string()
+        });
+    }
+
+    pub fn move_start_of_file(&mut self) {
         self.move_cursor(self.start_of_file());
     }
 
-    fn move_end_of_file(&mut self) {
+    pub fn move_end_of_file(&mut self) {
         self.move_cursor(self.end_of_file());
     }
 
-    fn begin_edit(&mut self) {
+    pub fn begin_edit(&mut self) {
         self.mode = Mode::Insert;
+        self.record(EditOp::BeginEdit);
     }
 
-    fn end_edit(&mut self) {
+    pub fn end_edit(&mut self) {
         self.mode = Mode::Normal;
+        self.finish_recording();
+    }
+
+    // the count typed before the current command, or 1 without one
+    pub fn count(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+
+    // Runs f count times, for commands that don't take a count themselves, stopping early once
+    // the cursor stays put, since it would from then on.
+    pub fn repeat(&mut self, f: impl Fn(&mut Self)) {
+        for _ in 0..self.count() {
+            let cursor = self.cursor;
+            f(self);
+            if self.cursor == cursor {
+                break;
+            }
+        }
+    }
+
+    // runs command, and at each of the other selections too if it moves, see selections.rs
+    pub fn run(&mut self, command: &Command) {
+        (command.run)(self);
+        if command.moves && !self.selections.is_empty() {
+            self.each_selection(command.run);
+        }
+    }
+
+    pub fn repeat_last_command(&mut self) {
+        match self.last_command {
+            Some(command) => self.run(command),
+            None => self.message = Some("No command to repeat".to_string()),
+        }
+    }
+
+    // kills always go on the kill ring, and also into the named register if one was chosen
+    pub fn kill(&mut self, text: String) {
+        if let Some(name) = self.register {
+            self.registers.insert(name, text.clone());
+        }
+        self.kills.push(text);
+    }
+
+    // Inserts the named register, or the most recent kill, at the cursor and selects it. With a
+    // count the text is pasted that many times over. Unless verbatim, several lines are
+    // reindented to fit where they're pasted, see indent.rs.
+    pub fn paste(&mut self, verbatim: bool) {
+        self.record(EditOp::Paste(self.register, self.count(), verbatim));
+        self.pad_virtual();
+        match self.register {
+            Some(name) => {
+                if let Some(text) = self.registers.get(&name).cloned() {
+                    self.paste_text(&text.repeat(self.count()), verbatim);
+                    self.last_paste = None;
+                }
+            }
+            None => self.paste_kill(0, verbatim),
+        }
+    }
+
+    // after a paste, replaces the pasted text with the next oldest kill
+    pub fn paste_cycle(&mut self) {
+        match self.last_paste {
+            Some((i, start, end, verbatim)) if self.target() == (start, end) => {
+                self.anchor = None;
+                self.move_cursor(start);
+                self.splice(start, end, "");
+                let i = (i + 1) % self.kills.len();
+                self.paste_kill(i, verbatim);
+                self.message = Some(format!("Kill {}/{}", i + 1, self.kills.len()));
+            }
+            _ => self.message = Some("Nothing pasted to cycle".to_string()),
+        }
+    }
+
+    fn paste_kill(&mut self, i: usize, verbatim: bool) {
+        if let Some(text) = self.kills.get(i).map(|text| text.repeat(self.count())) {
+            let start = self.paste_text(&text, verbatim);
+            self.last_paste = Some((i, start, self.cursor.into(), verbatim));
+        }
+    }
+
+    // returns where the pasted text starts
+    fn paste_text(&mut self, text: &str, verbatim: bool) -> Point {
+        let text = if verbatim || !self.config.auto_indent || !text.contains('\n') {
+            text.to_string()
+        } else {
+            self.fit_indent(text)
+        };
+        let start = self.cursor.into();
+        self.anchor = None;
+        self.insert(&text);
+        self.anchor = Some(self.cursor);
+        self.move_cursor(start);
+        self.invert_selection();
+        start
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.pad_virtual();
+        let cursor = self.cursor.into();
+        let end = self.splice(cursor, cursor, text);
+        self.move_cursor(end);
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.record(EditOp::InsertChar(c));
+        self.insert(c.encode_utf8(&mut [0; 4]));
+    }
+
+    // splits the line at the cursor, moving the cursor to the start of the new line
+    pub fn newline(&mut self) {
+        self.record(EditOp::Newline);
+        // the rest of the line moves down, so there's nothing to pad
+        self.clear_virtual();
+        let line = &self.text[self.cursor.y].0;
+        // only the indent before the cursor, the rest moves to the new line anyway
+        let indent = if self.config.auto_indent {
+            &line[..usize::min(line.len() - line.trim_start().len(), self.cursor.x)]
+        } else {
+            ""
+        };
+        self.insert(&format!("\n{}", indent));
+    }
+
+    // starts inserting on a new line below the cursor's line, or above it, with the same indent
+    pub fn open_line(&mut self, above: bool) {
+        let y = self.cursor.y;
+        let line = &self.text[y].0;
+        let indent = if self.config.auto_indent {
+            line[..line.len() - line.trim_start().len()].to_string()
+        } else {
+            String::new()
+        };
+        self.anchor = None;
+        if above {
+            let start = Point { x: 0, y };
+            self.splice(start, start, &format!("{}\n", indent));
+            self.move_cursor(Point { x: indent.len(), y });
+        } else {
+            let end = self.end_of_line(y);
+            let new_end = self.splice(end, end, &format!("\n{}", indent));
+            self.move_cursor(new_end);
+        }
+        self.mode = Mode::Insert;
+        // after the edit, so that the recording carries on through the insert
+        self.record(EditOp::OpenLine(above));
+    }
+
+    // deletes the char before the cursor, joining with the previous line at the start of a line
+    pub fn backspace(&mut self) {
+        self.record(EditOp::Backspace);
+        if self.virtual_columns() > 0 {
+            self.cursor.w -= 1;
+            return;
+        }
+        let cursor = self.cursor.into();
+        if let Some(prev) = self.prev_point(cursor) {
+            self.splice(prev, cursor, "");
+        }
+    }
+
+    // deletes the char under the cursor, joining with the next line at the end of a line
+    pub fn delete(&mut self) {
+        self.record(EditOp::Delete);
+        if self.virtual_columns() > 0 {
+            return;
+        }
+        let cursor = self.cursor.into();
+        if let Some(next) = self.next_point(cursor) {
+            self.splice(cursor, next, "");
+        }
+    }
+
+    // Joins the selected lines, or the cursor's line and the next, replacing the indentation of
+    // each joined line with a single space. The cursor ends up where the last join was.
+    pub fn join_lines(&mut self) {
+        self.record(EditOp::JoinLines);
+        let (first, last) = self.selected_lines();
+        let last = usize::min(usize::max(last, first + 1), self.text.len() - 1);
+        let mut transaction = Transaction::default();
+        // no space at the start of the joined line
+        let mut empty = self.text[first].0.is_empty();
+        for y in first + 1..=last {
+            let line = &self.text[y].0;
+            let indent = line.len() - line.trim_start().len();
+            let blank = indent == line.len();
+            let sep = if empty || blank { "" } else { " " };
+            empty &= blank;
+            transaction.replace(self.end_of_line(y - 1), Point { x: indent, y }, sep);
+        }
+        self.anchor = None;
+        let ranges = self.apply(transaction).expect("joins are in order");
+        if let Some(&(start, _)) = ranges.last() {
+            self.move_cursor(start);
+        }
+    }
+
+    // without asking for temp files, since whatever's waiting on them only sees what's saved
+    pub fn quit(&mut self) {
+        if self.dirty && self.temp_file.is_some() {
+            self.save_and_quit();
+        } else if self.dirty && !self.scratch {
+            self.confirm(
+                format!("Save changes to {} before quitting?", self.file),
+                State::save_and_quit,
+                State::force_quit,
+            );
+        } else {
+            self.force_quit();
+        }
+    }
+
+    // Saves without a word, unless there's something to say, and leaves files it can't save
+    // quietly, like ones changed on disk, which would need asking about. Returns whether it
+    // saved.
+    pub fn autosave(&mut self) -> bool {
+        if !self.dirty || self.new || self.scratch || self.temp_file.is_some() {
+            return false;
+        }
+        if self
+            .autosaved
+            .is_some_and(|autosaved| autosaved.elapsed() < AUTOSAVE_INTERVAL)
+        {
+            self.idle.schedule(Task::Autosave);
+            return false;
+        }
+        if self.changed_on_disk() {
+            return false;
+        }
+        let message = self.message.take();
+        self.save();
+        self.autosaved = Some(Instant::now());
+        if !self.dirty {
+            self.message = message;
+        }
+        true
+    }
+
+    pub fn save_and_quit(&mut self) {
+        self.save();
+        if !self.dirty {
+            self.force_quit();
+        }
+    }
+
+    // closes the buffer without saving, exiting with the last one
+    pub fn force_quit(&mut self) {
+        self.discard_recovery();
+        if !self.close_buffer() {
+            self.quit = Some(0);
+        }
+    }
+
+    // Exits without saving any buffer, nonzero, so that git (say) gives up on the commit or rebase
+    // it's waiting on rather than going ahead with whatever the file says.
+    pub fn quit_with_error(&mut self) {
+        if self.config.recovery {
+            for file in self.buffer_files() {
+                recovery::discard(&file);
+            }
+        }
+        self.quit = Some(1);
+    }
+
+    pub fn exit_status(&self) -> i32 {
+        self.quit.unwrap_or(0)
     }
 
-    fn select_word(&mut self, mut wordish: impl FnMut(char) -> bool) {
+    // with a count, that many words on from the one under the cursor
+    pub fn select_word(&mut self, mut wordish: impl FnMut(char) -> bool) {
         if let Some(left) = self.left_word(&mut wordish, self.cursor.into()) {
             if let Some(right) = self.right_word(&mut wordish, self.cursor.into()) {
                 self.move_cursor(left);
                 self.anchor = Some(self.cursor);
                 self.move_cursor(right);
+                for _ in 1..self.count() {
+                    self.move_right_word(&mut wordish);
+                }
             }
         }
     }
 
-    fn select_inside_brackets(&mut self) {
-        if let Some(open) = self.open_bracket(self.cursor.into()) {
-            if let Some(close) = self.close_bracket(self.cursor.into()) {
-                self.move_cursor(Point {
-                    x: open.x + 1,
-                    ..open
-                });
-                self.anchor = Some(self.cursor);
-                self.move_cursor(close);
+    // The brackets around the cursor, or with a count, that many levels out, stopping at the
+    // outermost.
+    fn enclosing_brackets(&self) -> Option<(Point, Point)> {
+        let mut open = self.open_bracket(self.cursor.into())?;
+        let mut close = self.close_bracket(self.cursor.into())?;
+        for _ in 1..self.count() {
+            let outer = self
+                .right_of(close)
+                .and_then(|right| Some((self.open_bracket(open)?, self.close_bracket(right)?)));
+            match outer {
+                Some(outer) => (open, close) = outer,
+                None => break,
             }
         }
+        Some((open, close))
+    }
+
+    pub fn select_inside_brackets(&mut self) {
+        if let Some((open, close)) = self.enclosing_brackets() {
+            self.move_cursor(Point {
+                x: open.x + 1,
+                ..open
+            });
+            self.anchor = Some(self.cursor);
+            self.move_cursor(close);
+        }
     }
 
-    fn select_outside_brackets(&mut self) {
+    pub fn select_outside_brackets(&mut self) {
         if let Some('[' | '{' | '(') = self.next_char(self.cursor.into()) {
             self.move_right(1);
         } else if let Some(']' | '}' | ')') = self.prev_char(self.cursor.into()) {
@@ -523,7 +1688,7 @@ impl State {
         self.grow_selection();
     }
 
-    fn select_inside_quotes(&mut self) {
+    pub fn select_inside_quotes(&mut self) {
         if let Some(open) = self.open_quote(self.cursor.into()) {
             if let Some(close) = self.close_quote(self.cursor.into()) {
                 self.move_cursor(Point {
@@ -536,7 +1701,7 @@ impl State {
         }
     }
 
-    fn select_outside_quotes(&mut self) {
+    pub fn select_outside_quotes(&mut self) {
         if let Some('"') = self.prev_char(self.cursor.into()) {
             self.move_left(1);
         }
@@ -544,16 +1709,36 @@ impl State {
         self.grow_selection();
     }
 
-    fn select_line(&mut self) {
+    pub fn select_all(&mut self) {
+        self.move_start_of_file();
+        self.anchor = Some(self.cursor);
+        self.move_end_of_file();
+    }
+
+    // with a count, that many lines from the one the cursor is on
+    pub fn select_line(&mut self) {
         self.move_start_of_line();
         self.anchor = Some(self.cursor);
-        self.move_end_of_line();
+        let y = usize::min(
+            self.cursor.y.saturating_add(self.count() - 1),
+            self.text.len() - 1,
+        );
+        self.move_cursor(self.end_of_line(y));
     }
 
-    fn select_para(&mut self) {
+    // with a count, that many paragraphs from the one the cursor is in
+    pub fn select_para(&mut self) {
         self.move_cursor(self.start_of_para(self.cursor.into()));
         self.anchor = Some(self.cursor);
-        self.move_cursor(self.end_of_para(self.cursor.into()));
+        let mut end = self.end_of_para(self.cursor.into());
+        for _ in 1..self.count() {
+            // the next one starts at the first line after with anything on it
+            match (end.y + 1..self.text.len()).find(|&y| !self.text[y].0.is_empty()) {
+                Some(y) => end = self.end_of_para(Point { x: 0, y }),
+                None => break,
+            }
+        }
+        self.move_cursor(end);
     }
 
     // assumes anchor is before cursor
@@ -571,3 +1756,285 @@ impl State {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    fn state(text: &str) -> State {
+        State::from_bytes(
+            "test.txt".to_string(),
+            text.as_bytes(),
+            Config::default(),
+            Keymap::default(),
+        )
+    }
+
+    fn keys(s: &mut State, keys: &str) {
+        for c in keys.chars() {
+            s.handle(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    // what saving bytes straight back after opening them writes
+    fn round_trip(name: &str, bytes: &[u8]) -> Vec<u8> {
+        saved(name, bytes, |_| ())
+    }
+
+    // what saving bytes after opening them and making an edit writes
+    fn saved(name: &str, bytes: &[u8], edit: impl FnOnce(&mut State)) -> Vec<u8> {
+        let file = std::env::temp_dir().join(format!("vee-{}-{}", name, std::process::id()));
+        fs::write(&file, bytes).unwrap();
+        let mut s = State::from_bytes(
+            file.to_str().unwrap().to_string(),
+            bytes,
+            Config::default(),
+            Keymap::default(),
+        );
+        edit(&mut s);
+        s.save();
+        let written = fs::read(&file).unwrap();
+        fs::remove_file(file).unwrap();
+        written
+    }
+
+    #[test]
+    fn keeps_line_endings() {
+        for (name, bytes) in [
+            ("lf", &b"a\nb\n"[..]),
+            ("crlf", b"a\r\nb\r\n"),
+            ("mixed", b"a\r\nb\nc\r\n"),
+            ("stray-cr", b"a\rb\nc\r\n"),
+            ("unfinished-cr", b"a\r\nb\r"),
+        ] {
+            assert_eq!(round_trip(name, bytes), bytes, "{}", name);
+        }
+        let s = state("a\r\nb\nc\r\n");
+        assert_eq!(s.text[0].0, "a\r");
+        assert!(!s.crlf);
+    }
+
+    #[test]
+    fn keeps_the_end_of_the_file() {
+        for (name, bytes) in [("noeol", &b"a\nb"[..]), ("empty", b""), ("blank", b"\n")] {
+            assert_eq!(round_trip(name, bytes), bytes, "{}", name);
+        }
+        let at = |x, y| Point { x, y };
+        let typed = saved("typed", b"", |s| {
+            s.splice(at(0, 0), at(0, 0), "a");
+        });
+        assert_eq!(typed, b"a\n");
+        let undone = saved("undone", b"", |s| {
+            s.splice(at(0, 0), at(0, 0), "a");
+            s.break_undo();
+            s.undo();
+        });
+        assert_eq!(undone, b"");
+        let retyped = saved("retyped", b"a\nb", |s| {
+            s.splice(at(0, 0), at(1, 1), "");
+            s.splice(at(0, 0), at(0, 0), "c");
+        });
+        assert_eq!(retyped, b"c");
+    }
+
+    #[test]
+    fn keeps_invalid_bytes_around_edits() {
+        let bytes = b"a\xffb\nc\xfe\n";
+        let at = |x, y| Point { x, y };
+        let insert = saved("insert", bytes, |s| {
+            s.splice(at(0, 0), at(0, 0), "X");
+        });
+        assert_eq!(insert, b"Xa\xffb\nc\xfe\n");
+        let split = saved("split", bytes, |s| {
+            s.splice(at(1, 0), at(1, 0), "\n");
+        });
+        assert_eq!(split, b"a\n\xffb\nc\xfe\n");
+        let join = saved("join", bytes, |s| {
+            let end = s.end_of_line(0);
+            s.splice(end, at(0, 1), " ");
+        });
+        assert_eq!(join, b"a\xffb c\xfe\n");
+    }
+
+    // with a search for x over three chunks of lines of x, and the first chunk annotated
+    fn annotating() -> State {
+        let mut s = state(&"x\n".repeat(idle::CHUNK * 3));
+        s.size = (80, 24);
+        s.search = Some(regex::Regex::new("x"));
+        s.reannotate();
+        s.run_idle();
+        s
+    }
+
+    #[test]
+    fn cancelled_reannotate_stops() {
+        let mut s = annotating();
+        let token = s.idle.token(Task::Reannotate);
+        assert_eq!(s.idle.running().map(|p| p.done), Some(idle::CHUNK));
+        s.cancel_task();
+        // the task still holds the same token, and sees it's been cancelled
+        assert!(token.is_cancelled());
+        assert!(s.idle.running().is_none());
+        s.run_idle();
+        assert!(s.idle.is_empty());
+        assert!(!s.text[idle::CHUNK - 1].1.matches.is_empty());
+        assert!(s.text[idle::CHUNK].1.matches.is_empty());
+    }
+
+    #[test]
+    fn rescheduled_reannotate_starts_again() {
+        let mut s = annotating();
+        let token = s.idle.token(Task::Reannotate);
+        s.reannotate();
+        assert!(token.is_cancelled());
+        s.run_idle();
+        assert_eq!(s.idle.running().map(|p| p.done), Some(idle::CHUNK));
+        assert!(!s.idle.token(Task::Reannotate).is_cancelled());
+    }
+
+    #[test]
+    fn count_applies_to_the_next_command() {
+        let mut s = state("a\nb\nc\nd\ne\n");
+        keys(&mut s, "3");
+        assert_eq!(s.count, Some(3));
+        keys(&mut s, "j");
+        assert_eq!(s.cursor.y, 3);
+        assert_eq!(s.count, None);
+        keys(&mut s, "j");
+        assert_eq!(s.cursor.y, 4);
+    }
+
+    #[test]
+    fn count_stops_at_the_maximum() {
+        let mut s = state("a\nb\nc\n");
+        keys(&mut s, &"9".repeat(40));
+        assert_eq!(s.count, Some(MAX_COUNT));
+        keys(&mut s, "J");
+        assert_eq!(s.cursor.y, 2);
+    }
+
+    #[test]
+    fn count_selects_lines_up_to_the_end() {
+        let mut s = state("a\nb\nc\n");
+        keys(&mut s, &format!("{}r", MAX_COUNT));
+        assert_eq!(s.cursor.y, 2);
+    }
+
+    #[test]
+    fn count_stops_repeating_once_stuck() {
+        let mut s = state("one two\n");
+        keys(&mut s, &format!("{}i", MAX_COUNT));
+        assert_eq!(s.cursor.x, 7);
+    }
+
+    #[test]
+    fn repeats_edits_at_every_selection() {
+        let mut s = state("ab\nab\nab\nab\n");
+        keys(&mut s, "fX");
+        s.handle(KeyEvent::from(KeyCode::Esc));
+        keys(&mut s, "jCC");
+        assert_eq!(s.selections.len(), 2);
+        keys(&mut s, "g");
+        let text: Vec<&str> = s.text.iter().map(|line| line.0.as_str()).collect();
+        assert_eq!(text, ["Xab", "aXb", "aXb", "aXb"]);
+    }
+
+    #[test]
+    fn paste_cycles_through_older_kills() {
+        let mut s = state("x\n");
+        s.paste_cycle();
+        assert_eq!(s.message.as_deref(), Some("Nothing pasted to cycle"));
+        for kill in ["one", "two", "three"] {
+            s.kill(kill.to_string());
+        }
+        s.paste(false);
+        assert_eq!(s.text[0].0, "threex");
+        s.paste_cycle();
+        assert_eq!(s.text[0].0, "twox");
+        assert_eq!(s.message.as_deref(), Some("Kill 2/3"));
+        s.paste_cycle();
+        s.paste_cycle();
+        // back round to the newest
+        assert_eq!(s.text[0].0, "threex");
+        let (start, end) = s.selection().unwrap();
+        assert_eq!(s.text_between(start.into(), end.into()), "three");
+        // only straight after a paste, while it's still selected
+        s.anchor = None;
+        s.paste_cycle();
+        assert_eq!(s.text[0].0, "threex");
+        assert!(s.violations().is_empty());
+    }
+
+    #[test]
+    fn kill_ring_drops_the_oldest() {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            b"\n",
+            Config {
+                kill_ring_size: 2,
+                ..Config::default()
+            },
+            Keymap::default(),
+        );
+        for kill in ["one", "two", "three"] {
+            s.kill(kill.to_string());
+        }
+        assert_eq!(s.kills.len(), 2);
+        s.paste(false);
+        assert_eq!(s.text[0].0, "three");
+        s.paste_cycle();
+        assert_eq!(s.text[0].0, "two");
+        assert_eq!(s.message.as_deref(), Some("Kill 2/2"));
+        // "one" fell off the end, so it wraps round to the newest
+        s.paste_cycle();
+        assert_eq!(s.text[0].0, "three");
+        assert_eq!(s.message.as_deref(), Some("Kill 1/2"));
+    }
+
+    #[test]
+    fn words_take_the_filetypes_chars() {
+        let word = |file: &str, text: &str, x: usize, config: Config| {
+            let mut s =
+                State::from_bytes(file.to_string(), text.as_bytes(), config, Keymap::default());
+            s.move_cursor(Point { x, y: 0 });
+            s.select_word(s.wordish());
+            let (start, end) = s.selection().unwrap();
+            s.text_between(start.into(), end.into())
+        };
+        assert_eq!(
+            word("a.css", "a foo-bar b", 3, Config::default()),
+            "foo-bar"
+        );
+        assert_eq!(word("a.txt", "a foo-bar b", 3, Config::default()), "foo");
+        // letters beyond ASCII are always word chars, and others can be made so
+        assert_eq!(
+            word("a.txt", "a café·crème b", 3, Config::default()),
+            "café"
+        );
+        let mut config = Config::default();
+        config.word_chars.insert("txt".to_string(), "·".to_string());
+        assert_eq!(
+            word("a.txt", "a café·crème b", 3, config.clone()),
+            "café·crème"
+        );
+        // in place of the language's, not as well as them
+        config.word_chars.insert("css".to_string(), "·".to_string());
+        assert_eq!(word("a.css", "a foo-bar b", 3, config), "foo");
+    }
+
+    #[test]
+    fn escape_leaves_a_register_unchosen() {
+        let mut s = state("one\n");
+        keys(&mut s, "\"");
+        assert!(s.mode == Mode::Register);
+        s.handle(KeyEvent::from(KeyCode::Esc));
+        assert!(s.mode == Mode::Normal);
+        assert_eq!((s.register, s.message.as_deref()), (None, None));
+        keys(&mut s, "\"a");
+        assert!(s.mode == Mode::Normal);
+        assert_eq!(s.register, Some('a'));
+    }
+}
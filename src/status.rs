@@ -0,0 +1,190 @@
+use crate::{
+    language::Language,
+    state::{Mode, Point, State},
+};
+
+// The parts of the status line that do something when clicked: the filetype, which asks for
+// another to treat the file as, and the cursor's position, which asks for a line to go to. Both
+// are typed on the bottom line, like the palette. A click in the sign column selects the line
+// beside it.
+
+// below this the filetype is left out, to leave room for the file's name
+const FILETYPE_WIDTH: u16 = 40;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Segment {
+    Filetype,
+    Position,
+}
+
+impl State {
+    // The parts at the right of the window's status line, in order, or none in zen mode, where
+    // the position isn't much use for prose.
+    pub fn status_segments(&self) -> Vec<(Segment, String)> {
+        let mut segments = Vec::new();
+        if self.zen {
+            return segments;
+        }
+        if self.size.0 >= FILETYPE_WIDTH {
+            segments.push((
+                Segment::Filetype,
+                self.filetype().unwrap_or("text").to_string(),
+            ));
+        }
+        segments.push((
+            Segment::Position,
+            format!("{:4}:{:<3}", self.cursor.y + 1, self.cursor.x + 1),
+        ));
+        segments
+    }
+
+    // the segment drawn at column of the status line, counting from the right, one space apart
+    fn segment_at(&self, column: u16) -> Option<Segment> {
+        let mut end = self.size.0 as usize;
+        for (segment, text) in self.status_segments().into_iter().rev() {
+            let start = end.saturating_sub(self.str_width(&text));
+            if (start..end).contains(&(column as usize)) {
+                return Some(segment);
+            }
+            end = start.saturating_sub(1);
+        }
+        None
+    }
+
+    pub fn click_status(&mut self, column: u16) {
+        match self.segment_at(column) {
+            Some(Segment::Filetype) => self.open_status_prompt(Mode::Filetype),
+            Some(Segment::Position) => self.open_status_prompt(Mode::Goto),
+            None => (),
+        }
+    }
+
+    // selects the line drawn at row, from a click in the sign column
+    pub fn click_gutter(&mut self, row: u16) {
+        let y = match self.line_at(row) {
+            Some(y) => y,
+            None => return,
+        };
+        self.anchor = None;
+        self.move_cursor(Point { x: 0, y });
+        self.select_line();
+    }
+
+    // mode is Goto, for a line number, or Filetype
+    pub fn open_status_prompt(&mut self, mode: Mode) {
+        self.status_prompt = Some(String::new());
+        self.mode = mode;
+    }
+
+    pub fn status_prompt_insert(&mut self, c: char) {
+        let fits = match self.mode {
+            Mode::Goto => c.is_ascii_digit(),
+            _ => !c.is_whitespace(),
+        };
+        if let Some(input) = &mut self.status_prompt {
+            if fits {
+                input.push(c);
+            }
+        }
+    }
+
+    pub fn status_prompt_backspace(&mut self) {
+        if let Some(input) = &mut self.status_prompt {
+            input.pop();
+        }
+    }
+
+    pub fn cancel_status_prompt(&mut self) {
+        self.status_prompt = None;
+        self.mode = Mode::Normal;
+    }
+
+    pub fn run_status_prompt(&mut self) {
+        let input = match self.status_prompt.take() {
+            Some(input) => input,
+            None => return,
+        };
+        let mode = std::mem::replace(&mut self.mode, Mode::Normal);
+        if mode == Mode::Goto {
+            self.goto_line(&input);
+        } else {
+            self.set_filetype(input);
+        }
+    }
+
+    // goes to the start of the line typed, or the last line if there aren't that many
+    fn goto_line(&mut self, input: &str) {
+        let y = match input.parse::<usize>() {
+            Ok(n) if n > 0 => usize::min(n - 1, self.text.len() - 1),
+            _ => {
+                self.message = Some(format!("Not a line number: {}", input));
+                return;
+            }
+        };
+        self.anchor = None;
+        self.move_cursor(Point { x: 0, y });
+    }
+
+    // Treats the file as filetype, whatever its extension says, until it's opened again, and
+    // highlights its comments to match. Nothing typed goes back to the extension.
+    pub fn set_filetype(&mut self, filetype: String) {
+        self.filetype_override = (!filetype.is_empty()).then_some(filetype);
+        self.language = Language::from_filetype(self.filetype());
+        self.message = Some(format!("Filetype {}", self.filetype().unwrap_or("text")));
+        if self.large {
+            return;
+        }
+        let comment = self.language.comment();
+        for line in &mut self.text {
+            line.highlight_comments(comment);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap},
+    };
+
+    #[test]
+    fn clicks_the_status_line() {
+        let mut s = State::from_bytes(
+            "test.rs".to_string(),
+            b"// one\ntwo\nthree\n",
+            Config::default(),
+            Keymap::default(),
+        );
+        s.resize((80, 24));
+        assert_eq!(s.segment_at(79), Some(Segment::Position));
+        s.click_status(79);
+        assert!(s.mode == Mode::Goto);
+        for c in "3x".chars() {
+            s.status_prompt_insert(c);
+        }
+        s.run_status_prompt();
+        assert_eq!(Point::from(s.cursor), Point { x: 0, y: 2 });
+        assert!(s.violations().is_empty());
+
+        // "rust", then a space, then the position
+        assert_eq!(s.segment_at(70), Some(Segment::Filetype));
+        assert_eq!(s.segment_at(71), None);
+        s.click_status(70);
+        assert!(s.mode == Mode::Filetype);
+        assert!(!s.text[0].1.comment_indices.is_empty());
+        for c in "txt".chars() {
+            s.status_prompt_insert(c);
+        }
+        s.run_status_prompt();
+        assert_eq!(s.language.name, "text");
+        assert!(s.text[0].1.comment_indices.is_empty());
+        s.click_gutter(1);
+        let (start, end) = s.selection().unwrap();
+        assert_eq!(
+            (Point::from(start), Point::from(end)),
+            (Point { x: 0, y: 1 }, Point { x: 3, y: 1 })
+        );
+        assert!(s.violations().is_empty());
+    }
+}
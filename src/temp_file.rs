@@ -0,0 +1,81 @@
+use {crate::language::Language, std::path::Path};
+
+// Files other programs hand us to edit through $VISUAL or $EDITOR, recognised by their names.
+// The program reads the file back once we exit, so quitting saves without asking, and
+// quit-with-error is the way to back out.
+pub struct TempFile {
+    // what the file is for, shown in the status line
+    pub name: &'static str,
+    // file names, or the start of them for names with a random part
+    names: &'static [&'static str],
+    prefixes: &'static [&'static str],
+    // if the extension doesn't say
+    language: Option<&'static str>,
+    pub ruler: Option<usize>,
+}
+
+pub static TEMP_FILES: &[TempFile] = &[
+    TempFile {
+        name: "git commit",
+        names: &[
+            "COMMIT_EDITMSG",
+            "MERGE_MSG",
+            "SQUASH_MSG",
+            "TAG_EDITMSG",
+            "EDIT_DESCRIPTION",
+        ],
+        prefixes: &[],
+        language: Some("git"),
+        // where git log's indented summaries start to wrap
+        ruler: Some(72),
+    },
+    TempFile {
+        name: "git rebase",
+        names: &["git-rebase-todo"],
+        prefixes: &[],
+        language: Some("git"),
+        ruler: None,
+    },
+    TempFile {
+        name: "git add",
+        names: &["addp-hunk-edit.diff"],
+        prefixes: &[],
+        language: Some("git"),
+        ruler: None,
+    },
+    TempFile {
+        name: "crontab",
+        names: &[],
+        prefixes: &["crontab."],
+        language: Some("config"),
+        ruler: None,
+    },
+    TempFile {
+        name: "psql",
+        names: &[],
+        prefixes: &["psql.edit."],
+        // named .sql already
+        language: None,
+        ruler: None,
+    },
+    TempFile {
+        name: "fc",
+        names: &[],
+        prefixes: &["bash-fc"],
+        language: Some("shell"),
+        ruler: None,
+    },
+];
+
+impl TempFile {
+    pub fn detect(file: &str) -> Option<&'static TempFile> {
+        let name = Path::new(file).file_name()?.to_str()?;
+        TEMP_FILES.iter().find(|temp| {
+            temp.names.contains(&name) || temp.prefixes.iter().any(|p| name.starts_with(p))
+        })
+    }
+
+    pub fn language(&self) -> Option<&'static Language> {
+        self.language.map(Language::from_name)
+    }
+}
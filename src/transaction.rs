@@ -0,0 +1,54 @@
+use {
+    crate::state::{self, Point, State},
+    anyhow::{bail, Result},
+};
+
+// Several replacements made as one edit. Every position is in terms of the text before any of
+// them is applied, so features can work out all their edits up front without adjusting each
+// for the ones before it. The cursor, selection, and annotations follow the text as they do for
+// a single splice.
+#[derive(Default)]
+pub struct Transaction {
+    replacements: Vec<(Point, Point, String)>,
+}
+
+impl Transaction {
+    pub fn replace(&mut self, start: Point, end: Point, text: impl Into<String>) {
+        self.replacements.push((start, end, text.into()));
+    }
+}
+
+impl State {
+    // Applies every replacement or, if any are out of range or overlap, none of them. Returns
+    // where each replacement's text ended up, in the order they were added.
+    pub fn apply(&mut self, transaction: Transaction) -> Result<Vec<(Point, Point)>> {
+        let mut order: Vec<usize> = (0..transaction.replacements.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&transaction.replacements[a], &transaction.replacements[b]);
+            (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap()
+        });
+        let mut prev: Option<Point> = None;
+        for &i in &order {
+            let (start, end, _) = &transaction.replacements[i];
+            if start > end || self.clamp(*start) != *start || self.clamp(*end) != *end {
+                bail!("Replacement out of range");
+            }
+            if prev.is_some_and(|prev| *start < prev) {
+                bail!("Replacements overlap");
+            }
+            prev = Some(*end);
+        }
+        let mut ranges = vec![None; order.len()];
+        // last first, so that the positions of the ones still to do stay valid
+        for &i in order.iter().rev() {
+            let (start, end, text) = &transaction.replacements[i];
+            let new_end = self.splice(*start, *end, text);
+            for (a, b) in ranges.iter_mut().flatten() {
+                *a = state::shift(*a, *start, *end, new_end);
+                *b = state::shift(*b, *start, *end, new_end);
+            }
+            ranges[i] = Some((*start, new_end));
+        }
+        Ok(ranges.into_iter().flatten().collect())
+    }
+}
@@ -0,0 +1,113 @@
+use {
+    crate::{
+        idle::Task,
+        state::{Mode, State},
+    },
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// A repository's own config can have commands in it, like git's core.fsmonitor, which anything
+// that runs its tools for us would run as well, so opening a file in a repository that's just
+// been cloned could run commands from it. The first time a file in a repository is opened, a
+// dialog asks whether to trust it, and nothing runs there until it is: no git status, and grep
+// goes through the files itself. Trusted repositories are kept in the state directory, one per
+// line, see State::trusted.
+
+// the top of the repository the file is in, if it's in one
+fn repository(file: &str) -> Option<PathBuf> {
+    let dir = Path::new(file)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let dir = fs::canonicalize(dir).ok()?;
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+impl State {
+    // the repository file is in, if it hasn't been trusted
+    pub fn untrusted(&self, file: &str) -> Option<String> {
+        let trusted = self.trusted.as_ref()?;
+        let root = repository(file)?.to_string_lossy().into_owned();
+        (!trusted.entries().contains(&root)).then_some(root)
+    }
+
+    // Whether commands can run for file, asking to trust its repository if that hasn't been
+    // asked yet. Nothing's asked in the middle of something else, only the next time.
+    pub fn trusts(&mut self, file: &str) -> bool {
+        let root = match self.untrusted(file) {
+            Some(root) => root,
+            None => return true,
+        };
+        if !self.distrusted.contains(&root) && self.mode == Mode::Normal {
+            let question = format!("Trust {}? Its config can run commands", root);
+            self.trusting = Some(root);
+            self.confirm(question, State::trust, State::distrust);
+        }
+        false
+    }
+
+    fn trust(&mut self) {
+        let (root, trusted) = match (self.trusting.take(), &mut self.trusted) {
+            (Some(root), Some(trusted)) => (root, trusted),
+            _ => return,
+        };
+        if let Err(err) = trusted.remember(&root) {
+            self.message = Some(format!("Couldn't save trusted repositories: {}", err));
+        }
+        self.git_checked = None;
+        self.idle.schedule(Task::GitStatus);
+    }
+
+    // only until we exit, so that it's asked again next time
+    fn distrust(&mut self) {
+        if let Some(root) = self.trusting.take() {
+            self.distrusted.push(root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap, recent::Recent},
+        std::env,
+    };
+
+    #[test]
+    fn asks_before_trusting() {
+        let dir = env::temp_dir().join(format!("vee-trust-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let file = dir.join("src").join("main.rs");
+        let file = file.to_string_lossy().into_owned();
+        let root = fs::canonicalize(&dir)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let mut s = State::from_bytes(file.clone(), b"", Config::default(), Keymap::default());
+        assert!(s.trusts(&file));
+        s.trusted = Some(Recent::default());
+        assert_eq!(s.untrusted(&file), Some(root.clone()));
+
+        assert!(!s.trusts(&file));
+        assert!(s.mode == Mode::Dialog);
+        s.answer(Some(false));
+        assert!(!s.trusts(&file));
+        assert!(s.mode == Mode::Normal);
+
+        s.distrusted.clear();
+        assert!(!s.trusts(&file));
+        s.answer(Some(true));
+        assert!(s.idle.is_pending(Task::GitStatus));
+        assert_eq!(s.untrusted(&file), None);
+        assert!(s.trusts(&file));
+        assert!(s.violations().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
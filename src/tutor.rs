@@ -0,0 +1,149 @@
+use {
+    crate::{
+        config::Config,
+        keymap::Keymap,
+        state::{Mode, State},
+    },
+    std::ops::Range,
+};
+
+// The tutorial is shown a section at a time. Each section ends in an exercise, and the next
+// section is added below once the exercise's check passes.
+struct Section {
+    text: &'static str,
+    // given the lines the section occupies
+    check: Option<fn(&State, Range<usize>) -> bool>,
+}
+
+const SECTIONS: &[Section] = &[
+    Section {
+        text: "\
+Welcome to vee!
+
+vee's keys are laid out by where they are on the keyboard rather than what letter they are.
+The home row moves: h j k l go left, down, up, and right, and H J K L go five at a time.
+
+Exercise: move the cursor onto the @ below.
+
+                @",
+        check: Some(cursor_on_marker),
+    },
+    Section {
+        text: "\
+Well done. The row above moves along lines:
+
+    y  start of line    u  left a word    i  right a word    o  end of line
+
+U and I move by bigger words, and Y and O move by paragraphs.
+
+Exercise: move to the end of this line with o.",
+        check: Some(|s, lines| {
+            let line = &s.text[s.cursor.y].0;
+            lines.contains(&s.cursor.y) && line.starts_with("Exercise") && s.cursor.x == line.len()
+        }),
+    },
+    Section {
+        text: "\
+The top row selects:
+
+    q  inside quotes    w  a word    e  inside brackets    r  a line
+
+Q W E R select a little more, and s sets an anchor so that moving afterwards extends the
+selection. Esc clears the selection.
+
+Exercise: put the cursor inside these brackets and select what's inside them with e.
+
+    (select me)",
+        check: Some(|s, _| {
+            s.selection()
+                .map(|(start, end)| s.text_between(start.into(), end.into()))
+                .as_deref()
+                == Some("select me")
+        }),
+    },
+    Section {
+        text: "\
+Operators act on the selection, or on the character under the cursor if nothing is selected:
+
+    c  copy    d  delete    a  delete and start typing    v  paste
+
+Exercise: delete the shouting from the line below, by selecting it with w and deleting it with d.
+
+    keep LOUD this",
+        check: Some(|s, lines| {
+            s.text[lines]
+                .iter()
+                .any(|line| line.0.starts_with("    keep") && !line.0.contains("LOUD"))
+        }),
+    },
+    Section {
+        text: "\
+f starts typing at the cursor and Esc stops. g repeats the last edit somewhere else.
+
+Exercise: make the line below say hello world, then press Esc.
+
+    hello",
+        check: Some(|s, lines| {
+            s.mode == Mode::Normal
+                && s.text[lines]
+                    .iter()
+                    .any(|line| line.0.trim() == "hello world")
+        }),
+    },
+    Section {
+        text: "\
+That's the tour! A few more keys to know about:
+
+    /  search    m  next match    ,  previous match    :  run a command by name
+
+Space starts a file command: space w saves and space q quits. Try running the keymap command
+to see every binding.",
+        check: None,
+    },
+];
+
+fn cursor_on_marker(s: &State, lines: Range<usize>) -> bool {
+    lines.contains(&s.cursor.y) && s.text[s.cursor.y].0[s.cursor.x..].starts_with('@')
+}
+
+pub struct Tutor {
+    section: usize,
+    // the first line of the current section
+    start: usize,
+}
+
+impl State {
+    pub fn tutor(config: Config, keymap: Keymap) -> Self {
+        let mut s = State::from_bytes(
+            "tutor.txt".to_string(),
+            SECTIONS[0].text.as_bytes(),
+            config,
+            keymap,
+        );
+        s.tutor = Some(Tutor {
+            section: 0,
+            start: 0,
+        });
+        s
+    }
+
+    pub fn check_tutor(&mut self) {
+        let tutor = match &self.tutor {
+            Some(tutor) => tutor,
+            None => return,
+        };
+        let passed = match SECTIONS[tutor.section].check {
+            // the lines before the section might have been deleted
+            Some(check) => check(self, tutor.start.min(self.text.len())..self.text.len()),
+            None => false,
+        };
+        if passed {
+            let section = tutor.section + 1;
+            let start = self.text.len() + 1;
+            let end = self.end_of_file();
+            self.splice(end, end, &format!("\n\n{}", SECTIONS[section].text));
+            self.tutor = Some(Tutor { section, start });
+            self.message = Some("Well done! Read on below.".to_string());
+        }
+    }
+}
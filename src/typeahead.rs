@@ -0,0 +1,68 @@
+use {
+    anyhow::Result,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    std::{collections::VecDeque, iter, time::Duration},
+};
+
+// A key that takes longer than this to handle is slow enough that keys typed in the meantime
+// may have been typed at a screen that was out of date.
+pub const SLOW: Duration = Duration::from_millis(100);
+
+// Keys typed while we were busy, in the order they were typed. Events are handled from here
+// before reading any more, and the screen is only drawn once it's empty, so that a slow draw
+// doesn't hold up catching up with the typing.
+#[derive(Default)]
+pub struct Typeahead(VecDeque<Event>);
+
+impl Typeahead {
+    pub fn pop(&mut self) -> Option<Event> {
+        self.0.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // reads every event already waiting, without blocking
+    pub fn read_pending(&mut self) -> Result<()> {
+        while event::poll(Duration::ZERO)? {
+            self.0.push_back(event::read()?);
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    // The text typed by first and everything waiting after it, if that's all plain characters.
+    // More than one key arriving at once like this is a paste, rather than typing.
+    pub fn typed(&self, first: &Event) -> Option<String> {
+        if self.0.is_empty() {
+            return None;
+        }
+        iter::once(first)
+            .chain(&self.0)
+            .map(|event| match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    modifiers,
+                }) if (*modifiers - KeyModifiers::SHIFT).is_empty() => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // After a slow key, esc means "stop that" rather than whatever it usually does. Drops
+    // everything up to and including the last esc, returning whether there was one.
+    pub fn take_cancel(&mut self) -> bool {
+        let esc = Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        match self.0.iter().rposition(|event| *event == esc) {
+            Some(i) => {
+                self.0.drain(..=i);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,84 +1,599 @@
 use {
-    crate::state::{Point, State},
-    anyhow::{bail, Result},
+    crate::{
+        ansi,
+        dialog::Dialog,
+        highlight::{self, Layer, Style},
+        hover::Tooltip,
+        idle,
+        minimap::{self, MinimapRow},
+        pager::Pager,
+        path_prompt::PathPrompt,
+        replace::Replace,
+        search::SearchPrompt,
+        selections::Filter,
+        session::SessionPrompt,
+        state::{Mode, Point, State},
+        window,
+    },
+    anyhow::Result,
     crossterm::{
         cursor, queue,
-        style::{self, Color},
+        style::{self, Attribute, Color},
         terminal::{self, ClearType},
     },
-    std::{io, iter},
-    unicode_width::UnicodeWidthChar,
+    std::{io, ops::Range},
 };
 
-fn draw_text<W>(mut out: W, s: &State, size: (u16, u16)) -> Result<usize>
+// Switches to style from whatever was drawn before, which it replaces entirely.
+fn set_style<W>(mut out: W, style: Style) -> Result<()>
+where
+    W: io::Write,
+{
+    queue!(out, style::ResetColor)?;
+    if let Some(fg) = style.fg {
+        queue!(out, style::SetForegroundColor(fg))?;
+    }
+    if let Some(bg) = style.bg {
+        queue!(out, style::SetBackgroundColor(bg))?;
+    }
+    if !style.attributes.is_empty() {
+        queue!(out, style::SetAttributes(style.attributes))?;
+    }
+    Ok(())
+}
+
+// for text that isn't part of the file, like hints and annotations
+fn hint(s: &State) -> Style {
+    Style::fg(Color::DarkGrey).or_mono(s.config.color, Some(Attribute::Dim))
+}
+
+// for the status line and anything else drawn over the text, like dialogs
+fn bar(s: &State) -> Style {
+    Style::bg(Color::Grey).or_mono(s.config.color, Some(Attribute::Reverse))
+}
+
+// Below this the status line doesn't fit, so we draw a placeholder until the terminal grows.
+const MIN_SIZE: (u16, u16) = (20, 3);
+
+fn draw_too_small<W>(mut out: W, size: (u16, u16)) -> Result<()>
 where
     W: io::Write,
 {
-    let h = size.1 as usize - 2;
-    let offset = if s.cursor.y < h / 2 || s.text.len() <= h {
-        0
-    } else if s.cursor.y - h / 2 + h <= s.text.len() {
-        s.cursor.y - h / 2
+    let msg: String = "terminal too small".chars().take(size.0 as usize).collect();
+    queue!(
+        out,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        style::Print(msg),
+    )?;
+    Ok(())
+}
+
+// Draws the chars of line y in range, and the space after the end of the line if the range
+// reaches it, starting at column w and stopping before column limit. Returns the column
+// reached.
+fn draw_span<W>(
+    mut out: W,
+    s: &State,
+    y: usize,
+    range: Range<usize>,
+    mut w: u16,
+    limit: u16,
+) -> Result<u16>
+where
+    W: io::Write,
+{
+    let line = &s.text[y];
+    let selection = match s.block() {
+        Some(block) if block.lines.contains(&y) => s.block_span(&block, y),
+        Some(_) => None,
+        None => s.selection().map(|(start, end)| (start.into(), end.into())),
+    };
+    let others: Vec<(Point, Point)> = s
+        .other_selections()
+        .into_iter()
+        .filter(|(start, end)| (start.y..=end.y).contains(&y))
+        .collect();
+    let end = (range.end == line.0.len()).then_some((range.end, ' ', 1));
+    // the colour left by the escape sequences before x, from the start of the line
+    let escapes = if s.ansi {
+        ansi::escapes(&line.0)
     } else {
-        s.text.len() - h
+        Vec::new()
     };
-    let selection = s.selection();
-    for (y, line) in s.text[offset..usize::min(offset + h, s.text.len())]
-        .iter()
-        .enumerate()
+    let mut escapes = escapes.into_iter().peekable();
+    let mut fg = None;
+    let mut current = Style::default();
+    for (x, c, cw) in s
+        .drawn_chars(y)
+        .filter(|(x, _, _)| range.contains(x))
+        .chain(end)
     {
-        queue!(out, cursor::MoveTo(0, y as u16))?;
-        let mut w = 0;
-        for (x, c) in line.0.char_indices().chain(iter::once((line.0.len(), ' '))) {
-            let p = Point { x, y: y + offset };
-            w += c.width().unwrap_or(0) as u16;
-            if w >= size.0 {
-                // TODO wrap or scroll
-                break;
-            }
-            if line.1.comment_indices.contains(&x) {
-                queue!(out, style::SetForegroundColor(Color::DarkRed))?;
+        while let Some(escape) = escapes.next_if(|escape| escape.range.start < x) {
+            fg = escape.fg.unwrap_or(fg);
+        }
+        let p = Point { x, y };
+        w += cw as u16;
+        if w >= limit {
+            break;
+        }
+        let mut layers = Vec::new();
+        if line.1.comment_indices.contains(&x) {
+            layers.push(Layer::Syntax);
+        }
+        if line.1.invalid.iter().any(|&(i, _)| i == x) {
+            layers.push(Layer::Invalid);
+        }
+        if line.1.match_indices.contains(&x) {
+            layers.push(Layer::Search);
+        }
+        if selection.is_some_and(|(start, end)| p >= start && p < end)
+            || others.iter().any(|&(start, end)| p >= start && p < end)
+        {
+            layers.push(Layer::Selection);
+        }
+        let mut style = highlight::resolve(layers, s.config.color);
+        if s.config.color {
+            style.fg = style.fg.or(fg);
+        }
+        if style != current {
+            set_style(&mut out, style)?;
+            current = style;
+        }
+        queue!(out, style::Print(c))?;
+    }
+    queue!(out, style::ResetColor)?;
+    Ok(w)
+}
+
+// Draws the gap between the column w reached and the edge of a window, where a line ends short of
+// it. Windows side by side share rows, so clearing the rest of the row would clear the next one.
+fn draw_gap<W>(mut out: W, w: u16, size: (u16, u16)) -> Result<()>
+where
+    W: io::Write,
+{
+    if w < size.0 {
+        queue!(out, style::Print(" ".repeat((size.0 - w) as usize)))?;
+    }
+    Ok(())
+}
+
+fn draw_text<W>(mut out: W, s: &State, origin: (u16, u16), size: (u16, u16)) -> Result<usize>
+where
+    W: io::Write,
+{
+    let offset = s.offset();
+    for (y, line) in s.text[s.visible()].iter().enumerate() {
+        queue!(out, cursor::MoveTo(origin.0, origin.1 + y as u16))?;
+        if s.config.sign_column {
+            match s.signs(y + offset).into_iter().max() {
+                Some(sign) => {
+                    set_style(
+                        &mut out,
+                        Style::fg(sign.color()).or_mono(s.config.color, None),
+                    )?;
+                    queue!(
+                        out,
+                        style::Print(sign.symbol()),
+                        style::ResetColor,
+                        style::Print(' '),
+                    )?;
+                }
+                None => queue!(out, style::Print("  "))?,
             }
-            if line.1.match_indices.contains(&x) {
-                queue!(out, style::SetBackgroundColor(Color::Red))?;
+        }
+        // TODO wrap or scroll
+        let limit = size.0 - s.minimap_width();
+        let w = draw_span(
+            &mut out,
+            s,
+            y + offset,
+            0..line.0.len(),
+            s.gutter_width(),
+            limit,
+        )?;
+        let w = draw_annotation_text(&mut out, s, y + offset, w, limit)?;
+        draw_gap(&mut out, w, size)?;
+        if let Some(ruler) = s.ruler.or(s.config.ruler) {
+            let col = s.gutter_width().saturating_add(ruler as u16);
+            if col >= w && col < limit {
+                queue!(out, cursor::MoveTo(origin.0 + col, origin.1 + y as u16))?;
+                set_style(
+                    &mut out,
+                    Style::fg(Color::DarkGrey).or_mono(s.config.color, None),
+                )?;
+                queue!(out, style::Print('│'), style::ResetColor)?;
             }
-            if selection
-                .map(|(start, end)| p >= start.into() && p < end.into())
-                .unwrap_or(false)
-            {
-                queue!(out, style::SetBackgroundColor(Color::Grey))?;
+        }
+    }
+    // past the end of the text
+    for y in s.visible().len()..s.height() {
+        queue!(out, cursor::MoveTo(origin.0, origin.1 + y as u16))?;
+        draw_gap(&mut out, 0, size)?;
+    }
+    Ok(offset)
+}
+
+// Draws what tools have to say about line y after the end of it, from column w, if there's room.
+// Returns the column reached.
+fn draw_annotation_text<W>(mut out: W, s: &State, y: usize, mut w: u16, limit: u16) -> Result<u16>
+where
+    W: io::Write,
+{
+    let text = s.annotation_text(y);
+    // announced along with the line instead, see announce.rs
+    if text.is_empty() || s.config.accessible {
+        return Ok(w);
+    }
+    set_style(&mut out, hint(s))?;
+    // a space on from the one drawn for the end of the line
+    for c in format!(" {}", text).chars() {
+        let cw = s.char_width(c) as u16;
+        if w + cw >= limit {
+            break;
+        }
+        queue!(out, style::Print(c))?;
+        w += cw;
+    }
+    queue!(out, style::ResetColor)?;
+    Ok(w)
+}
+
+// draws the minimap over the right of the text, highlighting the lines on screen and matches
+fn draw_minimap<W>(mut out: W, s: &State, origin: (u16, u16), size: (u16, u16)) -> Result<()>
+where
+    W: io::Write,
+{
+    let rows = s.minimap();
+    for row in 0..s.height() {
+        queue!(
+            out,
+            cursor::MoveTo(
+                origin.0 + size.0 - minimap::WIDTH - 1,
+                origin.1 + row as u16
+            )
+        )?;
+        match rows.get(row) {
+            Some(MinimapRow {
+                cells,
+                viewport,
+                matches,
+            }) => {
+                queue!(out, style::Print(' '))?;
+                let mut style = Style::default();
+                if *viewport {
+                    style = style.merge(
+                        Style::bg(Color::DarkGrey)
+                            .or_mono(s.config.color, Some(Attribute::Reverse)),
+                    );
+                }
+                if *matches {
+                    style = style.merge(
+                        Style::fg(Color::Yellow).or_mono(s.config.color, Some(Attribute::Bold)),
+                    );
+                }
+                set_style(&mut out, style)?;
+                queue!(out, style::Print(cells), style::ResetColor)?;
             }
-            queue!(out, style::Print(c), style::ResetColor)?;
+            None => queue!(out, style::Print(" ".repeat(minimap::WIDTH as usize + 1)))?,
         }
-        if w < size.0 {
-            queue!(out, terminal::Clear(ClearType::UntilNewLine))?;
+    }
+    Ok(())
+}
+
+// draws the text soft wrapped in a centred column, with nothing else around it
+fn draw_zen<W>(mut out: W, s: &State, origin: (u16, u16), size: (u16, u16)) -> Result<()>
+where
+    W: io::Write,
+{
+    let rows = s.zen_rows();
+    for row in 0..s.height() {
+        queue!(out, cursor::MoveTo(origin.0, origin.1 + row as u16))?;
+        draw_gap(&mut out, 0, size)?;
+        if let Some((y, range)) = rows.get(row) {
+            queue!(
+                out,
+                cursor::MoveTo(origin.0 + s.zen_margin(), origin.1 + row as u16)
+            )?;
+            draw_span(&mut out, s, *y, range.clone(), s.zen_margin(), size.0)?;
         }
     }
-    Ok(offset)
+    Ok(())
+}
+
+// The name on the left of a field of the given width, and on the right any count typed so far,
+// or otherwise where we are among the search matches.
+fn status_field(s: &State, name: &str, width: usize) -> String {
+    let right = match (s.count, s.match_position()) {
+        (Some(count), _) => count.to_string(),
+        (None, Some((Some(i), total))) => format!("match {}/{}", i, total),
+        (None, Some((None, 1))) => "1 match".to_string(),
+        (None, Some((None, total))) => format!("{} matches", total),
+        (None, None) => String::new(),
+    };
+    let pad = width.saturating_sub(name.chars().count() + right.chars().count());
+    format!("{}{}{}", name, " ".repeat(pad), right)
 }
 
-fn draw_status<W>(mut out: W, s: &State, size: (u16, u16)) -> Result<()>
+// for the status lines of windows without the focus
+fn dim_bar(s: &State) -> Style {
+    Style::bg(Color::DarkGrey).or_mono(s.config.color, Some(Attribute::Underlined))
+}
+
+// Draws the window's status line, which only says what mode we're in for the focused window,
+// and is dimmed for the others.
+fn draw_status<W>(
+    mut out: W,
+    s: &State,
+    origin: (u16, u16),
+    size: (u16, u16),
+    focused: bool,
+) -> Result<()>
 where
     W: io::Write,
 {
+    let mode = if focused {
+        s.mode.to_string()
+    } else {
+        String::new()
+    };
+    let name = format!(
+        "{}{}{}{}{}{}{}",
+        s.file,
+        if s.buffers.len() == 1 {
+            String::new()
+        } else {
+            format!(" ({}/{})", s.active + 1, s.buffers.len())
+        },
+        match &s.git {
+            Some(git) if git.code.is_empty() => format!(" ({})", git.branch),
+            Some(git) => format!(" ({} {})", git.branch, git.code),
+            None => String::new(),
+        },
+        match s.temp_file {
+            Some(temp_file) => format!(" [{}]", temp_file.name),
+            None if s.new => " [new]".to_string(),
+            None => String::new(),
+        },
+        if s.ends_in_newline() || s.is_empty() {
+            ""
+        } else {
+            " [noeol]"
+        },
+        if s.dirty { " [+]" } else { "" },
+        if s.macros.is_recording() {
+            " (recording)"
+        } else {
+            ""
+        },
+    );
+    let right: Vec<String> = s
+        .status_segments()
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect();
+    let status = if right.is_empty() {
+        format!("{:6} {}", mode, status_field(s, &name, size.0 as usize - 7))
+    } else {
+        let right = right.join(" ");
+        let width = (size.0 as usize - 8).saturating_sub(s.str_width(&right));
+        format!("{:6} {} {}", mode, status_field(s, &name, width), right)
+    };
+    queue!(out, cursor::MoveTo(origin.0, origin.1 + size.1 - 2))?;
+    set_style(&mut out, if focused { bar(s) } else { dim_bar(s) })?;
+    queue!(out, style::Print(status), style::ResetColor)?;
+    Ok(())
+}
+
+// draws the palette input, returning the column the cursor should be in
+fn draw_prompt<W>(mut out: W, s: &State, size: (u16, u16)) -> Result<u16>
+where
+    W: io::Write,
+{
+    let palette = &s.palette;
+    let (prefix, col) = match &palette.search {
+        Some((query, _)) => {
+            let prefix = format!("(search `{}'): ", query);
+            let col = "(search `".len() + s.str_width(query);
+            (prefix, col)
+        }
+        None => (
+            ":".to_string(),
+            1 + s.str_width(&palette.input[..palette.cursor]),
+        ),
+    };
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print(prefix),
+        style::Print(&palette.input),
+        terminal::Clear(ClearType::UntilNewLine),
+    )?;
+    Ok(col as u16)
+}
+
+// Draws the session name being typed. Returns the column the cursor should be in.
+fn draw_session_prompt<W>(
+    mut out: W,
+    s: &State,
+    prompt: &SessionPrompt,
+    size: (u16, u16),
+) -> Result<u16>
+where
+    W: io::Write,
+{
+    let prefix = if prompt.save {
+        "save session as: "
+    } else {
+        "load session: "
+    };
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print(prefix),
+        style::Print(&prompt.input),
+        terminal::Clear(ClearType::UntilNewLine),
+    )?;
+    Ok((s.str_width(prefix) + s.str_width(&prompt.input)) as u16)
+}
+
+// Draws the line number or filetype being typed. Returns the column the cursor should be in.
+fn draw_status_prompt<W>(mut out: W, s: &State, input: &str, size: (u16, u16)) -> Result<u16>
+where
+    W: io::Write,
+{
+    let prefix = if s.mode == Mode::Goto {
+        "go to line: "
+    } else {
+        "filetype: "
+    };
     queue!(
         out,
-        cursor::MoveTo(0, size.1 - 2),
-        style::SetBackgroundColor(Color::Grey),
-        style::Print(format!(
-            "{:6} {:<4$} {:4}:{:<3}",
-            &s.mode,
-            &s.file,
-            s.cursor.y + 1,
-            s.cursor.x + 1,
-            size.0 as usize - 16,
-        )),
-        style::ResetColor,
         cursor::MoveTo(0, size.1 - 1),
+        style::Print(prefix),
+        style::Print(input),
+        terminal::Clear(ClearType::UntilNewLine),
+    )?;
+    Ok((s.str_width(prefix) + s.str_width(input)) as u16)
+}
+
+fn draw_message<W>(mut out: W, msg: &str, size: (u16, u16)) -> Result<()>
+where
+    W: io::Write,
+{
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print(msg),
+        terminal::Clear(ClearType::UntilNewLine),
     )?;
     Ok(())
 }
 
+// Syntax errors come with the pattern and a line pointing into it, which we show already, so
+// only the last line with the error itself is kept.
+fn regex_error(err: &regex::Error) -> String {
+    match err {
+        regex::Error::Syntax(msg) => {
+            let last = msg.lines().last().unwrap_or_default();
+            last.trim_start_matches("error: ").to_string()
+        }
+        err => err.to_string(),
+    }
+}
+
+// draws the search pattern being typed, returning the column the cursor should be in
+fn draw_search_prompt<W>(
+    mut out: W,
+    s: &State,
+    prompt: &SearchPrompt,
+    size: (u16, u16),
+) -> Result<u16>
+where
+    W: io::Write,
+{
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print('/'),
+        style::Print(&prompt.input),
+    )?;
+    match &s.search {
+        Some(Err(err)) => {
+            set_style(
+                &mut out,
+                Style::fg(Color::Red).or_mono(s.config.color, Some(Attribute::Bold)),
+            )?;
+            queue!(out, style::Print(format!("  ! {}", regex_error(err))))?;
+        }
+        _ => {
+            set_style(&mut out, hint(s))?;
+            queue!(
+                out,
+                style::Print(format!(
+                    "  ({})",
+                    s.config.search_case.describe(&prompt.input)
+                )),
+            )?;
+        }
+    }
+    queue!(
+        out,
+        style::ResetColor,
+        terminal::Clear(ClearType::UntilNewLine),
+    )?;
+    Ok(1 + s.str_width(&prompt.input) as u16)
+}
+
+// Draws the replacement being typed, or the question about the match under the cursor. Returns
+// the column the cursor should be in while typing.
+fn draw_replace<W>(mut out: W, s: &State, replace: &Replace, size: (u16, u16)) -> Result<u16>
+where
+    W: io::Write,
+{
+    let prefix = format!(
+        "replace /{}/{} with: ",
+        replace.re.as_str(),
+        if replace.selection {
+            " in selection"
+        } else {
+            ""
+        },
+    );
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print(&prefix),
+        style::Print(&replace.input),
+    )?;
+    if s.mode == Mode::Confirm {
+        set_style(&mut out, hint(s))?;
+        queue!(
+            out,
+            style::Print("  (y)es (n)o (a)ll (q)uit"),
+            style::ResetColor,
+        )?;
+    }
+    queue!(out, terminal::Clear(ClearType::UntilNewLine))?;
+    Ok((s.str_width(&prefix) + s.str_width(&replace.input)) as u16)
+}
+
+// Draws the pattern being typed to filter selections by. Returns the column the cursor should be
+// in.
+fn draw_filter<W>(mut out: W, s: &State, filter: &Filter, size: (u16, u16)) -> Result<u16>
+where
+    W: io::Write,
+{
+    let prefix = format!(
+        "{} selections matching: ",
+        if filter.keep { "keep" } else { "drop" }
+    );
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print(&prefix),
+        style::Print(&filter.input),
+        terminal::Clear(ClearType::UntilNewLine),
+    )?;
+    Ok((s.str_width(&prefix) + s.str_width(&filter.input)) as u16)
+}
+
+// Draws the path being typed. Returns the column the cursor should be in.
+fn draw_path_prompt<W>(mut out: W, s: &State, prompt: &PathPrompt, size: (u16, u16)) -> Result<u16>
+where
+    W: io::Write,
+{
+    let prefix = format!("{}: ", prompt.label());
+    queue!(
+        out,
+        cursor::MoveTo(0, size.1 - 1),
+        style::Print(&prefix),
+        style::Print(&prompt.input),
+        terminal::Clear(ClearType::UntilNewLine),
+    )?;
+    Ok((s.str_width(&prefix) + s.str_width(&prompt.input)) as u16)
+}
+
 fn draw_search<W>(mut out: W, s: &State, size: (u16, u16)) -> Result<()>
 where
     W: io::Write,
@@ -87,12 +602,18 @@ where
     match &s.search {
         Some(Ok(re)) => {
             queue!(out, style::Print('/'), style::Print(re))?;
-        }
-        Some(Err(regex::Error::Syntax(msg))) => {
-            queue!(out, style::Print("! "), style::Print(msg))?;
+            set_style(&mut out, hint(s))?;
+            queue!(
+                out,
+                style::Print(format!(
+                    "  ({})",
+                    s.config.search_case.describe(re.as_str())
+                )),
+                style::ResetColor,
+            )?;
         }
         Some(Err(err)) => {
-            bail!("Unhandled error parsing regex for search: {}", err);
+            queue!(out, style::Print("! "), style::Print(regex_error(err)))?;
         }
         None => (),
     }
@@ -100,19 +621,270 @@ where
     Ok(())
 }
 
-pub fn draw<W>(mut out: W, s: &State, size: (u16, u16)) -> Result<()>
+// draws the dialog in a box in the middle of the text, returning where the cursor should go
+fn draw_dialog<W>(mut out: W, s: &State, dialog: &Dialog, size: (u16, u16)) -> Result<(u16, u16)>
 where
     W: io::Write,
 {
-    queue!(out, cursor::Hide)?;
-    let offset = draw_text(&mut out, s, size)?;
-    draw_status(&mut out, s, size)?;
-    draw_search(&mut out, s, size)?;
+    let options = match dialog.other {
+        Some((key, name, _)) => format!("[y]es [n]o [{}]{} [esc] cancel", key, &name[1..]),
+        None => "[y]es [n]o [esc] cancel".to_string(),
+    };
+    let inner = usize::max(s.str_width(&dialog.question), options.len()) + 2;
+    let x = (size.0 as usize).saturating_sub(inner + 2) as u16 / 2;
+    let y = (size.1 - 2).saturating_sub(4) / 2;
+    let border = format!("+{}+", "-".repeat(inner));
+    set_style(&mut out, bar(s))?;
+    for (i, line) in [
+        border.clone(),
+        format!(
+            "| {}{} |",
+            dialog.question,
+            " ".repeat(inner - 2 - s.str_width(&dialog.question))
+        ),
+        format!("| {:<1$} |", options, inner - 2),
+        border,
+    ]
+    .iter()
+    .enumerate()
+    {
+        queue!(out, cursor::MoveTo(x, y + i as u16), style::Print(line))?;
+    }
+    queue!(out, style::ResetColor)?;
+    Ok((x + 2 + s.str_width(&dialog.question) as u16, y + 1))
+}
+
+// draws the pager over the whole screen, with its own status line
+fn draw_pager<W>(mut out: W, s: &State, pager: &Pager, size: (u16, u16)) -> Result<()>
+where
+    W: io::Write,
+{
+    let height = size.1 as usize - 2;
+    let selected = pager.targets.get(pager.selected).map(|target| target.line);
+    for y in 0..height {
+        queue!(out, cursor::MoveTo(0, y as u16))?;
+        let i = pager.top + y;
+        // the chosen line is highlighted all the way across, and the match within each result
+        let base = if selected == Some(i) {
+            Style::bg(Color::DarkGrey).or_mono(s.config.color, Some(Attribute::Reverse))
+        } else {
+            Style::default()
+        };
+        let span = match pager.targets.binary_search_by_key(&i, |target| target.line) {
+            Ok(t) => pager.targets[t].span.clone(),
+            Err(_) => 0..0,
+        };
+        let mut current = Style::default();
+        let line = pager.lines.get(i).map_or("", String::as_str);
+        // with None for the rest of the row after the line
+        let chars = line.char_indices().take(size.0 as usize).map(Some);
+        for item in chars.chain([None]) {
+            let style = match item {
+                Some((x, _)) if span.contains(&x) => {
+                    base.merge(Layer::Search.style(s.config.color))
+                }
+                _ => base,
+            };
+            if style != current {
+                set_style(&mut out, style)?;
+                current = style;
+            }
+            if let Some((_, c)) = item {
+                queue!(out, style::Print(c))?;
+            }
+        }
+        queue!(
+            out,
+            terminal::Clear(ClearType::UntilNewLine),
+            style::ResetColor
+        )?;
+    }
+    queue!(out, cursor::MoveTo(0, size.1 - 2))?;
+    set_style(&mut out, bar(s))?;
     queue!(
         out,
-        cursor::MoveTo(s.cursor_width() as u16, (s.cursor.y - offset) as u16),
-        cursor::Show,
+        style::Print(format!(
+            "{:6} {:<3$} {:>8}",
+            Mode::Pager,
+            pager.title,
+            format!(
+                "{}/{}",
+                usize::min(pager.top + height, pager.lines.len()),
+                pager.lines.len(),
+            ),
+            size.0 as usize - 16,
+        )),
+        style::ResetColor,
     )?;
+    if pager.targets.is_empty() {
+        draw_message(&mut out, "j/k to scroll, q to close", size)
+    } else if pager.dir.is_some() {
+        draw_message(
+            &mut out,
+            "j/k to choose, enter to open, n/r/d to create/rename/delete, q to close",
+            size,
+        )
+    } else {
+        draw_message(&mut out, "j/k to choose, enter to open, q to close", size)
+    }
+}
+
+// Draws window s as it is now, in the part of the screen at origin, returning where the cursor
+// is in it.
+fn draw_window<W>(mut out: W, s: &State, origin: (u16, u16), focused: bool) -> Result<(u16, u16)>
+where
+    W: io::Write,
+{
+    let size = s.size;
+    let cursor = if s.zen {
+        draw_zen(&mut out, s, origin, size)?;
+        s.zen_cursor()
+    } else {
+        let offset = draw_text(&mut out, s, origin, size)?;
+        if s.minimap_width() > 0 {
+            draw_minimap(&mut out, s, origin, size)?;
+        }
+        (
+            s.gutter_width() + (s.cursor_width() + s.virtual_columns()) as u16,
+            (s.cursor.y - offset) as u16,
+        )
+    };
+    draw_status(&mut out, s, origin, size, focused)?;
+    Ok((origin.0 + cursor.0, origin.1 + cursor.1))
+}
+
+// Draws the tooltip just below the pointer, or just above it if there isn't room below, and
+// shifted left to fit. Lines too long for the screen are cut short.
+fn draw_tooltip<W>(mut out: W, s: &State, tooltip: &Tooltip, size: (u16, u16)) -> Result<()>
+where
+    W: io::Write,
+{
+    let width = tooltip
+        .lines
+        .iter()
+        .map(|line| s.str_width(line) + 2)
+        .max()
+        .unwrap_or(0)
+        .min(size.0 as usize);
+    let height = tooltip.lines.len().min(size.1 as usize - 1) as u16;
+    let y = if tooltip.row + 1 + height < size.1 {
+        tooltip.row + 1
+    } else {
+        tooltip.row.saturating_sub(height)
+    };
+    let x = tooltip.column.min(size.0 - width as u16);
+    queue!(out, style::SetBackgroundColor(Color::Grey))?;
+    for (i, line) in tooltip.lines.iter().take(height as usize).enumerate() {
+        let mut text = format!(" {}", line);
+        while s.str_width(&text) > width - 1 {
+            text.pop();
+        }
+        let pad = width - s.str_width(&text);
+        queue!(
+            out,
+            cursor::MoveTo(x, y + i as u16),
+            style::Print(text),
+            style::Print(" ".repeat(pad)),
+        )?;
+    }
+    queue!(out, style::ResetColor)?;
+    Ok(())
+}
+
+// Draws every window, each in its own part of the screen, and the message line below them.
+pub fn draw<W>(mut out: W, s: &mut State) -> Result<()>
+where
+    W: io::Write,
+{
+    let size = s.screen;
+    queue!(out, cursor::Hide)?;
+    let rects = s.window_rects();
+    if size.0 < MIN_SIZE.0
+        || size.1 < MIN_SIZE.1
+        || rects
+            .iter()
+            .any(|(_, rect)| rect.w < window::MIN_SIZE.0 || rect.h < window::MIN_SIZE.1)
+    {
+        draw_too_small(&mut out, size)?;
+        out.flush()?;
+        return Ok(());
+    }
+    if let Some(pager) = &s.pager {
+        draw_pager(&mut out, s, pager, size)?;
+        out.flush()?;
+        return Ok(());
+    }
+    let mut cursor = (0, 0);
+    for (i, rect) in rects {
+        let focused = i == s.window;
+        let at = s.with_window(i, |s| draw_window(&mut out, s, (rect.x, rect.y), focused))?;
+        if focused {
+            cursor = at;
+        }
+    }
+    for divider in s.dividers() {
+        queue!(out, style::SetForegroundColor(Color::DarkGrey))?;
+        for y in divider.y..divider.y + divider.h {
+            queue!(out, cursor::MoveTo(divider.x, y), style::Print('│'))?;
+        }
+        queue!(out, style::ResetColor)?;
+    }
+    let s = &*s;
+    if let Some(tooltip) = &s.tooltip {
+        draw_tooltip(&mut out, s, tooltip, size)?;
+    }
+    if let Some(dialog) = &s.dialog {
+        let (x, y) = draw_dialog(&mut out, s, dialog, size)?;
+        draw_search(&mut out, s, size)?;
+        queue!(out, cursor::MoveTo(x, y), cursor::Show)?;
+    } else if s.mode == Mode::Command {
+        let col = draw_prompt(&mut out, s, size)?;
+        queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+    } else if let Some(prompt) = &s.search_prompt {
+        let col = draw_search_prompt(&mut out, s, prompt, size)?;
+        queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+    } else if let Some(filter) = &s.filter {
+        let col = draw_filter(&mut out, s, filter, size)?;
+        queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+    } else if let Some(prompt) = &s.path_prompt {
+        let col = draw_path_prompt(&mut out, s, prompt, size)?;
+        queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+    } else if let Some(prompt) = &s.session_prompt {
+        let col = draw_session_prompt(&mut out, s, prompt, size)?;
+        queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+    } else if let Some(input) = &s.status_prompt {
+        let col = draw_status_prompt(&mut out, s, input, size)?;
+        queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+    } else if let Some(replace) = &s.replace {
+        let col = draw_replace(&mut out, s, replace, size)?;
+        if s.mode == Mode::Replace {
+            queue!(out, cursor::MoveTo(col, size.1 - 1), cursor::Show)?;
+        } else {
+            queue!(out, cursor::MoveTo(cursor.0, cursor.1), cursor::Show)?;
+        }
+    } else {
+        match (&s.message, s.idle.running()) {
+            (Some(msg), _) => draw_message(&mut out, msg, size)?,
+            // without the spinner or percentage, which would be read out as they change
+            (None, Some(progress)) if s.config.accessible => draw_message(
+                &mut out,
+                &format!("{}... (esc to cancel)", progress.label),
+                size,
+            )?,
+            (None, Some(progress)) => draw_message(
+                &mut out,
+                &format!(
+                    "{} {} {}% (esc to cancel)",
+                    ['|', '/', '-', '\\'][progress.done / idle::CHUNK % 4],
+                    progress.label,
+                    100 * progress.done / progress.total,
+                ),
+                size,
+            )?,
+            (None, None) => draw_search(&mut out, s, size)?,
+        }
+        queue!(out, cursor::MoveTo(cursor.0, cursor.1), cursor::Show)?;
+    }
     out.flush()?;
     Ok(())
 }
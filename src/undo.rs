@@ -0,0 +1,135 @@
+use crate::state::{Cursor, Mode, Point, State};
+
+// The text from start was old and is now new.
+struct Change {
+    start: Point,
+    old: String,
+    new: String,
+}
+
+// Everything one key did, or a whole insert session, with the cursor and selection either side
+// of it so that undo and redo put you back where you were.
+struct Group {
+    changes: Vec<Change>,
+    before: (Cursor, Option<Cursor>),
+    after: (Cursor, Option<Cursor>),
+}
+
+#[derive(Default)]
+pub struct History {
+    done: Vec<Group>,
+    undone: Vec<Group>,
+    current: Option<Group>,
+    // where the cursor and selection were when the current key was pressed
+    before: Option<(Cursor, Option<Cursor>)>,
+    // whether we were in insert mode when the current key was pressed
+    inserting: bool,
+    // so that undo and redo don't record their own changes
+    restoring: bool,
+}
+
+// where text ends when it's put at start
+fn end_of(start: Point, text: &str) -> Point {
+    match text.rsplit_once('\n') {
+        Some((head, tail)) => Point {
+            x: tail.len(),
+            y: start.y + head.matches('\n').count() + 1,
+        },
+        None => Point {
+            x: start.x + text.len(),
+            y: start.y,
+        },
+    }
+}
+
+impl State {
+    // called at the start of every key, before anything changes
+    pub fn checkpoint(&mut self) {
+        if self.history.current.is_none() {
+            self.history.before = Some((self.cursor, self.anchor));
+        }
+        self.history.inserting = self.mode == Mode::Insert;
+    }
+
+    // called by splice, before it replaces the text between start and end with new
+    pub fn record_change(&mut self, start: Point, end: Point, new: &str) {
+        if self.history.restoring {
+            return;
+        }
+        let old = self.text_between(start, end);
+        let before = self.history.before.unwrap_or((self.cursor, self.anchor));
+        let group = self.history.current.get_or_insert(Group {
+            changes: Vec::new(),
+            before,
+            after: before,
+        });
+        group.changes.push(Change {
+            start,
+            old,
+            new: new.to_string(),
+        });
+    }
+
+    // Called at the end of every key, finishing the group unless we're still inserting. A key
+    // that starts inserting, like changing the selection, finishes its own group too, so that
+    // what's typed next is undone first, on its own.
+    pub fn commit(&mut self) {
+        if self.mode == Mode::Insert && self.history.inserting {
+            return;
+        }
+        self.break_undo();
+    }
+
+    // Finishes the group so far, so that what follows is undone separately. For commands that
+    // make a lot of changes at once, which shouldn't be tangled up with whatever came before.
+    pub fn break_undo(&mut self) {
+        if let Some(mut group) = self.history.current.take() {
+            group.after = (self.cursor, self.anchor);
+            self.history.done.push(group);
+            self.history.undone.clear();
+        }
+        self.history.before = Some((self.cursor, self.anchor));
+    }
+
+    fn restore(&mut self, (cursor, anchor): (Cursor, Option<Cursor>)) {
+        self.cursor = cursor;
+        self.anchor = anchor;
+        self.fixup();
+    }
+
+    pub fn undo(&mut self) {
+        let group = match self.history.done.pop() {
+            Some(group) => group,
+            None => {
+                self.message = Some("Nothing to undo".to_string());
+                return;
+            }
+        };
+        self.history.restoring = true;
+        for change in group.changes.iter().rev() {
+            let end = end_of(change.start, &change.new);
+            self.splice(change.start, end, &change.old);
+        }
+        self.history.restoring = false;
+        self.restore(group.before);
+        self.history.undone.push(group);
+    }
+
+    pub fn redo(&mut self) {
+        let group = match self.history.undone.pop() {
+            Some(group) => group,
+            None => {
+                self.message = Some("Nothing to redo".to_string());
+                return;
+            }
+        };
+        self.history.restoring = true;
+        for change in &group.changes {
+            let end = end_of(change.start, &change.old);
+            self.splice(change.start, end, &change.new);
+        }
+        self.history.restoring = false;
+        self.restore(group.after);
+        self.history.done.push(group);
+    }
+}
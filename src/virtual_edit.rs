@@ -0,0 +1,47 @@
+use crate::state::State;
+
+// With virtual_edit on, the cursor can go past the end of its line, into columns that don't
+// exist yet. The cursor stays at the end of the line, and its target column says how far past
+// it is, the same as when moving down onto a shorter line. Nothing changes until an edit there,
+// which first pads the line out to the cursor with spaces.
+impl State {
+    pub fn toggle_virtual_edit(&mut self) {
+        self.config.virtual_edit = !self.config.virtual_edit;
+        if !self.config.virtual_edit {
+            self.cursor.w = self.cursor_width();
+        }
+        self.message = Some(format!(
+            "Virtual edit {}",
+            if self.config.virtual_edit {
+                "on"
+            } else {
+                "off"
+            }
+        ));
+    }
+
+    // how many columns past the end of its line the cursor is
+    pub fn virtual_columns(&self) -> usize {
+        if !self.config.virtual_edit || self.cursor.x < self.text[self.cursor.y].0.len() {
+            return 0;
+        }
+        self.cursor.w.saturating_sub(self.cursor_width())
+    }
+
+    // fills the line out to the cursor, for an edit there
+    pub fn pad_virtual(&mut self) {
+        let n = self.virtual_columns();
+        if n > 0 {
+            let cursor = self.cursor.into();
+            let end = self.splice(cursor, cursor, &" ".repeat(n));
+            self.move_cursor(end);
+        }
+    }
+
+    // brings the cursor back to the end of its line, for edits that don't need the padding
+    pub fn clear_virtual(&mut self) {
+        if self.virtual_columns() > 0 {
+            self.cursor.w = self.cursor_width();
+        }
+    }
+}
@@ -0,0 +1,706 @@
+use {
+    crate::{
+        mark::MarkId,
+        state::{Cursor, Point, State},
+    },
+    regex::Regex,
+};
+
+// Windows show buffers side by side or one above the other, in a layout that splits the screen
+// (less the message line at the bottom) in two, and each half again, and so on. Several can show
+// the same buffer, each from its own place and with its own search. The focused window's cursor,
+// anchor, and search are the buffer's own, like when there's only one window, and the others
+// park theirs until they're focused again.
+
+// smaller than this, a window can't fit its status line, so splitting stops short of it
+pub const MIN_SIZE: (u16, u16) = (20, 2);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    // including the window's status line
+    pub h: u16,
+}
+
+impl Rect {
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        (self.x..self.x + self.w).contains(&column) && (self.y..self.y + self.h).contains(&row)
+    }
+}
+
+pub enum Layout {
+    // by index into State::windows
+    Window(usize),
+    // vertical is side by side, with a column between for the divider, and first takes ratio of
+    // the room
+    Split {
+        vertical: bool,
+        ratio: f64,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    // the room along the split that first gets out of len, leaving some for second
+    fn first_len(ratio: f64, len: u16, min: u16) -> u16 {
+        let first = (len as f64 * ratio).round() as u16;
+        first.clamp(min.min(len / 2), len.saturating_sub(min).max(len / 2))
+    }
+
+    // The parts of area that first and second get, and the divider between, if there is one.
+    fn halves(vertical: bool, ratio: f64, area: Rect) -> (Rect, Rect, Option<Rect>) {
+        if vertical {
+            let room = area.w.saturating_sub(1);
+            let w = Layout::first_len(ratio, room, MIN_SIZE.0);
+            (
+                Rect { w, ..area },
+                Rect {
+                    x: area.x + w + 1,
+                    w: room - w,
+                    ..area
+                },
+                Some(Rect {
+                    x: area.x + w,
+                    w: 1,
+                    ..area
+                }),
+            )
+        } else {
+            let h = Layout::first_len(ratio, area.h, MIN_SIZE.1);
+            (
+                Rect { h, ..area },
+                Rect {
+                    y: area.y + h,
+                    h: area.h - h,
+                    ..area
+                },
+                None,
+            )
+        }
+    }
+
+    // every window in area, in order, with the part of it each gets
+    pub fn rects(&self, area: Rect, rects: &mut Vec<(usize, Rect)>) {
+        match self {
+            Layout::Window(i) => rects.push((*i, area)),
+            Layout::Split {
+                vertical,
+                ratio,
+                first,
+                second,
+            } => {
+                let (a, b, _) = Layout::halves(*vertical, *ratio, area);
+                first.rects(a, rects);
+                second.rects(b, rects);
+            }
+        }
+    }
+
+    // the columns between windows side by side
+    pub fn dividers(&self, area: Rect, dividers: &mut Vec<Rect>) {
+        if let Layout::Split {
+            vertical,
+            ratio,
+            first,
+            second,
+        } = self
+        {
+            let (a, b, divider) = Layout::halves(*vertical, *ratio, area);
+            dividers.extend(divider);
+            first.dividers(a, dividers);
+            second.dividers(b, dividers);
+        }
+    }
+
+    // The split whose divider is at column and row, as the way down to it, first or second at
+    // each split on the way. Windows one above the other are divided by the status line of the
+    // one above.
+    fn divider_at(&self, area: Rect, column: u16, row: u16) -> Option<Vec<bool>> {
+        let (vertical, ratio, first, second) = match self {
+            Layout::Split {
+                vertical,
+                ratio,
+                first,
+                second,
+            } => (vertical, ratio, first, second),
+            Layout::Window(_) => return None,
+        };
+        let (a, b, divider) = Layout::halves(*vertical, *ratio, area);
+        let on_divider = match divider {
+            Some(divider) => divider.contains(column, row),
+            None => a.contains(column, row) && row == a.y + a.h - 1,
+        };
+        if on_divider {
+            return Some(Vec::new());
+        }
+        let (half, area, second) = if a.contains(column, row) {
+            (first, a, false)
+        } else {
+            (second, b, true)
+        };
+        let mut path = half.divider_at(area, column, row)?;
+        path.insert(0, second);
+        Some(path)
+    }
+
+    // Moves the divider of the split at path in area to column or row, as far as it'll go.
+    fn drag(&mut self, path: &[bool], area: Rect, column: u16, row: u16) {
+        if let Layout::Split {
+            vertical,
+            ratio,
+            first,
+            second,
+        } = self
+        {
+            let (a, b, _) = Layout::halves(*vertical, *ratio, area);
+            match path.split_first() {
+                Some((false, path)) => first.drag(path, a, column, row),
+                Some((true, path)) => second.drag(path, b, column, row),
+                None if *vertical => {
+                    let room = area.w.saturating_sub(1).max(1);
+                    *ratio = column.saturating_sub(area.x) as f64 / room as f64;
+                }
+                // the status line above the divide is the first's last row
+                None => {
+                    let h = (row + 1).saturating_sub(area.y);
+                    *ratio = h as f64 / area.h.max(1) as f64;
+                }
+            }
+            *ratio = ratio.clamp(0.0, 1.0);
+        }
+    }
+
+    // splits window i in two, with new as the second half
+    fn split(&mut self, i: usize, new: usize, vertical: bool) {
+        match self {
+            Layout::Window(j) if *j == i => {
+                *self = Layout::Split {
+                    vertical,
+                    ratio: 0.5,
+                    first: Box::new(Layout::Window(i)),
+                    second: Box::new(Layout::Window(new)),
+                }
+            }
+            Layout::Window(_) => (),
+            Layout::Split { first, second, .. } => {
+                first.split(i, new, vertical);
+                second.split(i, new, vertical);
+            }
+        }
+    }
+
+    // Gives window i's room to whatever it was split from, and counts the windows after it down
+    // one, as they'll be once it's removed from State::windows.
+    fn remove(&mut self, i: usize) {
+        match self {
+            Layout::Window(j) => {
+                if *j > i {
+                    *j -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                let is_i = |layout: &Layout| matches!(layout, Layout::Window(j) if *j == i);
+                if is_i(first) {
+                    *self = std::mem::replace(second, Layout::Window(0));
+                    self.remove(i);
+                } else if is_i(second) {
+                    *self = std::mem::replace(first, Layout::Window(0));
+                    self.remove(i);
+                } else {
+                    first.remove(i);
+                    second.remove(i);
+                }
+            }
+        }
+    }
+}
+
+pub struct Window {
+    // by index into the buffers in the order they were opened, see buffer.rs
+    pub buffer: usize,
+    // None while focused
+    parked: Option<Parked>,
+}
+
+impl Window {
+    pub fn new(buffer: usize) -> Self {
+        Window {
+            buffer,
+            parked: None,
+        }
+    }
+}
+
+// Where a window was in its buffer, as marks in the buffer so that edits from other windows
+// move it along, and what it was searching for.
+struct Parked {
+    cursor: MarkId,
+    anchor: Option<MarkId>,
+    search: Option<Result<Regex, regex::Error>>,
+}
+
+// The windows as written to a session, see session.rs: each split as | (side by side) or - and its
+// ratio, followed by its halves, and each window as the session's entry for the buffer it shows
+// and where it's parked as :y:x, or with * before it for the focused one. None if a window shows
+// a buffer the session leaves out.
+fn encode(
+    s: &State,
+    layout: &Layout,
+    entry: &dyn Fn(usize) -> Option<usize>,
+    tokens: &mut Vec<String>,
+) -> Option<()> {
+    match layout {
+        Layout::Window(i) => {
+            let window = &s.windows[*i];
+            let e = entry(window.buffer)?;
+            tokens.push(match &window.parked {
+                Some(parked) => {
+                    let point = s.buffers[window.buffer].marks.get(parked.cursor)?;
+                    format!("{}:{}:{}", e, point.y, point.x)
+                }
+                None => format!("*{}", e),
+            });
+        }
+        Layout::Split {
+            vertical,
+            ratio,
+            first,
+            second,
+        } => {
+            tokens.push(format!("{}{}", if *vertical { '|' } else { '-' }, ratio));
+            encode(s, first, entry, tokens)?;
+            encode(s, second, entry, tokens)?;
+        }
+    }
+    Some(())
+}
+
+// the other way, with the entry and parked place of each window, by index, None where focused
+fn decode<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    windows: &mut Vec<(usize, Option<Point>)>,
+) -> Option<Layout> {
+    let token = tokens.next()?;
+    let vertical = match token.chars().next()? {
+        '|' => Some(true),
+        '-' => Some(false),
+        _ => None,
+    };
+    if let Some(vertical) = vertical {
+        let ratio = token[1..].parse().ok()?;
+        let first = decode(tokens, windows)?;
+        let second = decode(tokens, windows)?;
+        return Some(Layout::Split {
+            vertical,
+            ratio,
+            first: Box::new(first),
+            second: Box::new(second),
+        });
+    }
+    let window = match token.strip_prefix('*') {
+        Some(e) => (e.parse().ok()?, None),
+        None => {
+            let mut fields = token.split(':');
+            let e = fields.next()?.parse().ok()?;
+            let y = fields.next()?.parse().ok()?;
+            let x = fields.next()?.parse().ok()?;
+            (e, Some(Point { x, y }))
+        }
+    };
+    windows.push(window);
+    Some(Layout::Window(windows.len() - 1))
+}
+
+// what a search highlights, to tell whether two windows can share the matches on their lines
+fn pattern(search: &Option<Result<Regex, regex::Error>>) -> Option<&str> {
+    search.as_ref()?.as_ref().ok().map(Regex::as_str)
+}
+
+impl State {
+    // the screen, less the message line at the bottom
+    fn layout_area(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            w: self.screen.0,
+            h: self.screen.1.saturating_sub(1),
+        }
+    }
+
+    pub fn window_rects(&self) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.layout.rects(self.layout_area(), &mut rects);
+        rects
+    }
+
+    pub fn dividers(&self) -> Vec<Rect> {
+        let mut dividers = Vec::new();
+        self.layout.dividers(self.layout_area(), &mut dividers);
+        dividers
+    }
+
+    fn window_rect(&self, i: usize) -> Rect {
+        self.window_rects()
+            .into_iter()
+            .find_map(|(j, rect)| (j == i).then_some(rect))
+            .unwrap_or_else(|| self.layout_area())
+    }
+
+    // Window i's part of the screen, given as a screen of its own, so that everything that goes
+    // by size, like height, goes by the window. Its status line is where a screen's would be,
+    // and the message line would be just below it.
+    fn window_size(&self, i: usize) -> (u16, u16) {
+        let rect = self.window_rect(i);
+        (rect.w, rect.h + 1)
+    }
+
+    // for when the terminal changes size
+    pub fn resize(&mut self, screen: (u16, u16)) {
+        self.screen = screen;
+        self.size = self.window_size(self.window);
+    }
+
+    // Focuses the window at column and row, if there is one, returning where they are in it.
+    pub fn click_window(&mut self, column: u16, row: u16) -> Option<(u16, u16)> {
+        let (i, rect) = self
+            .window_rects()
+            .into_iter()
+            .find(|(_, rect)| rect.contains(column, row))?;
+        self.focus_window(i);
+        Some((column - rect.x, row - rect.y))
+    }
+
+    // Starts dragging the divider at column and row, if there is one there. Returns whether
+    // there was.
+    pub fn grab_divider(&mut self, column: u16, row: u16) -> bool {
+        self.dragging = self.layout.divider_at(self.layout_area(), column, row);
+        self.dragging.is_some()
+    }
+
+    // resizes the windows either side of the divider being dragged, as it's dragged
+    pub fn drag_divider(&mut self, column: u16, row: u16) {
+        if let Some(path) = self.dragging.take() {
+            let area = self.layout_area();
+            self.layout.drag(&path, area, column, row);
+            self.dragging = Some(path);
+            self.size = self.window_size(self.window);
+        }
+    }
+
+    pub fn drop_divider(&mut self) {
+        self.dragging = None;
+    }
+
+    pub fn split_window(&mut self, vertical: bool) {
+        let rect = self.window_rect(self.window);
+        let fits = if vertical {
+            rect.w > 2 * MIN_SIZE.0
+        } else {
+            rect.h >= 2 * MIN_SIZE.1
+        };
+        if !fits {
+            self.message = Some("Not enough room to split".to_string());
+            return;
+        }
+        let new = self.windows.len();
+        self.windows.push(Window::new(self.active));
+        self.layout.split(self.window, new, vertical);
+        self.windows[new].parked = Some(self.park());
+        self.focus_window(new);
+    }
+
+    pub fn next_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.message = Some("No other windows".to_string());
+            return;
+        }
+        let rects = self.window_rects();
+        let i = rects
+            .iter()
+            .position(|&(i, _)| i == self.window)
+            .unwrap_or(0);
+        self.focus_window(rects[(i + 1) % rects.len()].0);
+    }
+
+    pub fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.message = Some("Can't close the only window".to_string());
+            return;
+        }
+        let closing = self.window;
+        self.next_window();
+        self.remove_window(closing);
+    }
+
+    pub fn only_window(&mut self) {
+        for i in (0..self.windows.len()).rev() {
+            if i != self.window {
+                self.remove_window(i);
+            }
+        }
+    }
+
+    // the windows for a session, given each buffer's entry in it, see encode
+    pub fn encode_layout(&self, entry: impl Fn(usize) -> Option<usize>) -> Option<String> {
+        let mut tokens = Vec::new();
+        encode(self, &self.layout, &entry, &mut tokens)?;
+        Some(tokens.join(" "))
+    }
+
+    // Arranges the windows as encoded, given the buffer each of the session's entries was
+    // opened in, if it could be. Nothing changes if it doesn't parse, or if any of the windows'
+    // buffers are missing.
+    pub fn restore_layout(&mut self, encoded: &str, buffers: &[Option<usize>]) {
+        let mut parsed = Vec::new();
+        let layout = match decode(&mut encoded.split(' '), &mut parsed) {
+            Some(layout) => layout,
+            None => return,
+        };
+        let shown: Option<Vec<usize>> = parsed
+            .iter()
+            .map(|&(e, _)| buffers.get(e).copied().flatten())
+            .collect();
+        let focused: Vec<usize> = (0..parsed.len())
+            .filter(|&i| parsed[i].1.is_none())
+            .collect();
+        let (shown, focused) = match (shown, focused.as_slice()) {
+            (Some(shown), &[focused]) => (shown, focused),
+            _ => return,
+        };
+        self.only_window();
+        let active = self.active;
+        let mut windows = Vec::new();
+        for (&buffer, &(_, point)) in shown.iter().zip(&parsed) {
+            let mut window = Window::new(buffer);
+            if let Some(point) = point {
+                self.active = buffer;
+                let point = self.clamp(point);
+                window.parked = Some(Parked {
+                    cursor: self.marks.add(point),
+                    anchor: None,
+                    search: None,
+                });
+            }
+            windows.push(window);
+        }
+        self.active = active;
+        self.windows = windows;
+        self.layout = layout;
+        self.window = focused;
+        self.size = self.window_size(focused);
+        self.switch_buffer(shown[focused]);
+    }
+
+    fn remove_window(&mut self, i: usize) {
+        let window = self.windows.remove(i);
+        if let Some(parked) = window.parked {
+            let marks = &mut self.buffers[window.buffer].marks;
+            marks.remove(parked.cursor);
+            if let Some(anchor) = parked.anchor {
+                marks.remove(anchor);
+            }
+        }
+        self.layout.remove(i);
+        if self.window > i {
+            self.window -= 1;
+        }
+        self.size = self.window_size(self.window);
+    }
+
+    // Moves the focus to window i, parking the one that had it where it is.
+    pub fn focus_window(&mut self, i: usize) {
+        if i == self.window {
+            return;
+        }
+        let parked = self.park();
+        self.windows[self.window].parked = Some(parked);
+        self.window = i;
+        self.size = self.window_size(i);
+        if self.windows[i].buffer != self.active {
+            self.switch_buffer(self.windows[i].buffer);
+        }
+        if let Some(parked) = self.windows[i].parked.take() {
+            self.unpark(parked);
+        }
+    }
+
+    fn park(&mut self) -> Parked {
+        let (cursor, anchor) = (self.cursor, self.anchor);
+        let cursor = self.marks.add(cursor.into());
+        let anchor = anchor.map(|anchor| self.marks.add(anchor.into()));
+        Parked {
+            cursor,
+            anchor,
+            search: self.search.clone(),
+        }
+    }
+
+    fn unpark(&mut self, parked: Parked) {
+        let point = |s: &mut State, id| {
+            let point = s.parked_cursor(id);
+            s.marks.remove(id);
+            point
+        };
+        let cursor = point(self, parked.cursor);
+        self.anchor = parked.anchor.map(|id| point(self, id));
+        self.move_cursor(cursor.into());
+        let own = pattern(&parked.search) != pattern(&self.search);
+        self.search = parked.search;
+        if own {
+            self.reannotate();
+        }
+    }
+
+    // Where the mark id has moved to since it was parked. Marks are kept in the text by splice,
+    // and clamped after anything that replaces lines wholesale, but only up to the next key.
+    fn parked_cursor(&self, id: MarkId) -> Cursor {
+        let Point { x, y } = self.clamp(self.marks.get(id).unwrap_or(Point { x: 0, y: 0 }));
+        let w = self.str_width(&self.text[y].0[..x]);
+        Cursor { y, x, w }
+    }
+
+    // Runs f as if window i was focused, for drawing it, with the buffer it shows. Its lines are
+    // annotated with the matches for its own search for as long as that takes, and then given
+    // back the buffer's.
+    pub fn with_window<T>(&mut self, i: usize, f: impl FnOnce(&mut State) -> T) -> T {
+        let parked = match &self.windows[i].parked {
+            Some(parked) => (parked.cursor, parked.anchor, parked.search.clone()),
+            None => return f(self),
+        };
+        let (active, size) = (self.active, self.size);
+        self.active = self.windows[i].buffer;
+        self.size = self.window_size(i);
+        let (cursor, anchor) = (
+            self.parked_cursor(parked.0),
+            parked.1.map(|id| self.parked_cursor(id)),
+        );
+        let cursor = std::mem::replace(&mut self.cursor, cursor);
+        let anchor = std::mem::replace(&mut self.anchor, anchor);
+        let own = pattern(&parked.2) != pattern(&self.search);
+        let search = std::mem::replace(&mut self.search, parked.2);
+        let visible = self.visible();
+        if own {
+            self.annotate_lines(visible.clone());
+        }
+        let result = f(self);
+        self.search = search;
+        if own {
+            self.annotate_lines(visible);
+        }
+        self.cursor = cursor;
+        self.anchor = anchor;
+        self.size = size;
+        self.active = active;
+        result
+    }
+
+    // Keeps windows showing the buffers they were, with the one at i gone. Those that were
+    // showing it are closed, unless it's the focused one, which is left to show another.
+    pub fn remove_buffer_windows(&mut self, i: usize) {
+        for j in (0..self.windows.len()).rev() {
+            if self.windows[j].buffer == i && j != self.window {
+                // its marks went with the buffer
+                self.windows[j].parked = None;
+                self.remove_window(j);
+            }
+        }
+        for window in &mut self.windows {
+            if window.buffer > i {
+                window.buffer -= 1;
+            }
+        }
+    }
+
+    // and with a buffer inserted at i
+    pub fn insert_buffer_windows(&mut self, i: usize) {
+        for window in &mut self.windows {
+            if window.buffer >= i {
+                window.buffer += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{config::Config, keymap::Keymap, ui},
+        std::io,
+    };
+
+    fn matched(s: &State, y: usize) -> bool {
+        !s.text[y].1.matches.is_empty()
+    }
+
+    #[test]
+    fn each_window_keeps_its_own_search() {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            b"foo\nbar\n",
+            Config::default(),
+            Keymap::default(),
+        );
+        s.resize((80, 24));
+        s.search = Some(Regex::new("foo"));
+        s.reannotate();
+        s.split_window(false);
+        assert_eq!((s.window, s.size), (1, (80, 12)));
+        s.search = Some(Regex::new("bar"));
+        s.reannotate();
+        s.move_cursor(Point { x: 0, y: 1 });
+        assert!(matched(&s, 1) && !matched(&s, 0));
+        // the other window is drawn with its own matches, which are put back afterwards
+        let other = s.with_window(0, |s| (matched(s, 0), matched(s, 1), s.cursor.y));
+        assert_eq!(other, (true, false, 0));
+        assert!(matched(&s, 1) && !matched(&s, 0));
+        ui::draw(io::sink(), &mut s).unwrap();
+        s.next_window();
+        assert_eq!((s.window, s.size), (0, (80, 13)));
+        assert!(matched(&s, 0) && !matched(&s, 1));
+        // edits made from one window move the other's cursor along with the text
+        let start = Point { x: 0, y: 0 };
+        s.splice(start, start, "new\n");
+        assert_eq!(s.with_window(1, |s| s.cursor.y), 2);
+        s.close_window();
+        assert_eq!((s.windows.len(), s.window, s.size), (1, 0, (80, 24)));
+        assert_eq!(s.cursor.y, 2);
+        assert!(matched(&s, 2) && !matched(&s, 1));
+    }
+
+    #[test]
+    fn drags_dividers() {
+        let mut s = State::from_bytes(
+            "test.txt".to_string(),
+            b"text\n",
+            Config::default(),
+            Keymap::default(),
+        );
+        s.resize((81, 24));
+        s.split_window(true);
+        s.split_window(false);
+        let widths =
+            |s: &State| -> Vec<u16> { s.window_rects().iter().map(|(_, r)| r.w).collect() };
+        assert_eq!(widths(&s), [40, 40, 40]);
+        // the column between the windows side by side
+        assert!(!s.grab_divider(39, 5));
+        assert!(s.grab_divider(40, 5));
+        s.drag_divider(50, 5);
+        assert_eq!(widths(&s), [50, 30, 30]);
+        // as far as there's room for the window on the right
+        s.drag_divider(80, 5);
+        assert_eq!(widths(&s), [60, 20, 20]);
+        s.drop_divider();
+        // and the status line of the window on top of another
+        let heights =
+            |s: &State| -> Vec<u16> { s.window_rects().iter().map(|(_, r)| r.h).collect() };
+        assert_eq!(heights(&s), [23, 12, 11]);
+        assert!(s.grab_divider(70, 11));
+        s.drag_divider(70, 5);
+        assert_eq!(heights(&s), [23, 6, 17]);
+        assert_eq!(s.size, (20, 18));
+    }
+}
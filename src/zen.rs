@@ -0,0 +1,140 @@
+use {
+    crate::state::{Point, State},
+    std::ops::Range,
+};
+
+// Zen mode draws the text in a centred column of config.zen_width, soft wrapped at word
+// boundaries, with no gutter and only the essentials in the status line. It's for prose, where
+// lines are paragraphs and don't fit on screen.
+impl State {
+    pub fn toggle_zen(&mut self) {
+        self.zen = !self.zen;
+        // the lines on screen are different now
+        self.reannotate();
+    }
+
+    // columns of text per row, leaving room for the cursor after the end of a row
+    pub fn zen_width(&self) -> usize {
+        usize::min(
+            self.config.zen_width,
+            (self.size.0 as usize).saturating_sub(1),
+        )
+        .max(1)
+    }
+
+    // the column the text starts at, so that it's centred
+    pub fn zen_margin(&self) -> u16 {
+        ((self.size.0 as usize).saturating_sub(self.zen_width()) / 2) as u16
+    }
+
+    // The byte indices each row of line y starts at, breaking after whitespace where possible.
+    // Whitespace is allowed to hang off the end of a row, so that rows don't start with it.
+    pub fn wrap(&self, y: usize) -> Vec<usize> {
+        let width = self.zen_width();
+        let mut rows = vec![0];
+        let mut w = 0;
+        // the last place the current row could break, and the width before it
+        let mut brk = None;
+        for (x, c, cw) in self.drawn_chars(y) {
+            if w + cw > width && !c.is_whitespace() {
+                match brk {
+                    Some((b, bw)) if w - bw + cw <= width => {
+                        rows.push(b);
+                        w -= bw;
+                    }
+                    _ => {
+                        rows.push(x);
+                        w = 0;
+                    }
+                }
+                brk = None;
+            }
+            w += cw;
+            if c.is_whitespace() {
+                brk = Some((x + c.len_utf8(), w));
+            }
+        }
+        rows
+    }
+
+    // which row of its line p is drawn on, and the column within that row
+    fn wrapped_position(&self, p: Point) -> (usize, usize) {
+        let rows = self.wrap(p.y);
+        let row = rows.iter().rposition(|&start| start <= p.x).unwrap_or(0);
+        (row, self.drawn_width(p.y, rows[row]..p.x))
+    }
+
+    // The first line to draw, and how many of its rows to skip, keeping the cursor's row
+    // centred. Unlike offset this doesn't stop at the end of the file, so the line being typed
+    // stays in one place.
+    pub fn zen_top(&self) -> (usize, usize) {
+        let half = self.height() / 2;
+        let (row, _) = self.wrapped_position(self.cursor.into());
+        if row >= half {
+            return (self.cursor.y, row - half);
+        }
+        let mut above = row;
+        for y in (0..self.cursor.y).rev() {
+            let n = self.wrap(y).len();
+            if above + n > half {
+                return (y, above + n - half);
+            }
+            above += n;
+        }
+        (0, 0)
+    }
+
+    // the rows on screen, top to bottom, as the line and the range of it on that row
+    pub fn zen_rows(&self) -> Vec<(usize, Range<usize>)> {
+        let (top, mut skip) = self.zen_top();
+        let mut rows = Vec::new();
+        for y in top..self.text.len() {
+            if rows.len() >= self.height() {
+                break;
+            }
+            let starts = self.wrap(y);
+            for (i, &start) in starts.iter().enumerate().skip(skip) {
+                let end = starts.get(i + 1).copied().unwrap_or(self.text[y].0.len());
+                rows.push((y, start..end));
+            }
+            skip = 0;
+        }
+        rows.truncate(self.height());
+        rows
+    }
+
+    // where the cursor is on screen
+    pub fn zen_cursor(&self) -> (u16, u16) {
+        let (row, col) = self.wrapped_position(self.cursor.into());
+        let (top, skip) = self.zen_top();
+        let above: usize = (top..self.cursor.y).map(|y| self.wrap(y).len()).sum();
+        (self.zen_margin() + col as u16, (above + row - skip) as u16)
+    }
+
+    // the point in the text drawn at the given screen position, if any
+    pub fn zen_point_at(&self, column: u16, row: u16) -> Option<Point> {
+        let (y, range) = self.zen_rows().into_iter().nth(row as usize)?;
+        let column = column.checked_sub(self.zen_margin())? as usize;
+        let line = &self.text[y].0;
+        let mut w = 0;
+        for (x, _, cw) in self.drawn_chars(y).filter(|(x, _, _)| range.contains(x)) {
+            w += cw;
+            if w > column {
+                return Some(Point { x, y });
+            }
+        }
+        if range.end == line.len() {
+            Some(Point { x: range.end, y })
+        } else {
+            // past the end of a wrapped row is the last character on it, not the next row
+            let x = line[range.clone()]
+                .char_indices()
+                .last()
+                .map_or(0, |(x, _)| x);
+            Some(Point {
+                x: range.start + x,
+                y,
+            })
+        }
+    }
+}